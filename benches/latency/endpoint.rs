@@ -25,6 +25,7 @@ impl TestContext {
 pub struct TestEndpoint {
     connection_info: ConnectionInfo,
     payload: &'static str,
+    idle: bool,
 }
 
 impl ConnectionInfoProvider for TestEndpoint {
@@ -52,6 +53,15 @@ impl TestEndpoint {
         Self {
             connection_info: ConnectionInfo::new("127.0.0.1", port),
             payload,
+            idle: false,
+        }
+    }
+
+    /// Endpoint that stays connected but never sends or reads anything.
+    pub fn idle(port: u16) -> Self {
+        Self {
+            idle: true,
+            ..Self::new(port, "")
         }
     }
 
@@ -60,6 +70,9 @@ impl TestEndpoint {
         ws: &mut <Self as EndpointWithContext<TestContext>>::Target,
         ctx: &mut TestContext,
     ) -> std::io::Result<()> {
+        if self.idle {
+            return Ok(());
+        }
         if ctx.wants_write {
             ws.send_text(true, Some(self.payload.as_bytes()))?;
             ctx.wants_write = false;
@@ -12,6 +12,8 @@ use boomnet::stream::ConnectionInfo;
 mod endpoint;
 mod server;
 
+const IDLE_ENDPOINTS: usize = 64;
+
 const MSG: &str = unsafe { std::str::from_utf8_unchecked(&[90u8; 256]) };
 
 fn boomnet_rtt_benchmark(c: &mut Criterion) {
@@ -83,6 +85,43 @@ fn boomnet_rtt_benchmark_io_service(c: &mut Criterion) {
     group.finish();
 }
 
+fn boomnet_rtt_benchmark_io_service_many_endpoints(c: &mut Criterion) {
+    let mut group = c.benchmark_group("boomnet");
+    group.throughput(Throughput::Bytes(MSG.len() as u64));
+
+    // run servers in the background
+    server::start_on_thread(9004);
+    server::start_idle_on_thread(9005);
+
+    // affinity
+    core_affinity::set_for_current(core_affinity::CoreId { id: 14 });
+
+    // setup io service with idle endpoints polled alongside the active one
+    let mut ctx = TestContext::new();
+    let mut io_service = DirectSelector::new().unwrap().into_io_service_with_context();
+    for _ in 0..IDLE_ENDPOINTS {
+        io_service.register(TestEndpoint::idle(9005)).unwrap();
+    }
+    io_service.register(TestEndpoint::new(9004, MSG)).unwrap();
+
+    group.bench_function("boomnet_rtt_io_service_many_endpoints", |b| {
+        b.iter(|| {
+            loop {
+                io_service
+                    .poll(&mut ctx, |ws, ctx, endpoint| endpoint.poll(ws, ctx))
+                    .unwrap();
+                if ctx.processed == 100 {
+                    ctx.wants_write = true;
+                    ctx.processed = 0;
+                    break;
+                }
+            }
+        })
+    });
+
+    group.finish();
+}
+
 fn tungstenite_rtt_benchmark(c: &mut Criterion) {
     let mut group = c.benchmark_group("tungstenite");
     group.throughput(Throughput::Bytes(MSG.len() as u64));
@@ -117,5 +156,11 @@ fn tungstenite_rtt_benchmark(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, boomnet_rtt_benchmark, boomnet_rtt_benchmark_io_service, tungstenite_rtt_benchmark);
+criterion_group!(
+    benches,
+    boomnet_rtt_benchmark,
+    boomnet_rtt_benchmark_io_service,
+    boomnet_rtt_benchmark_io_service_many_endpoints,
+    tungstenite_rtt_benchmark
+);
 criterion_main!(benches);
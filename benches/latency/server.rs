@@ -1,21 +1,42 @@
 use std::net::TcpListener;
 use std::time::Duration;
 
-use tungstenite::accept;
+use boomnet::ws::{Websocket, WebsocketFrame};
 
 pub fn start_on_thread(port: u16) {
     let server = TcpListener::bind(format!("127.0.0.1:{port}")).unwrap();
     std::thread::spawn(move || {
         if let Some(stream) = server.incoming().next() {
-            let mut client = accept(stream.unwrap()).unwrap();
+            let stream = stream.unwrap();
+            stream.set_nodelay(true).unwrap();
+            let mut client = Websocket::accept(stream);
+            let mut msgs: Vec<Vec<u8>> = Vec::new();
             loop {
-                let msg = client.read().unwrap();
-                for _ in 0..100 {
-                    client.write(msg.clone()).unwrap();
+                msgs.clear();
+                for frame in client.read_batch().unwrap() {
+                    if let WebsocketFrame::Text(true, data) = frame.unwrap() {
+                        msgs.push(data.to_vec());
+                    }
+                }
+                // echo each message on its own
+                for msg in &msgs {
+                    for _ in 0..100 {
+                        client.send_text(true, Some(msg)).unwrap();
+                    }
                 }
-                client.flush().unwrap();
             }
         }
     });
     std::thread::sleep(Duration::from_secs(1));
 }
+
+/// Accept connections and keep them open without ever responding.
+pub fn start_idle_on_thread(port: u16) {
+    let server = TcpListener::bind(format!("127.0.0.1:{port}")).unwrap();
+    std::thread::spawn(move || {
+        let mut clients = Vec::new();
+        for stream in server.incoming() {
+            clients.push(stream.unwrap());
+        }
+    });
+}
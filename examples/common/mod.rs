@@ -123,7 +123,7 @@ impl TlsWebsocketEndpoint for TradeEndpoint {
         Ok(Some(ws))
     }
 
-    fn can_recreate(&mut self, reason: DisconnectReason) -> bool {
+    fn can_recreate(&mut self, reason: &DisconnectReason) -> bool {
         warn!("connection disconnected: {reason}");
         true
     }
@@ -1,6 +1,6 @@
 use std::io;
 use std::net::SocketAddr;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 
 use boomnet::service::IntoIOServiceWithContext;
 use boomnet::service::endpoint::Context;
@@ -12,22 +12,21 @@ use boomnet::ws::{IntoTlsWebsocket, WebsocketFrame};
 use log::info;
 use url::Url;
 
-/// This example demonstrates how to implement explicit timer inside the endpoint. Since endpoint
-/// poll method is called on every cycle by the io service we can implement timer functionality
-/// directly inside the endpoint. In this case, the endpoint will keep disconnecting every 10s.
+/// This example demonstrates how to use timers provided by the io service. The timer is scheduled
+/// for the endpoint handle and the callback is invoked with the endpoint, its websocket and the
+/// context, so there is no need to check the time on every poll. In this case, the endpoint will
+/// keep disconnecting every 10s.
 struct TradeEndpoint {
     connection_info: ConnectionInfo,
     instrument: &'static str,
-    next_disconnect_time_ns: u64,
 }
 
 impl TradeEndpoint {
-    pub fn new(url: &'static str, instrument: &'static str, ctx: &FeedContext) -> TradeEndpoint {
+    pub fn new(url: &'static str, instrument: &'static str) -> TradeEndpoint {
         let connection_info = Url::parse(url).try_into().unwrap();
         Self {
             connection_info,
             instrument,
-            next_disconnect_time_ns: ctx.current_time_ns() + Duration::from_secs(10).as_nanos() as u64,
         }
     }
 
@@ -35,16 +34,11 @@ impl TradeEndpoint {
     fn poll(
         &mut self,
         ws: &mut TlsWebsocket<<Self as TlsWebsocketEndpointWithContext<FeedContext>>::Stream>,
-        ctx: &mut FeedContext,
+        _ctx: &mut FeedContext,
     ) -> io::Result<()> {
         while let Some(Ok(WebsocketFrame::Text(fin, data))) = ws.receive_next() {
             info!("({fin}) {}", String::from_utf8_lossy(data));
         }
-        let now_ns = ctx.current_time_ns();
-        if now_ns > self.next_disconnect_time_ns {
-            self.next_disconnect_time_ns = now_ns + Duration::from_secs(10).as_nanos() as u64;
-            return Err(io::Error::other("disconnected due to timer"));
-        }
         Ok(())
     }
 }
@@ -58,10 +52,6 @@ impl FeedContext {
     pub fn new() -> Self {
         Self
     }
-
-    pub fn current_time_ns(&self) -> u64 {
-        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64
-    }
 }
 
 impl ConnectionInfoProvider for TradeEndpoint {
@@ -101,9 +91,12 @@ fn main() -> anyhow::Result<()> {
 
     let mut io_service = MioSelector::new()?.into_io_service_with_context();
 
-    let endpoint_btc = TradeEndpoint::new("wss://stream1.binance.com:443/ws", "btcusdt", &ctx);
+    let endpoint_btc = TradeEndpoint::new("wss://stream1.binance.com:443/ws", "btcusdt");
 
-    io_service.register(endpoint_btc)?;
+    let handle = io_service.register(endpoint_btc)?;
+    io_service.schedule_periodic(handle, Duration::from_secs(10), |_ws, _ctx, _endpoint| {
+        Err(io::Error::other("disconnected due to timer"))
+    });
     loop {
         io_service.poll(&mut ctx, |ws, ctx, endpoint| endpoint.poll(ws, ctx))?;
     }
@@ -102,7 +102,7 @@ impl Endpoint for TradeConnectionFactory {
         Ok(Some(TradeConnection { ws }))
     }
 
-    fn can_recreate(&mut self, reason: DisconnectReason) -> bool {
+    fn can_recreate(&mut self, reason: &DisconnectReason) -> bool {
         println!("on disconnect: reason={}", reason);
         true
     }
@@ -88,6 +88,20 @@ impl<const CHUNK_SIZE: usize, const INITIAL_CAPACITY: usize> ReadBuffer<CHUNK_SI
         }
     }
 
+    /// Wrap `bytes` of which the first `len` have already been filled with data, for example by
+    /// the kernel. Unlike [`ReadBuffer::from_bytes`] the `bytes` can be smaller than `INITIAL_CAPACITY`
+    /// as the buffer grows on demand.
+    #[inline]
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    pub(crate) fn from_filled(bytes: Vec<u8>, len: usize) -> ReadBuffer<CHUNK_SIZE, INITIAL_CAPACITY> {
+        assert!(len <= bytes.len(), "filled len {len} exceeds the buffer size {}", bytes.len());
+        ReadBuffer {
+            inner: bytes,
+            head: 0,
+            tail: len,
+        }
+    }
+
     #[inline]
     pub fn into_vec(self) -> Vec<u8> {
         self.inner
@@ -117,8 +131,8 @@ impl<const CHUNK_SIZE: usize, const INITIAL_CAPACITY: usize> ReadBuffer<CHUNK_SI
     #[inline]
     fn read_from_with_mode<S: Read, M: ReadMode>(&mut self, stream: &mut S) -> io::Result<()> {
         #[cold]
-        fn grow(buf: &mut Vec<u8>) {
-            buf.resize(buf.len() * 2, 0u8);
+        fn grow(buf: &mut Vec<u8>, min_len: usize) {
+            buf.resize((buf.len() * 2).max(min_len), 0u8);
         }
 
         #[cold]
@@ -143,7 +157,7 @@ impl<const CHUNK_SIZE: usize, const INITIAL_CAPACITY: usize> ReadBuffer<CHUNK_SI
 
         // ensure capacity for at least one chunk
         if self.tail + CHUNK_SIZE > self.inner.capacity() {
-            grow(&mut self.inner);
+            grow(&mut self.inner, self.tail + CHUNK_SIZE);
         }
 
         let capacity = self.inner.capacity();
@@ -153,6 +167,30 @@ impl<const CHUNK_SIZE: usize, const INITIAL_CAPACITY: usize> ReadBuffer<CHUNK_SI
         Ok(())
     }
 
+    /// Copies `bytes` to the end of the buffer, growing it by a factor of 2 (or more if needed)
+    /// when there is not enough space left.
+    #[inline]
+    pub fn append(&mut self, bytes: &[u8]) {
+        #[cold]
+        fn grow(buf: &mut Vec<u8>, min_len: usize) {
+            buf.resize((buf.len() * 2).max(min_len), 0u8);
+        }
+
+        let tail = self.tail + bytes.len();
+        if tail > self.inner.len() {
+            grow(&mut self.inner, tail);
+        }
+        self.inner[self.tail..tail].copy_from_slice(bytes);
+        self.tail = tail;
+    }
+
+    /// Discard all bytes in the buffer.
+    #[inline]
+    pub const fn clear(&mut self) {
+        self.head = 0;
+        self.tail = 0;
+    }
+
     #[inline]
     pub const fn consume_next(&mut self, len: usize) -> Option<&'static [u8]> {
         match self.available() >= len {
@@ -181,6 +219,20 @@ impl<const CHUNK_SIZE: usize, const INITIAL_CAPACITY: usize> ReadBuffer<CHUNK_SI
         }
     }
 
+    /// Same as [`ReadBuffer::consume_next_unchecked`] but allows the consumed bytes to be modified
+    /// in place (for example to unmask the payload).
+    ///
+    /// # Safety
+    /// This function should only be called after `available` bytes are known.
+    #[inline]
+    pub const unsafe fn consume_next_mut_unchecked(&mut self, len: usize) -> &'static mut [u8] {
+        unsafe {
+            let consumed_view = &mut *ptr::slice_from_raw_parts_mut(self.inner.as_mut_ptr().add(self.head), len);
+            self.head += len;
+            consumed_view
+        }
+    }
+
     #[inline]
     pub const fn consume_next_byte(&mut self) -> Option<u8> {
         match self.available() >= 1 {
@@ -297,6 +349,25 @@ mod pool {
             }
         }
 
+        /// Acquire raw bytes of at least `len` from the pool (or allocate new ones). The bytes are
+        /// expected to be wrapped with [`BufferPoolRef::wrap`] so that they find their way back.
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        pub(crate) fn acquire_bytes(&self, len: usize) -> Vec<u8> {
+            self.inner.borrow_mut().acquire_bytes(len)
+        }
+
+        /// Wrap `buffer` in an RAII guard that returns it to this pool on [`Drop`].
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        pub(crate) fn wrap<const CHUNK_SIZE: usize, const INITIAL_CAPACITY: usize>(
+            &self,
+            buffer: ReadBuffer<CHUNK_SIZE, INITIAL_CAPACITY>,
+        ) -> OwnedReadBuffer<CHUNK_SIZE, INITIAL_CAPACITY> {
+            OwnedReadBuffer {
+                inner: buffer,
+                pool: self.clone(),
+            }
+        }
+
         /// Return a buffer to the pool.
         ///
         /// You typically don’t need to call this directly. Dropping
@@ -355,16 +426,19 @@ mod pool {
         /// Acquire a buffer with at least `INITIAL_CAPACITY` bytes.
         ///
         /// Performs a linear scan for the first stored buffer satisfying the
-        /// capacity requirement; otherwise allocates a new zeroed vector.
+        /// size requirement; otherwise allocates a new zeroed vector.
         pub fn acquire<const CHUNK_SIZE: usize, const INITIAL_CAPACITY: usize>(
             &mut self,
         ) -> ReadBuffer<CHUNK_SIZE, INITIAL_CAPACITY> {
-            let idx = self.buffers.iter().position(|b| b.capacity() >= INITIAL_CAPACITY);
-            let bytes = match idx {
+            ReadBuffer::from_bytes(self.acquire_bytes(INITIAL_CAPACITY))
+        }
+
+        /// Acquire raw bytes of at least `len`, performs the same linear scan as [`BufferPool::acquire`].
+        pub fn acquire_bytes(&mut self, len: usize) -> Vec<u8> {
+            match self.buffers.iter().position(|b| b.len() >= len) {
                 Some(i) => self.buffers.swap_remove(i),
-                None => vec![0u8; INITIAL_CAPACITY],
-            };
-            ReadBuffer::from_bytes(bytes)
+                None => vec![0u8; len],
+            }
         }
 
         /// Return a buffer to the pool for future reuse.
@@ -424,6 +498,21 @@ mod tests {
         assert_eq!(DEFAULT_INITIAL_CAPACITY, buf.inner.len());
     }
 
+    #[test]
+    fn should_append_bytes_and_grow() {
+        let mut buf = ReadBuffer::<8, 16>::new();
+        buf.append(b"hello ");
+        buf.append(b"world, this is longer than the initial capacity!");
+
+        assert_eq!(b"hello world, this is longer than the initial capacity!", buf.view());
+        assert!(buf.inner.len() >= buf.available());
+
+        buf.clear();
+        assert_eq!(0, buf.available());
+        buf.append(b"hello");
+        assert_eq!(b"hello", buf.view());
+    }
+
     #[test]
     fn should_read_all_from_stream() {
         let mut buf = ReadBuffer::<8>::new();
@@ -4,6 +4,8 @@ pub mod http;
 pub mod inet;
 pub mod service;
 pub mod stream;
+#[cfg(test)]
+mod testing;
 mod util;
 #[cfg(feature = "ws")]
 pub mod ws;
@@ -0,0 +1,458 @@
+//! Bounded lock-free queue used to send commands to the endpoints from other threads.
+//!
+//! The [`CommandSender`] can be cloned and moved to any number of producer threads, while the
+//! [`CommandReceiver`] is handed to the [`IOService`](crate::service::IOService) which drains
+//! the queue on every poll and delivers each command to the endpoint identified by the
+//! [`Handle`]. The queue never allocates once created and reports backpressure to the producer
+//! by returning the command back when it is full.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! use std::thread;
+//! use boomnet::service::command;
+//! # use boomnet::service::Handle;
+//! # let handle = Handle::default();
+//!
+//! let (sender, receiver) = command::channel::<u64>(1024);
+//! thread::spawn(move || {
+//!     let mut order_id = 0;
+//!     loop {
+//!         match sender.send(handle, order_id) {
+//!             Ok(()) => order_id += 1,
+//!             Err(err) if err.is_full() => thread::yield_now(),
+//!             Err(_) => break,
+//!         }
+//!     }
+//! });
+//! ```
+
+use crate::service::Handle;
+use crate::service::node::NodeSlab;
+use crate::service::select::{Selectable, SelectorToken};
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::mem::MaybeUninit;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use thiserror::Error;
+
+/// Create bounded command queue that can hold at least `capacity` commands, the capacity is
+/// rounded up to the next power of two.
+pub fn channel<T>(capacity: usize) -> (CommandSender<T>, CommandReceiver<T>) {
+    let queue = Arc::new(Queue::new(capacity));
+    (CommandSender { queue: queue.clone() }, CommandReceiver { queue })
+}
+
+/// Error returned when the command could not be sent, the command is handed back to the caller.
+#[derive(Error)]
+pub enum SendError<T> {
+    #[error("command queue is full")]
+    Full(Handle, T),
+    #[error("command receiver has been dropped")]
+    Disconnected(Handle, T),
+}
+
+impl<T> Debug for SendError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Full(handle, _) => f.debug_tuple("Full").field(handle).finish_non_exhaustive(),
+            SendError::Disconnected(handle, _) => f.debug_tuple("Disconnected").field(handle).finish_non_exhaustive(),
+        }
+    }
+}
+
+impl<T> SendError<T> {
+    /// Returns `true` if the command can be retried once the queue has been drained.
+    pub const fn is_full(&self) -> bool {
+        matches!(self, SendError::Full(..))
+    }
+
+    /// Get back the command that could not be sent.
+    pub fn into_inner(self) -> (Handle, T) {
+        match self {
+            SendError::Full(handle, command) => (handle, command),
+            SendError::Disconnected(handle, command) => (handle, command),
+        }
+    }
+}
+
+/// Producer side of the command queue, can be cloned and shared between threads.
+pub struct CommandSender<T> {
+    queue: Arc<Queue<(Handle, T)>>,
+}
+
+impl<T> Clone for CommandSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T> CommandSender<T> {
+    /// Send `command` to the endpoint identified by `handle`. If the queue is full or the
+    /// receiver has been dropped the command is returned as part of the error.
+    #[inline]
+    pub fn send(&self, handle: Handle, command: T) -> Result<(), SendError<T>> {
+        if self.queue.closed.load(Ordering::Relaxed) {
+            return Err(SendError::Disconnected(handle, command));
+        }
+        self.queue
+            .push((handle, command))
+            .map_err(|(handle, command)| SendError::Full(handle, command))
+    }
+
+    /// Number of commands that are waiting to be delivered.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns `true` if there are no commands waiting to be delivered.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maximum number of commands the queue can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+/// Consumer side of the command queue, typically passed to the
+/// [`IOService`](crate::service::IOService).
+pub struct CommandReceiver<T> {
+    queue: Arc<Queue<(Handle, T)>>,
+}
+
+impl<T> CommandReceiver<T> {
+    /// Take the next command from the queue, if any.
+    #[inline]
+    pub fn try_recv(&mut self) -> Option<(Handle, T)> {
+        // SAFETY: receiver cannot be cloned and takes `&mut self` so there is only one consumer
+        unsafe { self.queue.pop() }
+    }
+
+    /// Number of commands that are waiting to be delivered.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns `true` if there are no commands waiting to be delivered.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maximum number of commands the queue can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+impl<T> Drop for CommandReceiver<T> {
+    fn drop(&mut self) {
+        self.queue.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Delivers the commands taken from the queue to the endpoints, allowing the
+/// [`IOService`](crate::service::IOService) to hold the receiver without knowing the command type.
+pub(crate) trait CommandDelivery<T, E, C> {
+    /// Deliver the commands that are currently in the queue to the connected endpoints. Commands
+    /// for the endpoints that are still `connecting` or `pending` reconnection are held until the
+    /// connection has been established, while commands for the endpoints that no longer exist are
+    /// undeliverable. Endpoints for which the handler has failed are added to `failed` and their
+    /// commands are held as well, while endpoints left with pending IO are added to `woken` if provided.
+    fn deliver(
+        &mut self,
+        io_nodes: &mut NodeSlab<T, E>,
+        ctx: &mut C,
+        failed: &mut Vec<(SelectorToken, io::Error)>,
+        woken: Option<&mut Vec<SelectorToken>>,
+        connecting: &[SelectorToken],
+        pending: &dyn Fn(Handle) -> bool,
+    );
+}
+
+/// Command receiver together with the handler the commands are passed to and the callback
+/// undeliverable commands are returned to.
+pub(crate) struct CommandHandler<Cmd, F, U> {
+    receiver: CommandReceiver<Cmd>,
+    handler: F,
+    undelivered: U,
+    /// Commands waiting for the endpoint to connect, in the order they have been received.
+    held: VecDeque<(Handle, Cmd)>,
+}
+
+impl<Cmd, F, U> CommandHandler<Cmd, F, U> {
+    pub(crate) const fn new(receiver: CommandReceiver<Cmd>, handler: F, undelivered: U) -> Self {
+        Self {
+            receiver,
+            handler,
+            undelivered,
+            held: VecDeque::new(),
+        }
+    }
+}
+
+impl<T, E, C, Cmd, F, U> CommandDelivery<T, E, C> for CommandHandler<Cmd, F, U>
+where
+    T: Selectable,
+    F: FnMut(&mut T, &mut E, &mut C, Cmd) -> io::Result<()>,
+    U: FnMut(Handle, Cmd),
+{
+    fn deliver(
+        &mut self,
+        io_nodes: &mut NodeSlab<T, E>,
+        ctx: &mut C,
+        failed: &mut Vec<(SelectorToken, io::Error)>,
+        mut woken: Option<&mut Vec<SelectorToken>>,
+        connecting: &[SelectorToken],
+        pending: &dyn Fn(Handle) -> bool,
+    ) {
+        // held commands go first so that the order of commands sent to each endpoint is preserved,
+        // while commands sent while draining are left for the next poll
+        let held = self.held.len();
+        let received = self.receiver.len();
+        for i in 0..held + received {
+            let next = match i < held {
+                true => self.held.pop_front(),
+                false => self.receiver.try_recv(),
+            };
+            let Some((handle, command)) = next else {
+                break;
+            };
+            let Some(io_node) = io_nodes.get_mut(handle.token()).filter(|io_node| io_node.owns(handle)) else {
+                match pending(handle) {
+                    true => self.held.push_back((handle, command)),
+                    false => (self.undelivered)(handle, command),
+                }
+                continue;
+            };
+            if connecting.contains(&handle.token()) || failed.iter().any(|(token, _)| *token == handle.token()) {
+                self.held.push_back((handle, command));
+                continue;
+            }
+            let (target, (_, endpoint)) = io_node.as_parts_mut();
+            match (self.handler)(target, endpoint, ctx, command) {
+                Ok(()) => {
+                    if let Some(woken) = woken.as_mut() {
+                        if target.has_pending_io() {
+                            woken.push(handle.token());
+                        }
+                    }
+                }
+                Err(err) => failed.push((handle.token(), err)),
+            }
+        }
+    }
+}
+
+/// Prevents the producer and consumer positions from sharing the cache line.
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+struct Slot<T> {
+    /// Position the slot can be written at (if equal to the enqueue position) or read from (if
+    /// one ahead of the dequeue position).
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Bounded multi-producer queue based on the array of sequenced slots, the consumer is required
+/// to be unique.
+struct Queue<T> {
+    slots: Box<[Slot<T>]>,
+    mask: usize,
+    enqueue_pos: CachePadded<AtomicUsize>,
+    dequeue_pos: CachePadded<AtomicUsize>,
+    closed: AtomicBool,
+}
+
+// SAFETY: values are moved between threads through the slots that are handed over using the
+// sequence numbers
+unsafe impl<T: Send> Send for Queue<T> {}
+unsafe impl<T: Send> Sync for Queue<T> {}
+
+impl<T> Queue<T> {
+    fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+        Self {
+            slots: (0..capacity)
+                .map(|pos| Slot {
+                    sequence: AtomicUsize::new(pos),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            mask: capacity - 1,
+            enqueue_pos: CachePadded(AtomicUsize::new(0)),
+            dequeue_pos: CachePadded(AtomicUsize::new(0)),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    #[inline]
+    fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        let dequeue_pos = self.dequeue_pos.load(Ordering::Relaxed);
+        let enqueue_pos = self.enqueue_pos.load(Ordering::Relaxed);
+        enqueue_pos.wrapping_sub(dequeue_pos).min(self.capacity())
+    }
+
+    #[inline]
+    fn push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.enqueue_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match (sequence as isize).wrapping_sub(pos as isize) {
+                0 => match self.enqueue_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: the slot has been claimed by this producer
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.sequence.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                },
+                // the slot has not been consumed yet
+                diff if diff < 0 => return Err(value),
+                // another producer has claimed the slot
+                _ => pos = self.enqueue_pos.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// # Safety
+    /// Must not be called concurrently.
+    #[inline]
+    unsafe fn pop(&self) -> Option<T> {
+        let pos = self.dequeue_pos.load(Ordering::Relaxed);
+        let slot = &self.slots[pos & self.mask];
+        let sequence = slot.sequence.load(Ordering::Acquire);
+        if sequence != pos.wrapping_add(1) {
+            return None;
+        }
+        self.dequeue_pos.store(pos.wrapping_add(1), Ordering::Relaxed);
+        // SAFETY: the slot has been published by the producer
+        let value = unsafe { (*slot.value.get()).assume_init_read() };
+        slot.sequence
+            .store(pos.wrapping_add(self.capacity()), Ordering::Release);
+        Some(value)
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        // SAFETY: we have exclusive access
+        while unsafe { self.pop() }.is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::thread;
+
+    #[test]
+    fn should_report_backpressure_when_full() {
+        let (sender, mut receiver) = channel::<u32>(3);
+        assert_eq!(4, sender.capacity());
+        for i in 0..4 {
+            sender.send(Handle::new(i, 0), i).unwrap();
+        }
+        let err = sender.send(Handle::new(4, 0), 4).unwrap_err();
+        assert!(err.is_full());
+        assert_eq!((Handle::new(4, 0), 4), err.into_inner());
+        assert_eq!(4, receiver.len());
+
+        assert_eq!(Some((Handle::new(0, 0), 0)), receiver.try_recv());
+        sender.send(Handle::new(4, 0), 4).unwrap();
+        let received: Vec<_> = std::iter::from_fn(|| receiver.try_recv()).map(|(_, i)| i).collect();
+        assert_eq!(vec![1, 2, 3, 4], received);
+        assert!(receiver.is_empty());
+    }
+
+    #[test]
+    fn should_report_disconnected_once_receiver_dropped() {
+        let (sender, receiver) = channel::<u32>(4);
+        drop(receiver);
+        let err = sender.send(Handle::new(0, 0), 0).unwrap_err();
+        assert!(!err.is_full());
+        assert_eq!((Handle::new(0, 0), 0), err.into_inner());
+    }
+
+    #[test]
+    fn should_preserve_order_per_producer() {
+        const PRODUCERS: u32 = 4;
+        const COMMANDS: u32 = 10_000;
+        let (sender, mut receiver) = channel::<u32>(64);
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|producer| {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for i in 0..COMMANDS {
+                        let mut command = i;
+                        while let Err(err) = sender.send(Handle::new(producer, 0), command) {
+                            command = err.into_inner().1;
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let mut next = [0u32; PRODUCERS as usize];
+        let mut received = 0;
+        while received < PRODUCERS * COMMANDS {
+            match receiver.try_recv() {
+                Some((handle, command)) => {
+                    let producer = handle.token() as usize;
+                    assert_eq!(next[producer], command);
+                    next[producer] += 1;
+                    received += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        producers.into_iter().for_each(|producer| producer.join().unwrap());
+    }
+
+    #[test]
+    fn should_drop_commands_left_in_queue() {
+        let command = Rc::new(());
+        let (sender, receiver) = channel(4);
+        sender.send(Handle::new(0, 0), command.clone()).unwrap();
+        sender.send(Handle::new(1, 0), command.clone()).unwrap();
+        drop(sender);
+        drop(receiver);
+        assert_eq!(1, Rc::strong_count(&command));
+    }
+}
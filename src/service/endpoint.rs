@@ -16,10 +16,26 @@ pub trait Endpoint: ConnectionInfoProvider {
     /// await the next connection attempt with (possibly) different `addr`.
     fn create_target(&mut self, addr: SocketAddr) -> io::Result<Option<Self::Target>>;
 
+    /// Invoked once the DNS query has completed with all the `addrs` the `IOService` will
+    /// attempt to connect to, in order.
+    fn on_dns_resolved(&mut self, _addrs: &[SocketAddr]) {}
+
+    /// Invoked once the connection has been established, before the `target` is polled for the
+    /// first time. This is the place to send any subscriptions. If error is returned the
+    /// connection is dropped as if it had failed during polling.
+    fn on_connected(&mut self, _target: &mut Self::Target) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Invoked whenever the connection is dropped or could not be established, before the
+    /// `IOService` queries [`Endpoint::can_recreate`] and parks the endpoint in the pending queue.
+    fn on_disconnected(&mut self, _reason: &DisconnectReason) {}
+
     /// Upon disconnection `IOService` will query the endpoint if the connection can be
-    /// recreated, passing the disconnect `reason`. If `false` is returned it will cause
-    /// program to panic.
-    fn can_recreate(&mut self, _reason: DisconnectReason) -> bool {
+    /// recreated, passing the disconnect `reason`. If `false` is returned the endpoint will be
+    /// removed from the service and made available via `IOService::drain_removed` together with
+    /// the same `reason`.
+    fn can_recreate(&mut self, _reason: &DisconnectReason) -> bool {
         true
     }
 
@@ -46,10 +62,27 @@ pub trait EndpointWithContext<C>: ConnectionInfoProvider {
     /// return `Ok(None)` and await the next connection attempt with (possibly) different `addr`.
     fn create_target(&mut self, addr: SocketAddr, context: &mut C) -> io::Result<Option<Self::Target>>;
 
+    /// Invoked once the DNS query has completed with all the `addrs` the `IOService` will
+    /// attempt to connect to, in order.
+    fn on_dns_resolved(&mut self, _addrs: &[SocketAddr], _context: &mut C) {}
+
+    /// Invoked once the connection has been established, before the `target` is polled for the
+    /// first time. This is the place to send any subscriptions. If error is returned the
+    /// connection is dropped as if it had failed during polling.
+    fn on_connected(&mut self, _target: &mut Self::Target, _context: &mut C) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Invoked whenever the connection is dropped or could not be established, before the
+    /// `IOService` queries [`EndpointWithContext::can_recreate`] and parks the endpoint in the
+    /// pending queue.
+    fn on_disconnected(&mut self, _reason: &DisconnectReason, _context: &mut C) {}
+
     /// Upon disconnection `IOService` will query the endpoint if the connection can be
-    /// recreated, passing the disconnect `reason`. If `false` is returned it will cause
-    /// program to panic.
-    fn can_recreate(&mut self, _reason: DisconnectReason, _context: &mut C) -> bool {
+    /// recreated, passing the disconnect `reason`. If `false` is returned the endpoint will be
+    /// removed from the service and made available via `IOService::drain_removed` together with
+    /// the same `reason`.
+    fn can_recreate(&mut self, _reason: &DisconnectReason, _context: &mut C) -> bool {
         true
     }
 
@@ -62,11 +95,14 @@ pub trait EndpointWithContext<C>: ConnectionInfoProvider {
 }
 
 /// Disconnect reason passed into `can_recreate()` service call.
+#[derive(Debug)]
 pub enum DisconnectReason {
     /// This is expected disconnection due to `ttl` on the connection expiring.
     AutoDisconnect(Duration),
     /// IO error has occurred such as reaching EOF or peer disconnect.
     IO(io::Error),
+    /// Reconnect policy has given up after the number of consecutive attempts.
+    ReconnectAttemptsExhausted(u32),
 }
 
 impl Display for DisconnectReason {
@@ -79,6 +115,9 @@ impl Display for DisconnectReason {
             DisconnectReason::IO(err) => {
                 write!(f, "{err}")
             }
+            DisconnectReason::ReconnectAttemptsExhausted(attempts) => {
+                write!(f, "gave up reconnecting after {attempts} attempts")
+            }
         }
     }
 }
@@ -91,6 +130,10 @@ impl DisconnectReason {
     pub(crate) fn other(err: io::Error) -> DisconnectReason {
         DisconnectReason::IO(err)
     }
+
+    pub(crate) fn reconnect_attempts_exhausted(attempts: u32) -> DisconnectReason {
+        DisconnectReason::ReconnectAttemptsExhausted(attempts)
+    }
 }
 
 #[cfg(all(feature = "ext", feature = "ws", any(feature = "rustls", feature = "openssl")))]
@@ -111,7 +154,15 @@ pub mod ws {
 
         fn create_websocket(&mut self, addr: SocketAddr) -> io::Result<Option<Websocket<TlsStream<Self::Stream>>>>;
 
-        fn can_recreate(&mut self, _reason: DisconnectReason) -> bool {
+        fn on_dns_resolved(&mut self, _addrs: &[SocketAddr]) {}
+
+        fn on_connected(&mut self, _ws: &mut TlsWebsocket<Self::Stream>) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn on_disconnected(&mut self, _reason: &DisconnectReason) {}
+
+        fn can_recreate(&mut self, _reason: &DisconnectReason) -> bool {
             true
         }
 
@@ -132,7 +183,22 @@ pub mod ws {
         }
 
         #[inline]
-        fn can_recreate(&mut self, reason: DisconnectReason) -> bool {
+        fn on_dns_resolved(&mut self, addrs: &[SocketAddr]) {
+            self.on_dns_resolved(addrs)
+        }
+
+        #[inline]
+        fn on_connected(&mut self, target: &mut Self::Target) -> io::Result<()> {
+            self.on_connected(target)
+        }
+
+        #[inline]
+        fn on_disconnected(&mut self, reason: &DisconnectReason) {
+            self.on_disconnected(reason)
+        }
+
+        #[inline]
+        fn can_recreate(&mut self, reason: &DisconnectReason) -> bool {
             self.can_recreate(reason)
         }
 
@@ -151,7 +217,15 @@ pub mod ws {
             ctx: &mut C,
         ) -> io::Result<Option<Websocket<TlsStream<Self::Stream>>>>;
 
-        fn can_recreate(&mut self, _reason: DisconnectReason, _ctx: &mut C) -> bool {
+        fn on_dns_resolved(&mut self, _addrs: &[SocketAddr], _ctx: &mut C) {}
+
+        fn on_connected(&mut self, _ws: &mut TlsWebsocket<Self::Stream>, _ctx: &mut C) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn on_disconnected(&mut self, _reason: &DisconnectReason, _ctx: &mut C) {}
+
+        fn can_recreate(&mut self, _reason: &DisconnectReason, _ctx: &mut C) -> bool {
             true
         }
 
@@ -172,7 +246,22 @@ pub mod ws {
         }
 
         #[inline]
-        fn can_recreate(&mut self, reason: DisconnectReason, context: &mut C) -> bool {
+        fn on_dns_resolved(&mut self, addrs: &[SocketAddr], context: &mut C) {
+            self.on_dns_resolved(addrs, context)
+        }
+
+        #[inline]
+        fn on_connected(&mut self, target: &mut Self::Target, context: &mut C) -> io::Result<()> {
+            self.on_connected(target, context)
+        }
+
+        #[inline]
+        fn on_disconnected(&mut self, reason: &DisconnectReason, context: &mut C) {
+            self.on_disconnected(reason, context)
+        }
+
+        #[inline]
+        fn can_recreate(&mut self, reason: &DisconnectReason, context: &mut C) -> bool {
             self.can_recreate(reason, context)
         }
 
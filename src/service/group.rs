@@ -0,0 +1,393 @@
+//! Arbitration of redundant (A/B) feeds that publish the same sequenced messages on more than one
+//! connection.
+//!
+//! The [`EndpointGroup`] is created by [`IOService::register_group`](crate::service::IOService::register_group),
+//! which registers one endpoint per leg and reserves a logical [`Handle`] for the whole group. The
+//! messages read by each leg are passed to [`EndpointGroup::on_message`] and only the first copy of
+//! each sequence number is delivered to the application. Messages that are missing on the leading
+//! leg can still be delivered late by the other legs as long as the gap is within the recovery
+//! window.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! use std::io;
+//! use std::net::SocketAddr;
+//! use boomnet::service::IntoIOService;
+//! use boomnet::service::endpoint::Endpoint;
+//! use boomnet::service::group::GroupEvent;
+//! use boomnet::service::select::direct::DirectSelector;
+//! use boomnet::stream::{ConnectionInfo, ConnectionInfoProvider};
+//! use boomnet::stream::tcp::TcpStream;
+//! use std::io::Read;
+//!
+//! struct FeedEndpoint {
+//!     leg: usize,
+//!     connection_info: ConnectionInfo,
+//! }
+//!
+//! impl ConnectionInfoProvider for FeedEndpoint {
+//!     fn connection_info(&self) -> &ConnectionInfo {
+//!         &self.connection_info
+//!     }
+//! }
+//!
+//! impl Endpoint for FeedEndpoint {
+//!     type Target = TcpStream;
+//!
+//!     fn create_target(&mut self, addr: SocketAddr) -> io::Result<Option<Self::Target>> {
+//!         self.connection_info.clone().into_tcp_stream_with_addr(addr).map(Some)
+//!     }
+//! }
+//!
+//! fn main() -> io::Result<()> {
+//!     let mut io_service = DirectSelector::new()?.into_io_service();
+//!
+//!     // sequence number is carried in the first eight bytes of each message
+//!     let sequence = |msg: &[u8]| u64::from_le_bytes(msg[..8].try_into().unwrap());
+//!     let mut group = io_service.register_group(2, sequence, |leg, _handle| {
+//!         let port = 9000 + leg as u16;
+//!         Ok(FeedEndpoint { leg, connection_info: ConnectionInfo::new("127.0.0.1", port) })
+//!     })?;
+//!
+//!     loop {
+//!         io_service.poll(|stream, endpoint| {
+//!             let mut msg = [0u8; 64];
+//!             if let Ok(64) = stream.read(&mut msg) {
+//!                 group.on_message(endpoint.leg, &msg[..], |msg| println!("{msg:?}"));
+//!             }
+//!             Ok(())
+//!         })?;
+//!         for event in group.drain_events() {
+//!             if let GroupEvent::Gap { from, to } = event {
+//!                 println!("messages {from}..{to} have not been received on any leg");
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+
+use crate::service::Handle;
+use std::collections::VecDeque;
+use std::ops::Range;
+
+/// Default number of sequence numbers behind the next expected message for which the gaps can
+/// still be filled by the other legs.
+pub const DEFAULT_RECOVERY_WINDOW: u64 = 1024;
+
+/// Events reported by the [`EndpointGroup`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GroupEvent {
+    /// The leg has delivered the first message and became the leader.
+    Leader { leg: usize },
+    /// Leadership has moved to the leg that got ahead of the previous leader by more than the
+    /// failover threshold, typically because the previous leader has stalled or disconnected.
+    Failover { from: usize, to: usize },
+    /// Messages with sequence numbers `from..to` have not been received on any leg yet. They can
+    /// still be delivered late by the other legs while within the recovery window.
+    Gap { from: u64, to: u64 },
+}
+
+struct Leg {
+    handle: Handle,
+    /// Last generation issued for the leg, known once the leg endpoint has been removed.
+    last_generation: Option<u32>,
+    /// Highest sequence number received on this leg.
+    sequence: Option<u64>,
+}
+
+/// Group of redundant endpoints, called legs, that publish the same messages. Each message is
+/// delivered to the application once, no matter how many legs have received it, while the group
+/// keeps track of the leading leg and the gaps in the sequence.
+pub struct EndpointGroup<X> {
+    handle: Handle,
+    legs: Vec<Leg>,
+    extractor: X,
+    last_sequence: Option<u64>,
+    leader: Option<usize>,
+    failover_threshold: u64,
+    recovery_window: u64,
+    gaps: Vec<Range<u64>>,
+    events: VecDeque<GroupEvent>,
+}
+
+impl<X> EndpointGroup<X> {
+    pub(crate) fn new(handle: Handle, legs: impl IntoIterator<Item = Handle>, extractor: X) -> Self {
+        Self {
+            handle,
+            legs: legs
+                .into_iter()
+                .map(|handle| Leg {
+                    handle,
+                    last_generation: None,
+                    sequence: None,
+                })
+                .collect(),
+            extractor,
+            last_sequence: None,
+            leader: None,
+            failover_threshold: 0,
+            recovery_window: DEFAULT_RECOVERY_WINDOW,
+            gaps: Vec::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Only fail over once the leader is behind the other leg by more than `threshold` messages,
+    /// by default the leadership moves to whichever leg delivers the next message first.
+    pub fn with_failover_threshold(self, threshold: u64) -> Self {
+        Self {
+            failover_threshold: threshold,
+            ..self
+        }
+    }
+
+    /// Number of sequence numbers behind the next expected message for which the gaps can still
+    /// be filled by the other legs, older gaps are forgotten.
+    pub fn with_recovery_window(self, window: u64) -> Self {
+        Self {
+            recovery_window: window,
+            ..self
+        }
+    }
+
+    /// Logical handle of the whole group.
+    #[inline]
+    pub const fn handle(&self) -> Handle {
+        self.handle
+    }
+
+    /// Handles of the group legs, indexed by the leg.
+    #[inline]
+    pub fn leg_handles(&self) -> impl Iterator<Item = Handle> + '_ {
+        self.legs.iter().map(|leg| leg.handle)
+    }
+
+    /// Find the leg the endpoint identified by `handle` belongs to, any generation of the handle
+    /// issued for the leg endpoint can be used.
+    pub fn leg(&self, handle: Handle) -> Option<usize> {
+        self.legs.iter().position(|leg| {
+            handle.token() == leg.handle.token()
+                && handle.generation() >= leg.handle.generation()
+                && leg.last_generation.is_none_or(|last| handle.generation() <= last)
+        })
+    }
+
+    /// Mark the leg the endpoint identified by `handle` belongs to as removed, so that the handles
+    /// issued later on for the recycled token no longer resolve to the leg. Should be called with
+    /// the handles returned by [`IOService::drain_removed`](crate::service::IOService::drain_removed).
+    pub fn on_leg_removed(&mut self, handle: Handle) -> Option<usize> {
+        let index = self.leg(handle)?;
+        self.legs[index].last_generation = Some(handle.generation());
+        Some(index)
+    }
+
+    /// Index of the leading leg, if any message has been received yet.
+    #[inline]
+    pub const fn leader(&self) -> Option<usize> {
+        self.leader
+    }
+
+    /// Sequence number of the next message expected by the group, saturates at `u64::MAX`.
+    #[inline]
+    pub fn next_sequence(&self) -> Option<u64> {
+        self.last_sequence.map(|last| last.saturating_add(1))
+    }
+
+    /// Highest sequence number received on the `leg`.
+    #[inline]
+    pub fn leg_sequence(&self, leg: usize) -> Option<u64> {
+        self.legs[leg].sequence
+    }
+
+    /// Pass the message received on the `leg` to the group, `deliver` is invoked if this is the
+    /// first copy of the message, otherwise the message is dropped and `None` returned.
+    pub fn on_message<M, T, F>(&mut self, leg: usize, msg: &M, deliver: F) -> Option<T>
+    where
+        M: ?Sized,
+        X: FnMut(&M) -> u64,
+        F: FnOnce(&M) -> T,
+    {
+        let sequence = (self.extractor)(msg);
+        let leg_sequence = &mut self.legs[leg].sequence;
+        *leg_sequence = Some(leg_sequence.map_or(sequence, |current| current.max(sequence)));
+        match self.last_sequence {
+            Some(last) if sequence > last => {
+                self.last_sequence = Some(sequence);
+                // cannot overflow as `last` is below `sequence`
+                let next = last + 1;
+                if sequence > next {
+                    self.open_gap(next..sequence);
+                }
+                self.update_leader(leg, sequence);
+                Some(deliver(msg))
+            }
+            Some(_) => self.fill_gap(sequence).then(|| deliver(msg)),
+            None => {
+                self.last_sequence = Some(sequence);
+                self.leader = Some(leg);
+                self.events.push_back(GroupEvent::Leader { leg });
+                Some(deliver(msg))
+            }
+        }
+    }
+
+    /// Drain the events reported since the last call.
+    #[inline]
+    pub fn drain_events(&mut self) -> impl Iterator<Item = GroupEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    #[inline]
+    fn update_leader(&mut self, leg: usize, sequence: u64) {
+        let Some(leader) = self.leader else {
+            return;
+        };
+        if leader == leg {
+            return;
+        }
+        let leader_sequence = self.legs[leader].sequence.unwrap_or(0);
+        if sequence.saturating_sub(leader_sequence) > self.failover_threshold {
+            self.leader = Some(leg);
+            self.events.push_back(GroupEvent::Failover { from: leader, to: leg });
+        }
+    }
+
+    #[cold]
+    fn open_gap(&mut self, gap: Range<u64>) {
+        self.events.push_back(GroupEvent::Gap {
+            from: gap.start,
+            to: gap.end,
+        });
+        self.gaps.push(gap);
+        self.expire_gaps();
+    }
+
+    /// Returns `true` if the late `sequence` falls into one of the open gaps within the recovery
+    /// window, which is then narrowed down.
+    #[inline]
+    fn fill_gap(&mut self, sequence: u64) -> bool {
+        // duplicates are the common case
+        if self.gaps.is_empty() || sequence < self.oldest_recoverable() {
+            return false;
+        }
+        let Some(index) = self.gaps.iter().position(|gap| gap.contains(&sequence)) else {
+            return false;
+        };
+        let gap = self.gaps.remove(index);
+        // cannot overflow as `sequence` is below the gap end
+        let after = sequence + 1..gap.end;
+        let before = gap.start..sequence;
+        if !after.is_empty() {
+            self.gaps.insert(index, after);
+        }
+        if !before.is_empty() {
+            self.gaps.insert(index, before);
+        }
+        true
+    }
+
+    /// Forget the gaps that have fallen behind the recovery window.
+    fn expire_gaps(&mut self) {
+        let oldest = self.oldest_recoverable();
+        self.gaps.retain(|gap| gap.end > oldest);
+    }
+
+    #[inline]
+    fn oldest_recoverable(&self) -> u64 {
+        self.next_sequence().unwrap_or(0).saturating_sub(self.recovery_window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group() -> EndpointGroup<impl FnMut(&u64) -> u64> {
+        EndpointGroup::new(Handle::new(0, 0), [Handle::new(1, 0), Handle::new(2, 0)], |msg: &u64| *msg)
+    }
+
+    fn feed<X: FnMut(&u64) -> u64>(group: &mut EndpointGroup<X>, leg: usize, sequences: &[u64]) -> Vec<u64> {
+        sequences
+            .iter()
+            .filter_map(|sequence| group.on_message(leg, sequence, |msg| *msg))
+            .collect()
+    }
+
+    #[test]
+    fn should_deliver_each_message_once() {
+        let mut group = group();
+        assert_eq!(vec![1, 2, 3], feed(&mut group, 0, &[1, 2, 3]));
+        assert_eq!(vec![4], feed(&mut group, 1, &[1, 2, 3, 4]));
+        assert_eq!(Vec::<u64>::new(), feed(&mut group, 0, &[4]));
+        assert_eq!(Some(5), group.next_sequence());
+        assert_eq!(Some(4), group.leg_sequence(0));
+        assert_eq!(Some(4), group.leg_sequence(1));
+        assert_eq!(
+            vec![GroupEvent::Leader { leg: 0 }, GroupEvent::Failover { from: 0, to: 1 }],
+            group.drain_events().collect::<Vec<_>>()
+        );
+        assert_eq!(Some(1), group.leader());
+    }
+
+    #[test]
+    fn should_fill_gaps_from_other_leg() {
+        let mut group = group();
+        assert_eq!(vec![1, 2, 5, 6], feed(&mut group, 0, &[1, 2, 5, 6]));
+        assert_eq!(
+            vec![GroupEvent::Leader { leg: 0 }, GroupEvent::Gap { from: 3, to: 5 }],
+            group.drain_events().collect::<Vec<_>>()
+        );
+
+        // the other leg is behind but has received the messages lost by the leader
+        assert_eq!(vec![4, 3], feed(&mut group, 1, &[1, 2, 4, 3, 5, 6]));
+        assert_eq!(Vec::<u64>::new(), feed(&mut group, 1, &[3, 4]));
+        assert_eq!(Some(0), group.leader());
+        assert_eq!(0, group.drain_events().count());
+    }
+
+    #[test]
+    fn should_forget_gaps_outside_recovery_window() {
+        let mut group = group().with_recovery_window(4);
+        assert_eq!(vec![1, 3], feed(&mut group, 0, &[1, 3]));
+        assert_eq!(vec![10], feed(&mut group, 0, &[10]));
+        assert_eq!(Vec::<u64>::new(), feed(&mut group, 1, &[2]));
+        assert_eq!(vec![9], feed(&mut group, 1, &[9]));
+    }
+
+    #[test]
+    fn should_fail_over_once_leader_falls_behind_threshold() {
+        let mut group = group().with_failover_threshold(2);
+        assert_eq!(vec![1, 2], feed(&mut group, 0, &[1, 2]));
+        assert_eq!(vec![3, 4], feed(&mut group, 1, &[1, 2, 3, 4]));
+        assert_eq!(Some(0), group.leader());
+        assert_eq!(vec![5], feed(&mut group, 1, &[5]));
+        assert_eq!(Some(1), group.leader());
+        assert_eq!(
+            vec![GroupEvent::Leader { leg: 0 }, GroupEvent::Failover { from: 0, to: 1 }],
+            group.drain_events().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn should_find_leg_by_handle() {
+        let mut group = EndpointGroup::new(Handle::new(0, 0), [Handle::new(1, 0), Handle::new(2, 2)], |msg: &u64| *msg);
+        assert_eq!(Some(1), group.leg(Handle::new(2, 3)));
+        assert_eq!(None, group.leg(Handle::new(2, 1)));
+        assert_eq!(None, group.leg(Handle::new(0, 0)));
+        assert_eq!(vec![Handle::new(1, 0), Handle::new(2, 2)], group.leg_handles().collect::<Vec<_>>());
+
+        // the recycled token no longer belongs to the removed leg
+        assert_eq!(Some(1), group.on_leg_removed(Handle::new(2, 4)));
+        assert_eq!(Some(1), group.leg(Handle::new(2, 4)));
+        assert_eq!(None, group.leg(Handle::new(2, 5)));
+    }
+
+    #[test]
+    fn should_not_overflow_at_max_sequence() {
+        let mut group = group();
+        assert_eq!(vec![u64::MAX - 2, u64::MAX], feed(&mut group, 0, &[u64::MAX - 2, u64::MAX]));
+        assert_eq!(vec![u64::MAX - 1], feed(&mut group, 1, &[u64::MAX - 1, u64::MAX]));
+        assert_eq!(Some(u64::MAX), group.next_sequence());
+    }
+}
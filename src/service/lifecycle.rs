@@ -0,0 +1,151 @@
+use crate::service::command::CommandDelivery;
+use crate::service::endpoint::{DisconnectReason, Endpoint, EndpointWithContext};
+use crate::service::node::NodeSlab;
+use crate::service::select::SelectorToken;
+use crate::service::{Handle, TimerCallback};
+use std::io;
+use std::net::SocketAddr;
+
+/// Forwards the service events to either [`Endpoint`] or [`EndpointWithContext`] so that the
+/// connection management code can be shared by both flavours of the service.
+pub trait Lifecycle<E, T, C> {
+    fn create_target(&mut self, endpoint: &mut E, addr: SocketAddr) -> io::Result<Option<T>>;
+
+    fn on_dns_resolved(&mut self, endpoint: &mut E, addrs: &[SocketAddr]);
+
+    fn on_connected(&mut self, endpoint: &mut E, target: &mut T) -> io::Result<()>;
+
+    fn on_disconnected(&mut self, endpoint: &mut E, reason: &DisconnectReason);
+
+    fn can_recreate(&mut self, endpoint: &mut E, reason: &DisconnectReason) -> bool;
+
+    fn can_auto_disconnect(&mut self, endpoint: &mut E) -> bool;
+
+    fn on_timer(&mut self, callback: &mut TimerCallback<T, E, C>, target: &mut T, endpoint: &mut E) -> io::Result<()>;
+
+    fn on_commands(
+        &mut self,
+        commands: &mut dyn CommandDelivery<T, E, C>,
+        io_nodes: &mut NodeSlab<T, E>,
+        failed: &mut Vec<(SelectorToken, io::Error)>,
+        woken: Option<&mut Vec<SelectorToken>>,
+        connecting: &[SelectorToken],
+        pending: &dyn Fn(Handle) -> bool,
+    );
+}
+
+/// Events are passed to the [`Endpoint`].
+pub struct NoContext;
+
+impl<E: Endpoint> Lifecycle<E, E::Target, ()> for NoContext {
+    #[inline]
+    fn create_target(&mut self, endpoint: &mut E, addr: SocketAddr) -> io::Result<Option<E::Target>> {
+        endpoint.create_target(addr)
+    }
+
+    #[inline]
+    fn on_dns_resolved(&mut self, endpoint: &mut E, addrs: &[SocketAddr]) {
+        endpoint.on_dns_resolved(addrs)
+    }
+
+    #[inline]
+    fn on_connected(&mut self, endpoint: &mut E, target: &mut E::Target) -> io::Result<()> {
+        endpoint.on_connected(target)
+    }
+
+    #[inline]
+    fn on_disconnected(&mut self, endpoint: &mut E, reason: &DisconnectReason) {
+        endpoint.on_disconnected(reason)
+    }
+
+    #[inline]
+    fn can_recreate(&mut self, endpoint: &mut E, reason: &DisconnectReason) -> bool {
+        endpoint.can_recreate(reason)
+    }
+
+    #[inline]
+    fn can_auto_disconnect(&mut self, endpoint: &mut E) -> bool {
+        endpoint.can_auto_disconnect()
+    }
+
+    #[inline]
+    fn on_timer(
+        &mut self,
+        callback: &mut TimerCallback<E::Target, E, ()>,
+        target: &mut E::Target,
+        endpoint: &mut E,
+    ) -> io::Result<()> {
+        callback(target, endpoint, &mut ())
+    }
+
+    #[inline]
+    fn on_commands(
+        &mut self,
+        commands: &mut dyn CommandDelivery<E::Target, E, ()>,
+        io_nodes: &mut NodeSlab<E::Target, E>,
+        failed: &mut Vec<(SelectorToken, io::Error)>,
+        woken: Option<&mut Vec<SelectorToken>>,
+        connecting: &[SelectorToken],
+        pending: &dyn Fn(Handle) -> bool,
+    ) {
+        commands.deliver(io_nodes, &mut (), failed, woken, connecting, pending)
+    }
+}
+
+/// Events are passed to the [`EndpointWithContext`] together with the user provided context.
+pub struct WithContext<'a, C>(pub &'a mut C);
+
+impl<C, E: EndpointWithContext<C>> Lifecycle<E, E::Target, C> for WithContext<'_, C> {
+    #[inline]
+    fn create_target(&mut self, endpoint: &mut E, addr: SocketAddr) -> io::Result<Option<E::Target>> {
+        endpoint.create_target(addr, self.0)
+    }
+
+    #[inline]
+    fn on_dns_resolved(&mut self, endpoint: &mut E, addrs: &[SocketAddr]) {
+        endpoint.on_dns_resolved(addrs, self.0)
+    }
+
+    #[inline]
+    fn on_connected(&mut self, endpoint: &mut E, target: &mut E::Target) -> io::Result<()> {
+        endpoint.on_connected(target, self.0)
+    }
+
+    #[inline]
+    fn on_disconnected(&mut self, endpoint: &mut E, reason: &DisconnectReason) {
+        endpoint.on_disconnected(reason, self.0)
+    }
+
+    #[inline]
+    fn can_recreate(&mut self, endpoint: &mut E, reason: &DisconnectReason) -> bool {
+        endpoint.can_recreate(reason, self.0)
+    }
+
+    #[inline]
+    fn can_auto_disconnect(&mut self, endpoint: &mut E) -> bool {
+        endpoint.can_auto_disconnect(self.0)
+    }
+
+    #[inline]
+    fn on_timer(
+        &mut self,
+        callback: &mut TimerCallback<E::Target, E, C>,
+        target: &mut E::Target,
+        endpoint: &mut E,
+    ) -> io::Result<()> {
+        callback(target, endpoint, self.0)
+    }
+
+    #[inline]
+    fn on_commands(
+        &mut self,
+        commands: &mut dyn CommandDelivery<E::Target, E, C>,
+        io_nodes: &mut NodeSlab<E::Target, E>,
+        failed: &mut Vec<(SelectorToken, io::Error)>,
+        woken: Option<&mut Vec<SelectorToken>>,
+        connecting: &[SelectorToken],
+        pending: &dyn Fn(Handle) -> bool,
+    ) {
+        commands.deliver(io_nodes, self.0, failed, woken, connecting, pending)
+    }
+}
@@ -1,44 +1,115 @@
 //! Service to manage multiple endpoint lifecycle.
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::VecDeque;
 use std::io;
 use std::io::ErrorKind;
 use std::marker::PhantomData;
 use std::net::SocketAddr;
 use std::time::Duration;
 
+use crate::service::command::{CommandDelivery, CommandHandler, CommandReceiver};
 use crate::service::dns::{BlockingDnsResolver, DnsQuery, DnsResolver};
 use crate::service::endpoint::{Context, DisconnectReason, Endpoint, EndpointWithContext};
-use crate::service::node::IONode;
-use crate::service::select::{Selector, SelectorToken};
+use crate::service::group::EndpointGroup;
+use crate::service::lifecycle::{Lifecycle, NoContext, WithContext};
+use crate::service::node::{IONode, NodeSlab};
+use crate::service::pending::{PendingEndpoint, PendingEndpoints, Resolution};
+use crate::service::reconnect::{FixedDelay, ReconnectPolicy};
+use crate::service::select::{Selectable, Selector, SelectorToken};
 use crate::service::time::{SystemTimeClockSource, TimeSource};
+use crate::service::timer::{Timer, TimerId, TimerWheel};
 use crate::stream::ConnectionInfoProvider;
 
+pub mod command;
 pub mod dns;
 pub mod endpoint;
+pub mod group;
+mod lifecycle;
 mod node;
+mod pending;
+pub mod reconnect;
+pub mod runtime;
 pub mod select;
 pub mod time;
+pub mod timer;
 
-const ENDPOINT_CREATION_THROTTLE_NS: u64 = Duration::from_secs(1).as_nanos() as u64;
-
-/// Endpoint handle.
+/// Endpoint handle. Besides the token identifying the endpoint it carries the generation of the
+/// endpoint connection, which is bumped every time the endpoint is recreated. Tokens of the removed
+/// endpoints are recycled with the next generation, so handles of the previous owner never match.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Default)]
-#[repr(transparent)]
-pub struct Handle(SelectorToken);
+pub struct Handle {
+    token: SelectorToken,
+    generation: u32,
+}
+
+impl Handle {
+    pub(crate) const fn new(token: SelectorToken, generation: u32) -> Self {
+        Self { token, generation }
+    }
+
+    /// Token used to identify the endpoint by the [`Selector`].
+    #[inline]
+    pub const fn token(&self) -> SelectorToken {
+        self.token
+    }
+
+    /// Generation of the endpoint connection this handle has been issued for.
+    #[inline]
+    pub const fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Returns `true` if this handle has been issued for the same token as the `current` one, with
+    /// the generation between `first_generation` and the `current` one. Generations wrap around, so
+    /// they are compared by their distance from `first_generation`.
+    #[inline]
+    pub(crate) const fn issued_between(&self, first_generation: u32, current: Handle) -> bool {
+        self.token == current.token
+            && self.generation.wrapping_sub(first_generation) <= current.generation.wrapping_sub(first_generation)
+    }
+
+    #[inline]
+    const fn next_generation(self) -> Self {
+        Self {
+            token: self.token,
+            generation: self.generation.wrapping_add(1),
+        }
+    }
+}
+
+/// Callback invoked by the [`IOService`] timer, uniformly taking the context (`()` if there is
+/// none) so that both flavours of the service share the same timer wheel.
+type TimerCallback<T, E, C> = Box<dyn FnMut(&mut T, &mut E, &mut C) -> io::Result<()>>;
+
+/// Timer payload identifying the endpoint the callback is scheduled for.
+type EndpointTimer<T, E, C> = (Handle, TimerCallback<T, E, C>);
 
 /// Handles the lifecycle of endpoints (see [`Endpoint`]), which are typically network connections.
 /// It uses `SelectService` pattern for managing asynchronous I/O operations.
 pub struct IOService<S: Selector, E, C, TS, D: DnsResolver> {
     selector: S,
-    pending_endpoints: VecDeque<(Handle, D::Query, u64, E)>,
-    io_nodes: HashMap<SelectorToken, IONode<S::Target, E>>,
-    next_endpoint_create_time_ns: u64,
+    pending_endpoints: PendingEndpoints<D::Query, E>,
+    removed_endpoints: Vec<(Handle, E, DisconnectReason)>,
+    /// Handles of the removed endpoints whose tokens can be reused.
+    free_handles: Vec<Handle>,
+    io_nodes: NodeSlab<S::Target, E>,
+    connecting: Vec<SelectorToken>,
+    /// Endpoints that have to be polled regardless of the selector readiness.
+    woken: Vec<SelectorToken>,
+    ready: Vec<SelectorToken>,
+    ready_only: bool,
+    timers: TimerWheel<EndpointTimer<S::Target, E, C>>,
+    expired_timers: Vec<Timer<EndpointTimer<S::Target, E, C>>>,
+    commands: Option<Box<dyn CommandDelivery<S::Target, E, C>>>,
+    failed_commands: Vec<(SelectorToken, io::Error)>,
+    reconnect_policy: Box<dyn ReconnectPolicy>,
     context: PhantomData<C>,
     auto_disconnect: Option<Box<dyn Fn() -> Duration>>,
     time_source: TS,
     dns_resolver: D,
     dns_query_timeout_ns: Option<u64>,
+    connect_timeout_ns: Option<u64>,
+    happy_eyeballs: bool,
 }
 
 /// Defines how an instance that implements `SelectService` can be transformed
@@ -64,14 +135,26 @@ impl<S: Selector, E, C, TS, D: DnsResolver> IOService<S, E, C, TS, D> {
     pub fn new(selector: S, time_source: TS, dns_resolver: D) -> IOService<S, E, C, TS, D> {
         Self {
             selector,
-            pending_endpoints: VecDeque::new(),
-            io_nodes: HashMap::new(),
-            next_endpoint_create_time_ns: 0,
+            pending_endpoints: PendingEndpoints::default(),
+            removed_endpoints: Vec::new(),
+            free_handles: Vec::new(),
+            io_nodes: NodeSlab::default(),
+            connecting: Vec::new(),
+            woken: Vec::new(),
+            ready: Vec::new(),
+            ready_only: false,
+            timers: TimerWheel::default(),
+            expired_timers: Vec::new(),
+            commands: None,
+            failed_commands: Vec::new(),
+            reconnect_policy: Box::new(FixedDelay::default()),
             context: PhantomData,
             auto_disconnect: None,
             time_source,
             dns_resolver,
             dns_query_timeout_ns: None,
+            connect_timeout_ns: None,
+            happy_eyeballs: false,
         }
     }
 
@@ -100,18 +183,80 @@ impl<S: Selector, E, C, TS, D: DnsResolver> IOService<S, E, C, TS, D> {
         }
     }
 
+    /// Specify timeout for the connection to be established. Once the timeout expires the
+    /// connection attempt is considered to have failed and the next resolved address (if any) will
+    /// be tried before issuing a new DNS query.
+    pub fn with_connect_timeout(self, timeout: Duration) -> IOService<S, E, C, TS, D> {
+        Self {
+            connect_timeout_ns: Some(timeout.as_nanos() as u64),
+            ..self
+        }
+    }
+
+    /// Specify [`ReconnectPolicy`] used to decide when the endpoint should be reconnected,
+    /// by default the service will wait one second before each attempt.
+    pub fn with_reconnect_policy<P>(self, reconnect_policy: P) -> IOService<S, E, C, TS, D>
+    where
+        P: ReconnectPolicy + 'static,
+    {
+        Self {
+            reconnect_policy: Box::new(reconnect_policy),
+            ..self
+        }
+    }
+
+    /// Order the resolved addresses by alternating between IPv6 and IPv4 address families
+    /// (as per RFC 8305 section 4), starting with the family of the first resolved address. The
+    /// addresses are still tried one at a time, so this is best combined with the connect timeout.
+    pub fn with_happy_eyeballs(self, happy_eyeballs: bool) -> IOService<S, E, C, TS, D> {
+        Self { happy_eyeballs, ..self }
+    }
+
+    /// Only invoke the `poll` action for the endpoints that the [`Selector`] has reported as ready,
+    /// the endpoints with pending IO (see [`Selectable::has_pending_io`]), the endpoints that have
+    /// just connected and the endpoints whose timers have fired. When used with edge-triggered
+    /// selectors the action is expected to read from the stream until it would block. Selectors
+    /// that do not report readiness keep polling every endpoint.
+    pub fn with_ready_only(self, ready_only: bool) -> IOService<S, E, C, TS, D> {
+        Self {
+            ready_only: ready_only && self.selector.ready().is_some(),
+            ..self
+        }
+    }
+
+    /// Specify resolution of the timer wheel used to drive scheduled callbacks, by default
+    /// callbacks can fire up to one millisecond late. Any timers scheduled so far are discarded.
+    pub fn with_timer_resolution(self, resolution: Duration) -> IOService<S, E, C, TS, D> {
+        Self {
+            timers: TimerWheel::with_resolution(resolution),
+            ..self
+        }
+    }
+
     /// Specify custom [`TimeSource`] instead of the default system time source.
     pub fn with_time_source<T: TimeSource>(self, time_source: T) -> IOService<S, E, C, T, D> {
         IOService {
             time_source,
             pending_endpoints: Default::default(),
+            removed_endpoints: Default::default(),
+            free_handles: self.free_handles,
             context: self.context,
             auto_disconnect: self.auto_disconnect,
             io_nodes: Default::default(),
-            next_endpoint_create_time_ns: self.next_endpoint_create_time_ns,
+            connecting: Default::default(),
+            woken: Default::default(),
+            ready: Default::default(),
+            ready_only: self.ready_only,
+            timers: self.timers,
+            expired_timers: self.expired_timers,
+            commands: self.commands,
+            failed_commands: Default::default(),
+            reconnect_policy: self.reconnect_policy,
             selector: self.selector,
             dns_resolver: self.dns_resolver,
             dns_query_timeout_ns: self.dns_query_timeout_ns,
+            connect_timeout_ns: self.connect_timeout_ns,
+            happy_eyeballs: self.happy_eyeballs,
         }
     }
 
@@ -120,13 +265,25 @@ impl<S: Selector, E, C, TS, D: DnsResolver> IOService<S, E, C, TS, D> {
         IOService {
             time_source: self.time_source,
             pending_endpoints: Default::default(),
+            removed_endpoints: Default::default(),
+            free_handles: self.free_handles,
             context: self.context,
             auto_disconnect: self.auto_disconnect,
             io_nodes: Default::default(),
-            next_endpoint_create_time_ns: self.next_endpoint_create_time_ns,
+            connecting: Default::default(),
+            woken: Default::default(),
+            ready: Default::default(),
+            ready_only: self.ready_only,
+            timers: self.timers,
+            expired_timers: self.expired_timers,
+            commands: self.commands,
+            failed_commands: Default::default(),
+            reconnect_policy: self.reconnect_policy,
             selector: self.selector,
             dns_resolver,
             dns_query_timeout_ns: self.dns_query_timeout_ns,
+            connect_timeout_ns: self.connect_timeout_ns,
+            happy_eyeballs: self.happy_eyeballs,
         }
     }
 
@@ -136,12 +293,8 @@ impl<S: Selector, E, C, TS, D: DnsResolver> IOService<S, E, C, TS, D> {
         E: ConnectionInfoProvider,
         TS: TimeSource,
     {
-        let handle = Handle(self.selector.next_token());
-        let info = endpoint.connection_info();
-        let query = self.dns_resolver.new_query(info.host(), info.port())?;
-        let now = self.time_source.current_time_nanos();
-        self.pending_endpoints.push_back((handle, query, now, endpoint));
-        Ok(handle)
+        let handle = self.next_handle();
+        self.enqueue(handle, endpoint)
     }
 
     /// Register a new [`Endpoint`] with the service using provided factory and return a handle to
@@ -152,42 +305,111 @@ impl<S: Selector, E, C, TS, D: DnsResolver> IOService<S, E, C, TS, D> {
         TS: TimeSource,
         F: FnOnce(Handle) -> io::Result<E>,
     {
-        let handle = Handle(self.selector.next_token());
-        let endpoint = endpoint_factory(handle)?;
+        let handle = self.next_handle();
+        match endpoint_factory(handle) {
+            Ok(endpoint) => self.enqueue(handle, endpoint),
+            Err(err) => {
+                self.free_handles.push(handle);
+                Err(err)
+            }
+        }
+    }
+
+    /// Issue the DNS query for the newly registered endpoint and queue it to be connected, the
+    /// `handle` is released if the query cannot be created.
+    fn enqueue(&mut self, handle: Handle, endpoint: E) -> io::Result<Handle>
+    where
+        E: ConnectionInfoProvider,
+        TS: TimeSource,
+    {
         let info = endpoint.connection_info();
-        let query = self.dns_resolver.new_query(info.host(), info.port())?;
+        let query = match self.dns_resolver.new_query(info.connect_host(), info.connect_port()) {
+            Ok(query) => query,
+            Err(err) => {
+                self.free_handles.push(handle);
+                return Err(err);
+            }
+        };
         let now = self.time_source.current_time_nanos();
-        self.pending_endpoints.push_back((handle, query, now, endpoint));
+        self.pending_endpoints.push(PendingEndpoint {
+            handle,
+            first_generation: handle.generation,
+            resolution: Resolution::Query(query, now),
+            endpoint,
+            attempt: 0,
+            next_attempt_ns: now,
+        });
         Ok(handle)
     }
 
-    /// Deregister [`Endpoint`] with the service based on a handle.
+    /// Deregister [`Endpoint`] with the service based on a handle, any generation of the handle
+    /// can be used. The endpoint token is then recycled for the endpoints registered later on.
     pub fn deregister(&mut self, handle: Handle) -> Option<E> {
-        match self.io_nodes.remove(&handle.0) {
-            Some(io_node) => Some(io_node.into_endpoint().1),
-            None => {
-                let mut index_to_remove = None;
-                for (index, endpoint) in self.pending_endpoints.iter().enumerate() {
-                    if endpoint.0 == handle {
-                        index_to_remove = Some(index);
-                        break;
+        let (handle, endpoint) = match self.io_nodes.get(handle.token) {
+            Some(io_node) if io_node.owns(handle) => {
+                // SAFETY: checked above
+                let mut io_node = unsafe { self.io_nodes.remove(handle.token).unwrap_unchecked() };
+                // the selector must not report any more events under the token that is going to be reused
+                let _ = self.selector.unregister(&mut io_node);
+                self.connecting.retain(|token| *token != handle.token);
+                io_node.into_endpoint()
+            }
+            _ => self.pending_endpoints.remove(handle)?,
+        };
+        self.timers.retain_parked(|(parked, _)| parked.token != handle.token);
+        self.free_handles.push(handle);
+        Some(endpoint)
+    }
+
+    /// Register `legs` redundant endpoints, created by the `endpoint_factory` from the leg index and
+    /// the endpoint handle, as an [`EndpointGroup`] identified by its own logical handle. The
+    /// `extractor` returns the sequence number of the messages passed to the group, which is used
+    /// to deliver each message once (see [`group`]).
+    pub fn register_group<X, F>(
+        &mut self,
+        legs: usize,
+        extractor: X,
+        mut endpoint_factory: F,
+    ) -> io::Result<EndpointGroup<X>>
+    where
+        E: ConnectionInfoProvider,
+        TS: TimeSource,
+        F: FnMut(usize, Handle) -> io::Result<E>,
+    {
+        if legs == 0 {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "endpoint group requires at least one leg"));
+        }
+        let mut handles = Vec::with_capacity(legs);
+        for leg in 0..legs {
+            match self.register_with_factory(|handle| endpoint_factory(leg, handle)) {
+                Ok(handle) => handles.push(handle),
+                Err(err) => {
+                    for handle in handles {
+                        self.deregister(handle);
                     }
-                }
-                if let Some(index_to_remove) = index_to_remove {
-                    self.pending_endpoints
-                        .remove(index_to_remove)
-                        .map(|(_, _, _, endpoint)| endpoint)
-                } else {
-                    None
+                    return Err(err);
                 }
             }
         }
+        let handle = self.next_handle();
+        Ok(EndpointGroup::new(handle, handles, extractor))
+    }
+
+    /// Deregister all the legs of the [`EndpointGroup`], returning the endpoints that were still
+    /// registered with the service.
+    pub fn deregister_group<X>(&mut self, group: EndpointGroup<X>) -> Vec<E> {
+        let endpoints = group
+            .leg_handles()
+            .filter_map(|handle| self.deregister(handle))
+            .collect();
+        self.free_handles.push(group.handle());
+        endpoints
     }
 
     /// Return iterator over active endpoints, additionally exposing handle and the stream.
     #[inline]
     pub fn iter(&self) -> impl Iterator<Item = (Handle, &S::Target, &E)> {
-        self.io_nodes.values().map(|io_node| {
+        self.io_nodes.iter().map(|io_node| {
             let (stream, (handle, endpoint)) = io_node.as_parts();
             (*handle, stream, endpoint)
         })
@@ -196,22 +418,91 @@ impl<S: Selector, E, C, TS, D: DnsResolver> IOService<S, E, C, TS, D> {
     /// Return mutable iterator over active endpoints, additionally exposing handle and the stream.
     #[inline]
     pub fn iter_mut(&mut self) -> impl Iterator<Item = (Handle, &mut S::Target, &mut E)> {
-        self.io_nodes.values_mut().map(|io_node| {
+        self.io_nodes.iter_mut().map(|io_node| {
             let (stream, (handle, endpoint)) = io_node.as_parts_mut();
             (*handle, stream, endpoint)
         })
     }
 
+    /// Cancel timer scheduled with the service, returns `false` if the timer has already
+    /// fired (one-shot) or has been cancelled before.
+    pub fn cancel_timer(&mut self, id: TimerId) -> bool {
+        self.timers.cancel(id).is_some()
+    }
+
+    /// Drain endpoints that have been removed from the service, either because
+    /// [`Endpoint::can_recreate`] returned `false` or the [`ReconnectPolicy`] has given up. Each
+    /// endpoint is returned together with its handle and the reason it has been disconnected. The
+    /// tokens of the drained endpoints are recycled for the endpoints registered later on.
+    #[inline]
+    pub fn drain_removed(&mut self) -> impl Iterator<Item = (Handle, E, DisconnectReason)> + '_ {
+        let free_handles = &mut self.free_handles;
+        let timers = &mut self.timers;
+        self.removed_endpoints.drain(..).inspect(move |(handle, _, _)| {
+            timers.retain_parked(|(parked, _)| parked.token != handle.token);
+            free_handles.push(*handle)
+        })
+    }
+
     /// Return iterator over pending endpoints.
     #[inline]
     pub fn pending(&self) -> impl Iterator<Item = (&Handle, &E)> {
         self.pending_endpoints
             .iter()
-            .map(|(handle, _, _, endpoint)| (handle, endpoint))
+            .map(|pending| (&pending.handle, &pending.endpoint))
+    }
+
+    /// Take the handle of a removed endpoint with its generation bumped, or issue a new token.
+    #[inline]
+    fn next_handle(&mut self) -> Handle {
+        match self.free_handles.pop() {
+            Some(handle) => handle.next_generation(),
+            None => Handle::new(self.selector.next_token(), 0),
+        }
+    }
+
+    /// Schedule the endpoint to be reconnected under the next generation of its handle. Remaining
+    /// resolved addresses are tried straight away, otherwise the [`ReconnectPolicy`] decides when
+    /// the next attempt should take place. If the policy gives up the endpoint is returned so that
+    /// it can be removed.
+    fn reschedule(
+        time_source: &TS,
+        reconnect_policy: &mut dyn ReconnectPolicy,
+        handle: Handle,
+        first_generation: u32,
+        endpoint: E,
+        addrs: VecDeque<SocketAddr>,
+        attempt: u32,
+    ) -> Result<PendingEndpoint<D::Query, E>, (Handle, E, DisconnectReason)>
+    where
+        TS: TimeSource,
+    {
+        let now = time_source.current_time_nanos();
+        if !addrs.is_empty() {
+            return Ok(PendingEndpoint {
+                handle: handle.next_generation(),
+                first_generation,
+                resolution: Resolution::Resolved(addrs),
+                endpoint,
+                attempt,
+                next_attempt_ns: now,
+            });
+        }
+        let Some(delay) = reconnect_policy.next_delay(handle, attempt.saturating_add(1)) else {
+            return Err((handle, endpoint, DisconnectReason::reconnect_attempts_exhausted(attempt)));
+        };
+        Ok(PendingEndpoint {
+            handle: handle.next_generation(),
+            first_generation,
+            resolution: Resolution::Unresolved,
+            endpoint,
+            attempt: attempt.saturating_add(1),
+            next_attempt_ns: now.saturating_add(delay.as_nanos() as u64),
+        })
     }
 
     #[inline]
-    fn resolve_dns(&self, query: &mut impl DnsQuery, created_time_ns: u64) -> io::Result<Option<SocketAddr>>
+    fn resolve_dns(&self, query: &mut impl DnsQuery, created_time_ns: u64) -> io::Result<Option<VecDeque<SocketAddr>>>
     where
         TS: TimeSource,
     {
@@ -224,52 +515,540 @@ impl<S: Selector, E, C, TS, D: DnsResolver> IOService<S, E, C, TS, D> {
         }
         match query.poll() {
             Ok(addrs) => {
-                let addr = addrs
-                    .into_iter()
-                    .next()
-                    .ok_or_else(|| io::Error::other("dns resolution dio not return any address"))?;
-                Ok(Some(addr))
+                let addrs: VecDeque<SocketAddr> = match self.happy_eyeballs {
+                    true => interleave_address_families(addrs),
+                    false => addrs.into_iter().collect(),
+                };
+                if addrs.is_empty() {
+                    return Err(io::Error::other("dns resolution did not return any address"));
+                }
+                Ok(Some(addrs))
             }
             Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(None),
             Err(err) => Err(err),
         }
     }
 
+    /// Connect the pending endpoints that are due. Endpoints that fail to resolve or connect are
+    /// passed to [`IOService::retry_or_remove`], so a single failing endpoint never holds up the
+    /// others.
     #[cold]
-    fn check_pending_endpoints<F>(&mut self, create_target: F) -> io::Result<()>
+    fn check_pending_endpoints<L>(&mut self, lifecycle: &mut L)
     where
         E: ConnectionInfoProvider,
         TS: TimeSource,
-        F: FnOnce(&mut E, SocketAddr) -> io::Result<Option<<S as Selector>::Target>>,
+        L: Lifecycle<E, S::Target, C>,
     {
         let current_time_ns = self.time_source.current_time_nanos();
-        if current_time_ns > self.next_endpoint_create_time_ns {
-            if let Some((handle, mut query, query_time_ns, mut endpoint)) = self.pending_endpoints.pop_front() {
-                if let Some(addr) = self.resolve_dns(&mut query, query_time_ns)? {
-                    match create_target(&mut endpoint, addr)? {
-                        Some(stream) => {
-                            let ttl = self.auto_disconnect.as_ref().map(|auto_disconnect| auto_disconnect());
-                            let mut io_node = IONode::new(stream, handle, endpoint, ttl, &self.time_source, addr);
-                            self.selector.register(handle.0, &mut io_node)?;
-                            self.io_nodes.insert(handle.0, io_node);
-                        }
-                        None => {
-                            // request new dns query
-                            let info = endpoint.connection_info();
-                            let query = self.dns_resolver.new_query(info.host(), info.port())?;
-                            let now = self.time_source.current_time_nanos();
-                            self.pending_endpoints.push_back((handle, query, now, endpoint))
-                        }
+        if !self.pending_endpoints.is_due(current_time_ns) {
+            return;
+        }
+        for _ in 0..self.pending_endpoints.begin_check() {
+            // SAFETY: we only pop as many endpoints as there were at the start of the check
+            let mut pending = unsafe { self.pending_endpoints.pop_front().unwrap_unchecked() };
+            if current_time_ns < pending.next_attempt_ns {
+                self.pending_endpoints.push(pending);
+                continue;
+            }
+            let addr = match self.next_address(&mut pending, current_time_ns, lifecycle) {
+                Ok(Some(addr)) => addr,
+                Ok(None) => {
+                    // dns query still in progress
+                    self.pending_endpoints.push(pending);
+                    continue;
+                }
+                Err(err) => {
+                    self.retry_or_remove(pending, DisconnectReason::other(err), lifecycle);
+                    continue;
+                }
+            };
+            let stream = match lifecycle.create_target(&mut pending.endpoint, addr) {
+                Ok(Some(stream)) => stream,
+                Ok(None) => {
+                    // try the next address or schedule new dns query
+                    let PendingEndpoint {
+                        handle,
+                        first_generation,
+                        resolution,
+                        endpoint,
+                        attempt,
+                        ..
+                    } = pending;
+                    match Self::reschedule(
+                        &self.time_source,
+                        self.reconnect_policy.as_mut(),
+                        handle,
+                        first_generation,
+                        endpoint,
+                        resolution.into_addrs(),
+                        attempt,
+                    ) {
+                        Ok(pending) => self.pending_endpoints.push(pending),
+                        Err(removed) => self.removed_endpoints.push(removed),
                     }
-                } else {
-                    self.pending_endpoints
-                        .push_back((handle, query, query_time_ns, endpoint))
+                    continue;
+                }
+                Err(err) => {
+                    self.retry_or_remove(pending, DisconnectReason::other(err), lifecycle);
+                    continue;
+                }
+            };
+            let PendingEndpoint {
+                handle,
+                first_generation,
+                resolution,
+                endpoint,
+                attempt,
+                ..
+            } = pending;
+            let ttl = self.auto_disconnect.as_ref().map(|auto_disconnect| auto_disconnect());
+            let mut io_node = IONode::new(
+                stream,
+                handle,
+                first_generation,
+                endpoint,
+                ttl,
+                &self.time_source,
+                addr,
+                resolution.into_addrs(),
+                attempt,
+            );
+            match self.selector.register(handle.token, &mut io_node) {
+                Ok(()) => {
+                    self.io_nodes.insert(handle.token, io_node);
+                    self.connecting.push(handle.token);
+                }
+                Err(err) => {
+                    let pending = io_node.into_pending(current_time_ns);
+                    self.retry_or_remove(pending, DisconnectReason::other(err), lifecycle);
                 }
             }
-            self.next_endpoint_create_time_ns = current_time_ns + ENDPOINT_CREATION_THROTTLE_NS;
+        }
+    }
+
+    /// Take the next address to connect the pending endpoint to, issuing the DNS query first if
+    /// needed. Returns `None` while the query is still in progress.
+    fn next_address<L>(
+        &self,
+        pending: &mut PendingEndpoint<D::Query, E>,
+        current_time_ns: u64,
+        lifecycle: &mut L,
+    ) -> io::Result<Option<SocketAddr>>
+    where
+        E: ConnectionInfoProvider,
+        TS: TimeSource,
+        L: Lifecycle<E, S::Target, C>,
+    {
+        if let Resolution::Unresolved = pending.resolution {
+            let info = pending.endpoint.connection_info();
+            let query = self.dns_resolver.new_query(info.connect_host(), info.connect_port())?;
+            pending.resolution = Resolution::Query(query, current_time_ns);
+        }
+        if let Resolution::Query(query, query_time_ns) = &mut pending.resolution {
+            match self.resolve_dns(query, *query_time_ns)? {
+                Some(mut addrs) => {
+                    lifecycle.on_dns_resolved(&mut pending.endpoint, addrs.make_contiguous());
+                    pending.resolution = Resolution::Resolved(addrs);
+                }
+                None => return Ok(None),
+            }
+        }
+        match &mut pending.resolution {
+            Resolution::Resolved(addrs) => match addrs.pop_front() {
+                Some(addr) => Ok(Some(addr)),
+                None => Err(io::Error::other("no resolved address left to connect to")),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    /// Check if the connections that have not been established yet are now connected, failed
+    /// or timed out. Failed endpoints will be recreated using the next resolved address.
+    #[inline]
+    fn check_connecting<L>(&mut self, lifecycle: &mut L) -> io::Result<()>
+    where
+        E: ConnectionInfoProvider,
+        TS: TimeSource,
+        L: Lifecycle<E, S::Target, C>,
+    {
+        if self.connecting.is_empty() {
+            return Ok(());
+        }
+        self.check_connecting_slow(lifecycle)
+    }
+
+    #[cold]
+    fn check_connecting_slow<L>(&mut self, lifecycle: &mut L) -> io::Result<()>
+    where
+        E: ConnectionInfoProvider,
+        TS: TimeSource,
+        L: Lifecycle<E, S::Target, C>,
+    {
+        let now = self.time_source.current_time_nanos();
+        let mut index = 0;
+        while index < self.connecting.len() {
+            let token = self.connecting[index];
+            let Some(io_node) = self.io_nodes.get_mut(token) else {
+                // endpoint has been removed in the meantime
+                self.connecting.swap_remove(index);
+                continue;
+            };
+            let err = match io_node.stream.connected() {
+                Ok(true) => {
+                    self.connecting.swap_remove(index);
+                    let (stream, (_, endpoint)) = io_node.as_parts_mut();
+                    match lifecycle.on_connected(endpoint, stream) {
+                        Ok(()) => {
+                            io_node.addrs.clear();
+                            io_node.attempt = 0;
+                            // one-shot timers that have expired while the endpoint was not connected
+                            self.timers.unpark(now, |(handle, _)| io_node.owns(*handle));
+                            if self.ready_only {
+                                self.woken.push(token);
+                            }
+                            continue;
+                        }
+                        Err(err) => err,
+                    }
+                }
+                Ok(false) => match self.connect_timeout_ns {
+                    Some(timeout) if now.saturating_sub(io_node.connect_time_ns) > timeout => {
+                        self.connecting.swap_remove(index);
+                        io::Error::new(ErrorKind::TimedOut, format!("connection to {} timed out", io_node.addr))
+                    }
+                    _ => {
+                        index += 1;
+                        continue;
+                    }
+                },
+                Err(err) => {
+                    self.connecting.swap_remove(index);
+                    err
+                }
+            };
+            // SAFETY: checked above
+            let io_node = unsafe { self.io_nodes.remove(token).unwrap_unchecked() };
+            self.disconnect(io_node, DisconnectReason::other(err), lifecycle)?;
         }
         Ok(())
     }
+
+    /// Fire timers that are due, the callbacks are only invoked for endpoints with established
+    /// connection. Should the callback fail the endpoint is disconnected.
+    #[inline]
+    fn check_timers<L>(&mut self, lifecycle: &mut L) -> io::Result<()>
+    where
+        E: ConnectionInfoProvider,
+        TS: TimeSource,
+        L: Lifecycle<E, S::Target, C>,
+    {
+        if self.timers.is_empty() {
+            return Ok(());
+        }
+        let now = self.time_source.current_time_nanos();
+        if !self.timers.is_due(now) {
+            return Ok(());
+        }
+        self.check_timers_slow(now, lifecycle)
+    }
+
+    #[cold]
+    fn check_timers_slow<L>(&mut self, now: u64, lifecycle: &mut L) -> io::Result<()>
+    where
+        E: ConnectionInfoProvider,
+        TS: TimeSource,
+        L: Lifecycle<E, S::Target, C>,
+    {
+        let mut expired = std::mem::take(&mut self.expired_timers);
+        self.timers.expire(now, &mut expired);
+        let mut result = Ok(());
+        for mut timer in expired.drain(..) {
+            let handle = timer.payload().0;
+            let active = self
+                .io_nodes
+                .get(handle.token)
+                .is_some_and(|io_node| io_node.owns(handle));
+            let connected = active && !self.connecting.contains(&handle.token);
+            if !connected {
+                if active || self.pending_endpoints.contains(handle) {
+                    // one-shot timers wait for the connection while periodic ones skip this period
+                    if timer.is_periodic() {
+                        self.timers.rearm(timer, now);
+                    } else {
+                        self.timers.park(timer);
+                    }
+                }
+                continue;
+            }
+            // SAFETY: checked above
+            let io_node = unsafe { self.io_nodes.get_mut(handle.token).unwrap_unchecked() };
+            let (stream, (_, endpoint)) = io_node.as_parts_mut();
+            let outcome = lifecycle.on_timer(&mut timer.payload_mut().1, stream, endpoint);
+            self.timers.rearm(timer, now);
+            if self.ready_only && outcome.is_ok() {
+                self.woken.push(handle.token);
+            }
+            if let Err(err) = outcome {
+                // SAFETY: checked above
+                let io_node = unsafe { self.io_nodes.remove(handle.token).unwrap_unchecked() };
+                if let Err(err) = self.disconnect(io_node, DisconnectReason::other(err), lifecycle) {
+                    result = Err(err);
+                }
+            }
+        }
+        self.expired_timers = expired;
+        result
+    }
+
+    /// Deliver the commands received from other threads, endpoints for which the command handler
+    /// has failed are disconnected.
+    #[inline]
+    fn check_commands<L>(&mut self, lifecycle: &mut L) -> io::Result<()>
+    where
+        E: ConnectionInfoProvider,
+        TS: TimeSource,
+        L: Lifecycle<E, S::Target, C>,
+    {
+        let Some(commands) = self.commands.as_mut() else {
+            return Ok(());
+        };
+        let woken = self.ready_only.then_some(&mut self.woken);
+        let pending_endpoints = &self.pending_endpoints;
+        lifecycle.on_commands(
+            commands.as_mut(),
+            &mut self.io_nodes,
+            &mut self.failed_commands,
+            woken,
+            &self.connecting,
+            &|handle| pending_endpoints.contains(handle),
+        );
+        if self.failed_commands.is_empty() {
+            return Ok(());
+        }
+        let mut result = Ok(());
+        let mut failed = std::mem::take(&mut self.failed_commands);
+        for (token, err) in failed.drain(..) {
+            if let Some(io_node) = self.io_nodes.remove(token) {
+                if let Err(err) = self.disconnect(io_node, DisconnectReason::other(err), lifecycle) {
+                    result = Err(err);
+                }
+            }
+        }
+        self.failed_commands = failed;
+        result
+    }
+
+    /// Invoke the `action` for the endpoints reported as ready by the selector together with the
+    /// endpoints that have been woken up since the last poll. Should the action fail the endpoint
+    /// is disconnected.
+    fn poll_ready<L, F>(&mut self, lifecycle: &mut L, mut action: F) -> io::Result<()>
+    where
+        E: ConnectionInfoProvider,
+        TS: TimeSource,
+        L: Lifecycle<E, S::Target, C>,
+        F: FnMut(&mut L, &mut S::Target, &mut E) -> io::Result<()>,
+    {
+        let mut ready = std::mem::take(&mut self.ready);
+        ready.extend_from_slice(self.selector.ready().unwrap_or_default());
+        ready.append(&mut self.woken);
+        ready.sort_unstable();
+        ready.dedup();
+        let mut result = Ok(());
+        for token in ready.drain(..) {
+            let Some(io_node) = self.io_nodes.get_mut(token) else {
+                // endpoint has been removed in the meantime
+                continue;
+            };
+            let (target, (_, endpoint)) = io_node.as_parts_mut();
+            match action(lifecycle, target, endpoint) {
+                Ok(()) => {
+                    if target.has_pending_io() {
+                        self.woken.push(token);
+                    }
+                }
+                Err(err) => {
+                    // SAFETY: checked above
+                    let io_node = unsafe { self.io_nodes.remove(token).unwrap_unchecked() };
+                    if let Err(err) = self.disconnect(io_node, DisconnectReason::other(err), lifecycle) {
+                        result = Err(err);
+                    }
+                }
+            }
+        }
+        self.ready = ready;
+        result
+    }
+
+    /// Invoke the `action` for every active endpoint. Should the action fail the endpoint is disconnected.
+    #[inline]
+    fn poll_all<L, F>(&mut self, lifecycle: &mut L, mut action: F) -> io::Result<()>
+    where
+        E: ConnectionInfoProvider,
+        TS: TimeSource,
+        L: Lifecycle<E, S::Target, C>,
+        F: FnMut(&mut L, &mut S::Target, &mut E) -> io::Result<()>,
+    {
+        let mut result = Ok(());
+        for token in 0..self.io_nodes.token_bound() {
+            let Some(io_node) = self.io_nodes.get_mut(token) else {
+                continue;
+            };
+            let (target, (_, endpoint)) = io_node.as_parts_mut();
+            if let Err(err) = action(lifecycle, target, endpoint) {
+                // SAFETY: checked above
+                let io_node = unsafe { self.io_nodes.remove(token).unwrap_unchecked() };
+                if let Err(err) = self.disconnect(io_node, DisconnectReason::other(err), lifecycle) {
+                    result = Err(err);
+                }
+            }
+        }
+        result
+    }
+
+    /// Disconnect the endpoints that have outlived their TTL, unless the endpoint refuses in which
+    /// case the TTL is extended.
+    #[inline]
+    fn check_auto_disconnect<L>(&mut self, lifecycle: &mut L) -> io::Result<()>
+    where
+        E: ConnectionInfoProvider,
+        TS: TimeSource,
+        L: Lifecycle<E, S::Target, C>,
+    {
+        if self.auto_disconnect.is_none() {
+            return Ok(());
+        }
+        let current_time_ns = self.time_source.current_time_nanos();
+        let mut result = Ok(());
+        for token in 0..self.io_nodes.token_bound() {
+            let Some(io_node) = self.io_nodes.get_mut(token) else {
+                continue;
+            };
+            if current_time_ns <= io_node.disconnect_time_ns {
+                continue;
+            }
+            // check if we really have to disconnect
+            if !lifecycle.can_auto_disconnect(&mut io_node.as_endpoint_mut().1) {
+                // extend the endpoint TTL
+                let extend = self.auto_disconnect.as_ref().map_or(0, |ttl| ttl().as_nanos() as u64);
+                io_node.disconnect_time_ns = io_node.disconnect_time_ns.saturating_add(extend);
+                continue;
+            }
+            let reason = DisconnectReason::auto_disconnect(io_node.ttl);
+            // SAFETY: checked above
+            let mut io_node = unsafe { self.io_nodes.remove(token).unwrap_unchecked() };
+            // resolve the endpoint again rather than trying the remaining addresses
+            io_node.addrs.clear();
+            if let Err(err) = self.disconnect(io_node, reason, lifecycle) {
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    /// Invoke the `action` with the active endpoint owning the `handle`, with `current` the handle
+    /// generation has to match the current connection of the endpoint.
+    #[inline]
+    fn dispatch_to<F, T>(&mut self, handle: Handle, current: bool, mut action: F) -> io::Result<Option<T>>
+    where
+        F: FnMut(&mut S::Target, &mut E) -> io::Result<T>,
+    {
+        let Some(io_node) = self.io_nodes.get_mut(handle.token) else {
+            return Ok(None);
+        };
+        let issued = match current {
+            true => io_node.as_endpoint().0 == handle,
+            false => io_node.owns(handle),
+        };
+        if !issued {
+            return Ok(None);
+        }
+        let (stream, (_, endpoint)) = io_node.as_parts_mut();
+        let result = action(stream, endpoint)?;
+        if self.ready_only && stream.has_pending_io() {
+            self.woken.push(handle.token);
+        }
+        Ok(Some(result))
+    }
+
+    /// Remove the connection and either schedule the endpoint to be reconnected or, if it cannot
+    /// be recreated, move it to the removed endpoints.
+    fn disconnect<L>(
+        &mut self,
+        mut io_node: IONode<S::Target, E>,
+        reason: DisconnectReason,
+        lifecycle: &mut L,
+    ) -> io::Result<()>
+    where
+        E: ConnectionInfoProvider,
+        TS: TimeSource,
+        L: Lifecycle<E, S::Target, C>,
+    {
+        let unregistered = self.selector.unregister(&mut io_node);
+        // the endpoint may be disconnected before the connection has been established
+        let token = io_node.as_endpoint().0.token;
+        if let Some(index) = self.connecting.iter().position(|connecting| *connecting == token) {
+            self.connecting.swap_remove(index);
+        }
+        let now = self.time_source.current_time_nanos();
+        self.retry_or_remove(io_node.into_pending(now), reason, lifecycle);
+        unregistered
+    }
+
+    /// Notify the endpoint that it has been disconnected (or could not be connected) and either
+    /// schedule it to be reconnected, trying the remaining resolved addresses first, or move it to
+    /// the removed endpoints.
+    fn retry_or_remove<L>(&mut self, pending: PendingEndpoint<D::Query, E>, reason: DisconnectReason, lifecycle: &mut L)
+    where
+        E: ConnectionInfoProvider,
+        TS: TimeSource,
+        L: Lifecycle<E, S::Target, C>,
+    {
+        let PendingEndpoint {
+            handle,
+            first_generation,
+            resolution,
+            mut endpoint,
+            attempt,
+            ..
+        } = pending;
+        lifecycle.on_disconnected(&mut endpoint, &reason);
+        if lifecycle.can_recreate(&mut endpoint, &reason) {
+            match Self::reschedule(
+                &self.time_source,
+                self.reconnect_policy.as_mut(),
+                handle,
+                first_generation,
+                endpoint,
+                resolution.into_addrs(),
+                attempt,
+            ) {
+                Ok(pending) => self.pending_endpoints.push(pending),
+                Err(removed) => self.removed_endpoints.push(removed),
+            }
+        } else {
+            self.removed_endpoints.push((handle, endpoint, reason));
+        }
+    }
+}
+
+/// Alternate between address families starting with the family of the first address.
+fn interleave_address_families(addrs: impl IntoIterator<Item = SocketAddr>) -> VecDeque<SocketAddr> {
+    let mut addrs = addrs.into_iter().peekable();
+    let Some(first_is_ipv6) = addrs.peek().map(SocketAddr::is_ipv6) else {
+        return VecDeque::new();
+    };
+    let (mut preferred, mut other): (VecDeque<_>, VecDeque<_>) =
+        addrs.partition(|addr| addr.is_ipv6() == first_is_ipv6);
+    let mut interleaved = VecDeque::with_capacity(preferred.len() + other.len());
+    loop {
+        match (preferred.pop_front(), other.pop_front()) {
+            (None, None) => return interleaved,
+            (first, second) => {
+                interleaved.extend(first);
+                interleaved.extend(second);
+            }
+        }
+    }
 }
 
 impl<S, E, TS, D> IOService<S, E, (), TS, D>
@@ -288,81 +1067,133 @@ where
     where
         F: FnMut(&mut E::Target, &mut E) -> io::Result<()>,
     {
-        // check for pending endpoints (one at a time & throttled)
+        // check for pending endpoints that are due to connect
         if !self.pending_endpoints.is_empty() {
-            self.check_pending_endpoints(|endpoint, addr| endpoint.create_target(addr))?;
+            self.check_pending_endpoints(&mut NoContext);
         }
 
         // check for readiness events
         self.selector.poll(&mut self.io_nodes)?;
 
+        // check for connection establishment and timeout
+        self.check_connecting(&mut NoContext)?;
+
+        // fire timers that are due
+        self.check_timers(&mut NoContext)?;
+
+        // deliver commands sent from other threads
+        self.check_commands(&mut NoContext)?;
+
         // check for auto disconnect if enabled
-        if let Some(auto_disconnect) = self.auto_disconnect.as_ref() {
-            let current_time_ns = self.time_source.current_time_nanos();
-            self.io_nodes.retain(|_token, io_node| {
-                let force_disconnect = current_time_ns > io_node.disconnect_time_ns;
-                if force_disconnect {
-                    // check if we really have to disconnect
-                    return if io_node.as_endpoint_mut().1.can_auto_disconnect() {
-                        self.selector.unregister(io_node).unwrap();
-                        let (handle, mut endpoint) = io_node.endpoint.take().unwrap();
-                        if endpoint.can_recreate(DisconnectReason::auto_disconnect(io_node.ttl)) {
-                            let info = endpoint.connection_info();
-                            let query = self.dns_resolver.new_query(info.host(), info.port()).unwrap();
-                            let now = self.time_source.current_time_nanos();
-                            self.pending_endpoints.push_back((handle, query, now, endpoint));
-                        } else {
-                            panic!("unrecoverable error when polling endpoint");
-                        }
-                        false
-                    } else {
-                        // extend the endpoint TTL
-                        let extend = auto_disconnect().as_nanos() as u64;
-                        io_node.disconnect_time_ns = io_node.disconnect_time_ns.saturating_add(extend);
-                        true
-                    };
-                }
-                true
-            });
+        self.check_auto_disconnect(&mut NoContext)?;
+
+        // poll only the endpoints that are ready
+        if self.ready_only {
+            return self.poll_ready(&mut NoContext, |_, target, endpoint| action(target, endpoint));
         }
 
         // poll endpoints
-        self.io_nodes.retain(|_token, io_node| {
-            let (target, (_, endpoint)) = io_node.as_parts_mut();
-            if let Err(err) = action(target, endpoint) {
-                self.selector.unregister(io_node).unwrap();
-                let (handle, mut endpoint) = io_node.endpoint.take().unwrap();
-                if endpoint.can_recreate(DisconnectReason::other(err)) {
-                    let info = endpoint.connection_info();
-                    let query = self.dns_resolver.new_query(info.host(), info.port()).unwrap();
-                    let now = self.time_source.current_time_nanos();
-                    self.pending_endpoints.push_back((handle, query, now, endpoint));
-                } else {
-                    panic!("unrecoverable error when polling endpoint");
-                }
-                return false;
-            }
-            true
-        });
+        self.poll_all(&mut NoContext, |_, target, endpoint| action(target, endpoint))
+    }
 
-        Ok(())
+    /// Deliver commands sent through the [`CommandSender`](command::CommandSender) paired with the
+    /// `receiver`. The queue is drained on every `poll` and each command is passed to the `handler`
+    /// together with the endpoint identified by the command handle. Commands for endpoints that
+    /// are connecting or reconnecting are held until the connection has been established, while
+    /// commands for endpoints that have been removed are dropped (see
+    /// [`IOService::with_commands_and_undelivered`]). Returning error from the handler will
+    /// disconnect the endpoint the same way as failing inside `poll` does.
+    pub fn with_commands<T, F>(self, receiver: CommandReceiver<T>, handler: F) -> IOService<S, E, (), TS, D>
+    where
+        T: 'static,
+        F: FnMut(&mut E::Target, &mut E, T) -> io::Result<()> + 'static,
+    {
+        self.with_commands_and_undelivered(receiver, handler, |_, _| {})
+    }
+
+    /// Same as [`IOService::with_commands`] but the commands that cannot be delivered, because the
+    /// endpoint identified by the command handle no longer exists, are passed to `undelivered`.
+    pub fn with_commands_and_undelivered<T, F, U>(
+        self,
+        receiver: CommandReceiver<T>,
+        mut handler: F,
+        undelivered: U,
+    ) -> IOService<S, E, (), TS, D>
+    where
+        T: 'static,
+        F: FnMut(&mut E::Target, &mut E, T) -> io::Result<()> + 'static,
+        U: FnMut(Handle, T) + 'static,
+    {
+        let handler =
+            move |target: &mut E::Target, endpoint: &mut E, _: &mut (), command: T| handler(target, endpoint, command);
+        Self {
+            commands: Some(Box::new(CommandHandler::new(receiver, handler, undelivered))),
+            ..self
+        }
+    }
+
+    /// Schedule `callback` to be invoked once with the endpoint identified by `handle` after the
+    /// `delay`. If the endpoint is not connected at that time the callback is invoked as soon as
+    /// the connection has been established. Returning error from the callback will disconnect the
+    /// endpoint the same way as failing inside `poll` does.
+    pub fn schedule_once<F>(&mut self, handle: Handle, delay: Duration, callback: F) -> TimerId
+    where
+        F: FnOnce(&mut E::Target, &mut E) -> io::Result<()> + 'static,
+    {
+        let mut callback = Some(callback);
+        let deadline_ns = self
+            .time_source
+            .current_time_nanos()
+            .saturating_add(delay.as_nanos() as u64);
+        self.timers.schedule(
+            deadline_ns,
+            (
+                handle,
+                Box::new(move |target, endpoint, _| {
+                    callback.take().map_or(Ok(()), |callback| callback(target, endpoint))
+                }),
+            ),
+        )
+    }
+
+    /// Schedule `callback` to be invoked with the endpoint identified by `handle` every `period`,
+    /// until the timer is cancelled or the endpoint removed. Periods during which the endpoint is
+    /// not connected are skipped. Returning error from the callback will disconnect the endpoint
+    /// the same way as failing inside `poll` does.
+    pub fn schedule_periodic<F>(&mut self, handle: Handle, period: Duration, mut callback: F) -> TimerId
+    where
+        F: FnMut(&mut E::Target, &mut E) -> io::Result<()> + 'static,
+    {
+        let deadline_ns = self
+            .time_source
+            .current_time_nanos()
+            .saturating_add(period.as_nanos() as u64);
+        self.timers.schedule_periodic(
+            deadline_ns,
+            period,
+            (handle, Box::new(move |target, endpoint, _| callback(target, endpoint))),
+        )
     }
 
     /// Dispatch command to an active endpoint using `handle` and provided `action`. If the
     /// endpoint is currently active `Ok(Some(...))` will be returned and the provided `action` invoked,
-    /// otherwise this method will return `Ok(None)` and no `action` will be invoked.
-    pub fn dispatch<F, T>(&mut self, handle: Handle, mut action: F) -> io::Result<Option<T>>
+    /// otherwise this method will return `Ok(None)` and no `action` will be invoked. Handles of any
+    /// generation issued for the endpoint can be used.
+    pub fn dispatch<F, T>(&mut self, handle: Handle, action: F) -> io::Result<Option<T>>
     where
         F: FnMut(&mut E::Target, &mut E) -> std::io::Result<T>,
     {
-        match self.io_nodes.get_mut(&handle.0) {
-            Some(io_node) => {
-                let (stream, (_, endpoint)) = io_node.as_parts_mut();
-                let result = action(stream, endpoint)?;
-                Ok(Some(result))
-            }
-            None => Ok(None),
-        }
+        self.dispatch_to(handle, false, action)
+    }
+
+    /// Dispatch command to an active endpoint the same way as [`IOService::dispatch`] does, but
+    /// only if the `handle` generation matches the current connection of the endpoint. Stale
+    /// handles issued before the endpoint has been recreated result in `Ok(None)`.
+    pub fn dispatch_current<F, T>(&mut self, handle: Handle, action: F) -> io::Result<Option<T>>
+    where
+        F: FnMut(&mut E::Target, &mut E) -> std::io::Result<T>,
+    {
+        self.dispatch_to(handle, true, action)
     }
 }
 
@@ -383,81 +1214,1043 @@ where
     where
         F: FnMut(&mut E::Target, &mut C, &mut E) -> io::Result<()>,
     {
-        // check for pending endpoints (one at a time & throttled)
+        // check for pending endpoints that are due to connect
         if !self.pending_endpoints.is_empty() {
-            self.check_pending_endpoints(|endpoint, addr| endpoint.create_target(addr, ctx))?;
+            self.check_pending_endpoints(&mut WithContext(ctx));
         }
 
         // check for readiness events
         self.selector.poll(&mut self.io_nodes)?;
 
+        // check for connection establishment and timeout
+        self.check_connecting(&mut WithContext(ctx))?;
+
+        // fire timers that are due
+        self.check_timers(&mut WithContext(ctx))?;
+
+        // deliver commands sent from other threads
+        self.check_commands(&mut WithContext(ctx))?;
+
         // check for auto disconnect if enabled
-        if let Some(auto_disconnect) = self.auto_disconnect.as_ref() {
-            let current_time_ns = self.time_source.current_time_nanos();
-            self.io_nodes.retain(|_token, io_node| {
-                let force_disconnect = current_time_ns > io_node.disconnect_time_ns;
-                if force_disconnect {
-                    // check if we really have to disconnect
-                    return if io_node.as_endpoint_mut().1.can_auto_disconnect(ctx) {
-                        self.selector.unregister(io_node).unwrap();
-                        let (handle, mut endpoint) = io_node.endpoint.take().unwrap();
-                        if endpoint.can_recreate(DisconnectReason::auto_disconnect(io_node.ttl), ctx) {
-                            let info = endpoint.connection_info();
-                            let query = self.dns_resolver.new_query(info.host(), info.port()).unwrap();
-                            let now = self.time_source.current_time_nanos();
-                            self.pending_endpoints.push_back((handle, query, now, endpoint));
-                        } else {
-                            panic!("unrecoverable error when polling endpoint");
-                        }
-                        false
-                    } else {
-                        // extend the endpoint TTL
-                        let extend = auto_disconnect().as_nanos() as u64;
-                        io_node.disconnect_time_ns = io_node.disconnect_time_ns.saturating_add(extend);
-                        true
-                    };
-                }
-                true
+        self.check_auto_disconnect(&mut WithContext(ctx))?;
+
+        // poll only the endpoints that are ready
+        if self.ready_only {
+            return self.poll_ready(&mut WithContext(ctx), |lifecycle: &mut WithContext<C>, target, endpoint| {
+                action(target, lifecycle.0, endpoint)
             });
         }
 
         // poll endpoints
-        self.io_nodes.retain(|_token, io_node| {
-            let (target, (_, endpoint)) = io_node.as_parts_mut();
-            if let Err(err) = action(target, ctx, endpoint) {
-                self.selector.unregister(io_node).unwrap();
-                let (handle, mut endpoint) = io_node.endpoint.take().unwrap();
-                if endpoint.can_recreate(DisconnectReason::other(err), ctx) {
-                    let info = endpoint.connection_info();
-                    let query = self.dns_resolver.new_query(info.host(), info.port()).unwrap();
-                    let now = self.time_source.current_time_nanos();
-                    self.pending_endpoints.push_back((handle, query, now, endpoint));
-                } else {
-                    panic!("unrecoverable error when polling endpoint");
-                }
-                return false;
-            }
-            true
-        });
+        self.poll_all(&mut WithContext(ctx), |lifecycle: &mut WithContext<C>, target, endpoint| {
+            action(target, lifecycle.0, endpoint)
+        })
+    }
 
-        Ok(())
+    /// Deliver commands sent through the [`CommandSender`](command::CommandSender) paired with the
+    /// `receiver`. The queue is drained on every `poll` and each command is passed to the `handler`
+    /// together with the endpoint identified by the command handle and the [`Context`]. Commands
+    /// for endpoints that are connecting or reconnecting are held until the connection has been
+    /// established, while commands for endpoints that have been removed are dropped (see
+    /// [`IOService::with_commands_and_undelivered`]). Returning error from the handler will
+    /// disconnect the endpoint the same way as failing inside `poll` does.
+    pub fn with_commands<T, F>(self, receiver: CommandReceiver<T>, handler: F) -> IOService<S, E, C, TS, D>
+    where
+        T: 'static,
+        F: FnMut(&mut E::Target, &mut C, &mut E, T) -> io::Result<()> + 'static,
+    {
+        self.with_commands_and_undelivered(receiver, handler, |_, _| {})
+    }
+
+    /// Same as [`IOService::with_commands`] but the commands that cannot be delivered, because the
+    /// endpoint identified by the command handle no longer exists, are passed to `undelivered`.
+    pub fn with_commands_and_undelivered<T, F, U>(
+        self,
+        receiver: CommandReceiver<T>,
+        mut handler: F,
+        undelivered: U,
+    ) -> IOService<S, E, C, TS, D>
+    where
+        T: 'static,
+        F: FnMut(&mut E::Target, &mut C, &mut E, T) -> io::Result<()> + 'static,
+        U: FnMut(Handle, T) + 'static,
+    {
+        let handler = move |target: &mut E::Target, endpoint: &mut E, ctx: &mut C, command: T| {
+            handler(target, ctx, endpoint, command)
+        };
+        Self {
+            commands: Some(Box::new(CommandHandler::new(receiver, handler, undelivered))),
+            ..self
+        }
+    }
+
+    /// Schedule `callback` to be invoked once with the endpoint identified by `handle` and the
+    /// [`Context`] after the `delay`. If the endpoint is not connected at that time the callback is
+    /// invoked as soon as the connection has been established. Returning error from the callback
+    /// will disconnect the endpoint the same way as failing inside `poll` does.
+    pub fn schedule_once<F>(&mut self, handle: Handle, delay: Duration, callback: F) -> TimerId
+    where
+        F: FnOnce(&mut E::Target, &mut C, &mut E) -> io::Result<()> + 'static,
+    {
+        let mut callback = Some(callback);
+        let deadline_ns = self
+            .time_source
+            .current_time_nanos()
+            .saturating_add(delay.as_nanos() as u64);
+        self.timers.schedule(
+            deadline_ns,
+            (
+                handle,
+                Box::new(move |target, endpoint, ctx| {
+                    callback
+                        .take()
+                        .map_or(Ok(()), |callback| callback(target, ctx, endpoint))
+                }),
+            ),
+        )
+    }
+
+    /// Schedule `callback` to be invoked with the endpoint identified by `handle` and the
+    /// [`Context`] every `period`, until the timer is cancelled or the endpoint removed. Periods
+    /// during which the endpoint is not connected are skipped. Returning error from the callback
+    /// will disconnect the endpoint the same way as failing inside `poll` does.
+    pub fn schedule_periodic<F>(&mut self, handle: Handle, period: Duration, mut callback: F) -> TimerId
+    where
+        F: FnMut(&mut E::Target, &mut C, &mut E) -> io::Result<()> + 'static,
+    {
+        let deadline_ns = self
+            .time_source
+            .current_time_nanos()
+            .saturating_add(period.as_nanos() as u64);
+        self.timers.schedule_periodic(
+            deadline_ns,
+            period,
+            (handle, Box::new(move |target, endpoint, ctx| callback(target, ctx, endpoint))),
+        )
     }
 
     /// Dispatch command to an active endpoint using `handle` and provided `action`. If the
     /// endpoint is currently active `Ok(Some(...))` will be returned and the provided `action` invoked,
     /// otherwise this method will return `Ok(None)` and no `action` will be invoked. This method
-    /// requires `Context` to be passed and exposes it to the provided `action`.
+    /// requires `Context` to be passed and exposes it to the provided `action`. Handles of any
+    /// generation issued for the endpoint can be used.
     pub fn dispatch<F, T>(&mut self, handle: Handle, ctx: &mut C, mut action: F) -> io::Result<Option<T>>
     where
         F: FnMut(&mut E::Target, &mut E, &mut C) -> std::io::Result<T>,
     {
-        match self.io_nodes.get_mut(&handle.0) {
-            Some(io_node) => {
-                let (stream, (_, endpoint)) = io_node.as_parts_mut();
-                let result = action(stream, endpoint, ctx)?;
-                Ok(Some(result))
+        self.dispatch_to(handle, false, |stream, endpoint| action(stream, endpoint, ctx))
+    }
+
+    /// Dispatch command to an active endpoint the same way as [`IOService::dispatch`] does, but
+    /// only if the `handle` generation matches the current connection of the endpoint. Stale
+    /// handles issued before the endpoint has been recreated result in `Ok(None)`.
+    pub fn dispatch_current<F, T>(&mut self, handle: Handle, ctx: &mut C, mut action: F) -> io::Result<Option<T>>
+    where
+        F: FnMut(&mut E::Target, &mut E, &mut C) -> std::io::Result<T>,
+    {
+        self.dispatch_to(handle, true, |stream, endpoint| action(stream, endpoint, ctx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::reconnect::ExponentialBackoff;
+    use crate::service::select::direct::DirectSelector;
+    use crate::stream::{ConnectionInfo, ConnectionInfoProvider};
+    use crate::testing::{ManualTimeSource, MockStream, StaticDnsQuery, StaticDnsResolver};
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    /// Resolver that cannot create the query for the "invalid" host and fails to resolve the "bad" one.
+    struct FailingDnsResolver(Vec<SocketAddr>);
+
+    impl DnsResolver for FailingDnsResolver {
+        type Query = io::Result<StaticDnsQuery>;
+
+        fn new_query(&self, host: impl AsRef<str>, _port: u16) -> io::Result<Self::Query> {
+            match host.as_ref() {
+                "invalid" => Err(io::Error::new(ErrorKind::InvalidInput, "invalid host")),
+                "bad" => Ok(Err(io::Error::new(ErrorKind::NotFound, "nxdomain"))),
+                _ => Ok(Ok(StaticDnsQuery(self.0.clone()))),
+            }
+        }
+    }
+
+    impl DnsQuery for io::Result<StaticDnsQuery> {
+        fn poll(&mut self) -> io::Result<impl IntoIterator<Item = SocketAddr>> {
+            match self {
+                Ok(query) => Ok(query.0.clone()),
+                Err(err) => Err(io::Error::new(err.kind(), err.to_string())),
             }
-            None => Ok(None),
         }
     }
+
+    /// Selector that reports the tokens set by the test as ready.
+    #[derive(Default)]
+    struct ReadySelector {
+        next_ready: Rc<RefCell<Vec<SelectorToken>>>,
+        ready: Vec<SelectorToken>,
+        next_token: SelectorToken,
+    }
+
+    impl Selector for ReadySelector {
+        type Target = MockStream;
+
+        fn register<E>(&mut self, _token: SelectorToken, _io_node: &mut IONode<Self::Target, E>) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn unregister<E>(&mut self, _io_node: &mut IONode<Self::Target, E>) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn poll<E>(&mut self, _io_nodes: &mut NodeSlab<Self::Target, E>) -> io::Result<()> {
+            self.ready = std::mem::take(&mut *self.next_ready.borrow_mut());
+            Ok(())
+        }
+
+        fn next_token(&mut self) -> SelectorToken {
+            self.next_token += 1;
+            self.next_token
+        }
+
+        fn ready(&self) -> Option<&[SelectorToken]> {
+            Some(&self.ready)
+        }
+    }
+
+    struct MockEndpoint {
+        connection_info: ConnectionInfo,
+        reachable: SocketAddr,
+        attempts: Rc<RefCell<Vec<SocketAddr>>>,
+        recreate: bool,
+    }
+
+    impl ConnectionInfoProvider for MockEndpoint {
+        fn connection_info(&self) -> &ConnectionInfo {
+            &self.connection_info
+        }
+    }
+
+    impl Endpoint for MockEndpoint {
+        type Target = MockStream;
+
+        fn create_target(&mut self, addr: SocketAddr) -> io::Result<Option<Self::Target>> {
+            self.attempts.borrow_mut().push(addr);
+            Ok(Some(MockStream::connecting(addr, self.reachable)))
+        }
+
+        fn can_recreate(&mut self, _reason: &DisconnectReason) -> bool {
+            self.recreate
+        }
+    }
+
+    #[test]
+    fn should_try_next_address_when_connect_times_out() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap(), "10.0.0.2:80".parse().unwrap()];
+        let time_source = ManualTimeSource::default();
+        let attempts = Rc::new(RefCell::new(Vec::new()));
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_time_source(time_source.clone())
+            .with_dns_resolver(StaticDnsResolver(addrs.clone()))
+            .with_connect_timeout(Duration::from_secs(5));
+
+        io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 80),
+                reachable: addrs[1],
+                attempts: attempts.clone(),
+                recreate: true,
+            })
+            .unwrap();
+
+        time_source.advance(Duration::from_secs(1));
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(vec![addrs[0]], *attempts.borrow());
+        assert_eq!(1, io_service.iter().count());
+
+        // first address never connects
+        time_source.advance(Duration::from_secs(6));
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(0, io_service.iter().count());
+        assert_eq!(1, io_service.pending().count());
+
+        // second address is tried without going back to dns
+        time_source.advance(Duration::from_secs(1));
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(vec![addrs[0], addrs[1]], *attempts.borrow());
+        assert_eq!(1, io_service.iter().count());
+
+        // connected, remaining addresses are no longer relevant
+        time_source.advance(Duration::from_secs(10));
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(1, io_service.iter().count());
+        assert!(io_service.connecting.is_empty());
+    }
+
+    #[test]
+    fn should_go_back_to_dns_once_all_addresses_failed() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap()];
+        let time_source = ManualTimeSource::default();
+        let attempts = Rc::new(RefCell::new(Vec::new()));
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_time_source(time_source.clone())
+            .with_dns_resolver(StaticDnsResolver(addrs.clone()))
+            .with_connect_timeout(Duration::from_secs(5));
+
+        io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 80),
+                reachable: "10.0.0.2:80".parse().unwrap(),
+                attempts: attempts.clone(),
+                recreate: true,
+            })
+            .unwrap();
+
+        for _ in 0..2 {
+            time_source.advance(Duration::from_secs(1));
+            io_service.poll(|_, _| Ok(())).unwrap();
+            time_source.advance(Duration::from_secs(6));
+            io_service.poll(|_, _| Ok(())).unwrap();
+        }
+        assert_eq!(vec![addrs[0], addrs[0]], *attempts.borrow());
+        assert!(matches!(
+            io_service.pending_endpoints.front().map(|pending| &pending.resolution),
+            Some(Resolution::Unresolved)
+        ));
+    }
+
+    #[test]
+    fn should_apply_reconnect_policy_per_endpoint() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap()];
+        let time_source = ManualTimeSource::default();
+        let healthy_attempts = Rc::new(RefCell::new(Vec::new()));
+        let flapping_attempts = Rc::new(RefCell::new(Vec::new()));
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_time_source(time_source.clone())
+            .with_dns_resolver(StaticDnsResolver(addrs.clone()))
+            .with_connect_timeout(Duration::from_secs(1))
+            .with_reconnect_policy(
+                ExponentialBackoff::new(Duration::from_secs(1), Duration::from_secs(60)).with_max_attempts(10),
+            );
+
+        let flapping = io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 80),
+                reachable: "10.0.0.2:80".parse().unwrap(),
+                attempts: flapping_attempts.clone(),
+                recreate: true,
+            })
+            .unwrap();
+        let healthy = io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 80),
+                reachable: addrs[0],
+                attempts: healthy_attempts.clone(),
+                recreate: true,
+            })
+            .unwrap();
+
+        // both endpoints are connected without waiting for each other
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(1, healthy_attempts.borrow().len());
+        assert_eq!(1, flapping_attempts.borrow().len());
+
+        // flapping endpoint backs off exponentially (1s, 2s, 4s) after each timeout
+        let mut attempt_times = vec![];
+        for second in 1..=12 {
+            time_source.advance(Duration::from_millis(1001));
+            let before = flapping_attempts.borrow().len();
+            io_service.poll(|_, _| Ok(())).unwrap();
+            if flapping_attempts.borrow().len() > before {
+                attempt_times.push(second);
+            }
+        }
+        assert_eq!(vec![2, 5, 10], attempt_times);
+        assert_eq!(vec![healthy], io_service.iter().map(|(handle, ..)| handle).collect::<Vec<_>>());
+        assert_eq!(1, healthy_attempts.borrow().len());
+        assert!(
+            io_service
+                .pending()
+                .any(|(handle, _)| handle.token() == flapping.token())
+        );
+    }
+
+    #[test]
+    fn should_not_hold_up_other_endpoints_when_dns_fails() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap()];
+        let time_source = ManualTimeSource::default();
+        let bad_attempts = Rc::new(RefCell::new(Vec::new()));
+        let good_attempts = Rc::new(RefCell::new(Vec::new()));
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_time_source(time_source.clone())
+            .with_dns_resolver(FailingDnsResolver(addrs.clone()));
+        let endpoint = |host: &str, attempts: &Rc<RefCell<Vec<SocketAddr>>>| MockEndpoint {
+            connection_info: ConnectionInfo::new(host, 80),
+            reachable: addrs[0],
+            attempts: attempts.clone(),
+            recreate: false,
+        };
+
+        // failure to create the query is reported straight away
+        let err = io_service.register(endpoint("invalid", &bad_attempts)).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        let bad = io_service.register(endpoint("bad", &bad_attempts)).unwrap();
+        let good = io_service.register(endpoint("good", &good_attempts)).unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(vec![addrs[0]], *good_attempts.borrow());
+        assert_eq!(vec![good], io_service.iter().map(|(handle, ..)| handle).collect::<Vec<_>>());
+
+        // endpoint that failed to resolve is removed rather than dropped
+        let removed: Vec<_> = io_service.drain_removed().collect();
+        assert_eq!(1, removed.len());
+        assert_eq!(bad, removed[0].0);
+        assert!(matches!(&removed[0].2, DisconnectReason::IO(err) if err.kind() == ErrorKind::NotFound));
+        assert!(bad_attempts.borrow().is_empty());
+        assert_eq!(0, io_service.pending().count());
+    }
+
+    #[test]
+    fn should_stop_connecting_endpoint_disconnected_by_poll_action() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap()];
+        let time_source = ManualTimeSource::default();
+        let attempts = Rc::new(RefCell::new(Vec::new()));
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_time_source(time_source.clone())
+            .with_dns_resolver(StaticDnsResolver(addrs.clone()))
+            .with_connect_timeout(Duration::from_secs(5))
+            .with_reconnect_policy(ExponentialBackoff::new(Duration::from_secs(1), Duration::from_secs(1)));
+
+        let handle = io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 80),
+                reachable: "10.0.0.2:80".parse().unwrap(),
+                attempts: attempts.clone(),
+                recreate: true,
+            })
+            .unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(vec![handle.token()], io_service.connecting);
+
+        // endpoint still connecting fails in the poll action and goes through the reconnect policy
+        io_service
+            .poll(|_, _| Err(io::Error::new(ErrorKind::BrokenPipe, "broken")))
+            .unwrap();
+        assert!(io_service.connecting.is_empty());
+        assert_eq!(0, io_service.iter().count());
+        assert!(
+            io_service
+                .pending()
+                .any(|(pending, _)| pending.token() == handle.token())
+        );
+        assert_eq!(0, io_service.drain_removed().count());
+    }
+
+    #[test]
+    fn should_remove_endpoint_that_cannot_be_recreated() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap()];
+        let time_source = ManualTimeSource::default();
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_time_source(time_source.clone())
+            .with_dns_resolver(StaticDnsResolver(addrs.clone()))
+            .with_connect_timeout(Duration::from_secs(1));
+
+        let removed = io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 80),
+                reachable: "10.0.0.2:80".parse().unwrap(),
+                attempts: Rc::default(),
+                recreate: false,
+            })
+            .unwrap();
+        let healthy = io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 80),
+                reachable: addrs[0],
+                attempts: Rc::default(),
+                recreate: false,
+            })
+            .unwrap();
+
+        io_service.poll(|_, _| Ok(())).unwrap();
+        time_source.advance(Duration::from_secs(2));
+        io_service.poll(|_, _| Ok(())).unwrap();
+
+        let drained: Vec<_> = io_service.drain_removed().collect();
+        assert_eq!(1, drained.len());
+        assert_eq!(removed, drained[0].0);
+        assert!(matches!(&drained[0].2, DisconnectReason::IO(err) if err.kind() == ErrorKind::TimedOut));
+        assert_eq!(0, io_service.drain_removed().count());
+
+        // service keeps running the remaining endpoint
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(vec![healthy], io_service.iter().map(|(handle, ..)| handle).collect::<Vec<_>>());
+        assert_eq!(0, io_service.pending().count());
+    }
+
+    #[test]
+    fn should_keep_original_error_for_removed_endpoint() {
+        #[derive(Debug)]
+        struct ProtocolError;
+
+        impl std::fmt::Display for ProtocolError {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "protocol error")
+            }
+        }
+
+        impl std::error::Error for ProtocolError {}
+
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap()];
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_time_source(ManualTimeSource::default())
+            .with_dns_resolver(StaticDnsResolver(addrs.clone()));
+
+        let handle = io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 80),
+                reachable: addrs[0],
+                attempts: Rc::default(),
+                recreate: false,
+            })
+            .unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+        io_service.poll(|_, _| Err(io::Error::other(ProtocolError))).unwrap();
+
+        let drained: Vec<_> = io_service.drain_removed().collect();
+        assert_eq!(1, drained.len());
+        assert_eq!(handle, drained[0].0);
+        let DisconnectReason::IO(err) = &drained[0].2 else {
+            panic!("expected io error, got {}", drained[0].2);
+        };
+        assert!(err.get_ref().is_some_and(|inner| inner.is::<ProtocolError>()));
+    }
+
+    #[test]
+    fn should_remove_endpoint_once_reconnect_attempts_exhausted() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap()];
+        let time_source = ManualTimeSource::default();
+        let attempts = Rc::new(RefCell::new(Vec::new()));
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_time_source(time_source.clone())
+            .with_dns_resolver(StaticDnsResolver(addrs.clone()))
+            .with_connect_timeout(Duration::from_secs(1))
+            .with_reconnect_policy(FixedDelay::new(Duration::from_secs(1)).with_max_attempts(2));
+
+        let handle = io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 80),
+                reachable: "10.0.0.2:80".parse().unwrap(),
+                attempts: attempts.clone(),
+                recreate: true,
+            })
+            .unwrap();
+
+        for _ in 0..10 {
+            io_service.poll(|_, _| Ok(())).unwrap();
+            time_source.advance(Duration::from_millis(1001));
+        }
+
+        // initial attempt followed by two reconnects
+        assert_eq!(3, attempts.borrow().len());
+        let drained: Vec<_> = io_service.drain_removed().collect();
+        assert_eq!(1, drained.len());
+        assert_eq!(handle.token(), drained[0].0.token());
+        assert_eq!(2, drained[0].0.generation());
+        assert!(matches!(drained[0].2, DisconnectReason::ReconnectAttemptsExhausted(2)));
+        assert_eq!(0, io_service.iter().count());
+        assert_eq!(0, io_service.pending().count());
+    }
+
+    struct LifecycleEndpoint {
+        inner: MockEndpoint,
+        events: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl ConnectionInfoProvider for LifecycleEndpoint {
+        fn connection_info(&self) -> &ConnectionInfo {
+            self.inner.connection_info()
+        }
+    }
+
+    impl Endpoint for LifecycleEndpoint {
+        type Target = MockStream;
+
+        fn create_target(&mut self, addr: SocketAddr) -> io::Result<Option<Self::Target>> {
+            self.inner.create_target(addr)
+        }
+
+        fn on_dns_resolved(&mut self, addrs: &[SocketAddr]) {
+            self.events.borrow_mut().push(format!("dns_resolved {addrs:?}"));
+        }
+
+        fn on_connected(&mut self, target: &mut Self::Target) -> io::Result<()> {
+            self.events.borrow_mut().push(format!("connected {}", target.addr));
+            Ok(())
+        }
+
+        fn on_disconnected(&mut self, reason: &DisconnectReason) {
+            self.events.borrow_mut().push(format!("disconnected {reason}"));
+        }
+    }
+
+    #[test]
+    fn should_invoke_lifecycle_callbacks() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap(), "10.0.0.2:80".parse().unwrap()];
+        let time_source = ManualTimeSource::default();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_time_source(time_source.clone())
+            .with_dns_resolver(StaticDnsResolver(addrs.clone()))
+            .with_connect_timeout(Duration::from_secs(1));
+
+        io_service
+            .register(LifecycleEndpoint {
+                inner: MockEndpoint {
+                    connection_info: ConnectionInfo::new("example.com", 80),
+                    reachable: addrs[1],
+                    attempts: Rc::default(),
+                    recreate: true,
+                },
+                events: events.clone(),
+            })
+            .unwrap();
+
+        io_service.poll(|_, _| Ok(())).unwrap();
+        time_source.advance(Duration::from_secs(2));
+        io_service.poll(|_, _| Ok(())).unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+
+        assert_eq!(
+            vec![
+                "dns_resolved [10.0.0.1:80, 10.0.0.2:80]".to_string(),
+                "disconnected connection to 10.0.0.1:80 timed out".to_string(),
+                "connected 10.0.0.2:80".to_string(),
+            ],
+            *events.borrow()
+        );
+    }
+
+    #[test]
+    fn should_fire_timers_for_connected_endpoints() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap()];
+        let time_source = ManualTimeSource::default();
+        let ticks = Rc::new(Cell::new(0));
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_time_source(time_source.clone())
+            .with_dns_resolver(StaticDnsResolver(addrs.clone()));
+
+        let handle = io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 80),
+                reachable: addrs[0],
+                attempts: Rc::default(),
+                recreate: true,
+            })
+            .unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+
+        let counter = ticks.clone();
+        io_service.schedule_periodic(handle, Duration::from_secs(1), move |_, _| {
+            counter.set(counter.get() + 1);
+            Ok(())
+        });
+        io_service.schedule_once(handle, Duration::from_millis(2500), |_, _| Err(io::Error::other("resubscribe")));
+        let cancelled = io_service.schedule_once(handle, Duration::from_secs(1), |_, _| panic!("cancelled"));
+        assert!(io_service.cancel_timer(cancelled));
+
+        for _ in 0..2 {
+            time_source.advance(Duration::from_secs(1));
+            io_service.poll(|_, _| Ok(())).unwrap();
+        }
+        assert_eq!(2, ticks.get());
+
+        // failing callback disconnects the endpoint
+        time_source.advance(Duration::from_millis(500));
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(0, io_service.iter().count());
+        assert_eq!(1, io_service.pending().count());
+
+        // periodic timer is skipped while the endpoint is reconnecting
+        time_source.advance(Duration::from_millis(500));
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(2, ticks.get());
+
+        time_source.advance(Duration::from_millis(600));
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(1, io_service.iter().count());
+        time_source.advance(Duration::from_millis(400));
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(3, ticks.get());
+        assert!(!io_service.cancel_timer(cancelled));
+    }
+
+    #[test]
+    fn should_park_one_shot_timer_until_endpoint_connects() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap(), "10.0.0.2:80".parse().unwrap()];
+        let time_source = ManualTimeSource::default();
+        let fired = Rc::new(Cell::new(false));
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_time_source(time_source.clone())
+            .with_dns_resolver(StaticDnsResolver(addrs.clone()))
+            .with_connect_timeout(Duration::from_secs(5));
+
+        let handle = io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 80),
+                reachable: addrs[1],
+                attempts: Rc::default(),
+                recreate: true,
+            })
+            .unwrap();
+        let flag = fired.clone();
+        io_service.schedule_once(handle, Duration::from_secs(1), move |_, _| {
+            flag.set(true);
+            Ok(())
+        });
+        io_service.poll(|_, _| Ok(())).unwrap();
+
+        // the timer expires while the first address is still connecting
+        time_source.advance(Duration::from_secs(1));
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert!(!fired.get());
+        assert_eq!(1, io_service.timers.len());
+        assert!(!io_service.timers.is_due(time_source.current_time_nanos()));
+
+        // connect times out and the second address connects straight away
+        time_source.advance(Duration::from_secs(5));
+        io_service.poll(|_, _| Ok(())).unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+        time_source.advance(Duration::from_millis(1));
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert!(fired.get());
+        assert!(io_service.timers.is_empty());
+    }
+
+    #[test]
+    fn should_only_poll_ready_endpoints() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap()];
+        let time_source = ManualTimeSource::default();
+        let selector = ReadySelector::default();
+        let next_ready = selector.next_ready.clone();
+        let mut io_service =
+            IOService::new(selector, time_source.clone(), StaticDnsResolver(addrs.clone())).with_ready_only(true);
+
+        let handles: Vec<Handle> = (80..83)
+            .map(|port| {
+                io_service
+                    .register(MockEndpoint {
+                        connection_info: ConnectionInfo::new("example.com", port),
+                        reachable: addrs[0],
+                        attempts: Rc::default(),
+                        recreate: true,
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        let poll = |io_service: &mut IOService<_, MockEndpoint, (), _, _>| {
+            let mut polled = Vec::new();
+            io_service
+                .poll(|stream, endpoint| {
+                    stream.pending_io = false;
+                    polled.push(endpoint.connection_info.port());
+                    Ok(())
+                })
+                .unwrap();
+            polled.sort();
+            polled
+        };
+
+        // newly connected endpoints are polled once
+        assert_eq!(vec![80, 81, 82], poll(&mut io_service));
+        assert!(poll(&mut io_service).is_empty());
+
+        // endpoint reported by the selector
+        next_ready.borrow_mut().push(handles[1].token);
+        assert_eq!(vec![81], poll(&mut io_service));
+        assert!(poll(&mut io_service).is_empty());
+
+        // endpoint with pending io after dispatch
+        io_service
+            .dispatch(handles[0], |stream, _| {
+                stream.pending_io = true;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(vec![80], poll(&mut io_service));
+        assert!(poll(&mut io_service).is_empty());
+
+        // endpoint with timer due
+        io_service.schedule_once(handles[2], Duration::from_secs(1), |_, _| Ok(()));
+        time_source.advance(Duration::from_secs(1));
+        next_ready.borrow_mut().push(handles[1].token);
+        assert_eq!(vec![81, 82], poll(&mut io_service));
+        assert!(poll(&mut io_service).is_empty());
+    }
+
+    #[test]
+    fn should_deliver_commands_sent_from_other_threads() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap()];
+        let (sender, receiver) = command::channel::<u32>(16);
+        let delivered = Rc::new(RefCell::new(Vec::new()));
+        let log = delivered.clone();
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_time_source(ManualTimeSource::default())
+            .with_dns_resolver(StaticDnsResolver(addrs.clone()))
+            .with_commands(receiver, move |_, endpoint: &mut MockEndpoint, command| {
+                log.borrow_mut().push((endpoint.connection_info.port(), command));
+                match command {
+                    0 => Err(io::Error::other("rejected")),
+                    _ => Ok(()),
+                }
+            });
+
+        let first = io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 80),
+                reachable: addrs[0],
+                attempts: Rc::default(),
+                recreate: true,
+            })
+            .unwrap();
+        let second = io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 81),
+                reachable: addrs[0],
+                attempts: Rc::default(),
+                recreate: true,
+            })
+            .unwrap();
+
+        // commands for unknown endpoints are dropped
+        sender.send(Handle::new(42, 0), 1).unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert!(delivered.borrow().is_empty());
+        assert_eq!(2, io_service.iter().count());
+
+        let producer = sender.clone();
+        std::thread::spawn(move || {
+            producer.send(first, 2).unwrap();
+            producer.send(second, 3).unwrap();
+            producer.send(first, 0).unwrap();
+            producer.send(first, 4).unwrap();
+        })
+        .join()
+        .unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+        // no more commands are delivered to the endpoint once the handler has failed
+        assert_eq!(vec![(80, 2), (81, 3), (80, 0)], *delivered.borrow());
+        assert_eq!(1, io_service.iter().count());
+        assert_eq!(1, io_service.pending().count());
+        assert!(sender.is_empty());
+    }
+
+    #[test]
+    fn should_hold_commands_until_connected_and_return_undelivered() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap(), "10.0.0.2:80".parse().unwrap()];
+        let time_source = ManualTimeSource::default();
+        let (sender, receiver) = command::channel::<u32>(16);
+        let delivered = Rc::new(RefCell::new(Vec::new()));
+        let undelivered = Rc::new(RefCell::new(Vec::new()));
+        let (log, returned) = (delivered.clone(), undelivered.clone());
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_time_source(time_source.clone())
+            .with_dns_resolver(StaticDnsResolver(addrs.clone()))
+            .with_connect_timeout(Duration::from_secs(5))
+            .with_commands_and_undelivered(
+                receiver,
+                move |_, _: &mut MockEndpoint, command| {
+                    log.borrow_mut().push(command);
+                    match command {
+                        0 => Err(io::Error::other("rejected")),
+                        _ => Ok(()),
+                    }
+                },
+                move |handle, command| returned.borrow_mut().push((handle, command)),
+            );
+
+        let slow = io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 80),
+                reachable: addrs[1],
+                attempts: Rc::default(),
+                recreate: true,
+            })
+            .unwrap();
+        let fragile = io_service
+            .register(MockEndpoint {
+                connection_info: ConnectionInfo::new("example.com", 81),
+                reachable: addrs[0],
+                attempts: Rc::default(),
+                recreate: false,
+            })
+            .unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+
+        // held while the first address is still connecting
+        sender.send(slow, 1).unwrap();
+        sender.send(slow, 2).unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert!(delivered.borrow().is_empty());
+
+        // the rejected command removes the endpoint that cannot be recreated
+        sender.send(fragile, 0).unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(vec![0], *delivered.borrow());
+        sender.send(fragile, 3).unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(vec![(fragile, 3)], *undelivered.borrow());
+
+        // connect times out and the commands are delivered in order once the second address connects
+        time_source.advance(Duration::from_secs(6));
+        for _ in 0..3 {
+            io_service.poll(|_, _| Ok(())).unwrap();
+        }
+        assert_eq!(vec![0, 1, 2], *delivered.borrow());
+        assert_eq!(1, undelivered.borrow().len());
+    }
+
+    #[test]
+    fn should_match_handle_generations_across_wrap() {
+        let first_generation = u32::MAX - 1;
+        let current = Handle::new(3, 1);
+        for generation in [first_generation, u32::MAX, 0, 1] {
+            assert!(Handle::new(3, generation).issued_between(first_generation, current));
+        }
+        assert!(!Handle::new(3, 2).issued_between(first_generation, current));
+        assert!(!Handle::new(3, first_generation - 1).issued_between(first_generation, current));
+        assert!(!Handle::new(4, 0).issued_between(first_generation, current));
+    }
+
+    #[test]
+    fn should_reject_stale_handles() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap()];
+        let time_source = ManualTimeSource::default();
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_time_source(time_source.clone())
+            .with_dns_resolver(StaticDnsResolver(addrs.clone()));
+        let endpoint = || MockEndpoint {
+            connection_info: ConnectionInfo::new("example.com", 80),
+            reachable: addrs[0],
+            attempts: Rc::default(),
+            recreate: true,
+        };
+
+        let first = io_service.register(endpoint()).unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(Some(Some(())), io_service.dispatch_current(first, |_, _| Ok(())).ok());
+
+        // endpoint is recreated under the next generation
+        io_service.poll(|_, _| Err(io::Error::other("disconnected"))).unwrap();
+        time_source.advance(Duration::from_secs(1));
+        io_service.poll(|_, _| Ok(())).unwrap();
+        let current = io_service.iter().map(|(handle, ..)| handle).next().unwrap();
+        assert_eq!(first.token(), current.token());
+        assert_eq!(1, current.generation());
+        assert_eq!(Some(()), io_service.dispatch(first, |_, _| Ok(())).unwrap());
+        assert_eq!(None, io_service.dispatch_current(first, |_, _| Ok(())).unwrap());
+        assert_eq!(Some(()), io_service.dispatch_current(current, |_, _| Ok(())).unwrap());
+
+        // token is recycled once the endpoint is deregistered
+        assert!(io_service.deregister(first).is_some());
+        let second = io_service.register(endpoint()).unwrap();
+        assert_eq!(first.token(), second.token());
+        assert_eq!(2, second.generation());
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(None, io_service.dispatch(first, |_, _| Ok(())).unwrap());
+        assert_eq!(None, io_service.dispatch(current, |_, _| Ok(())).unwrap());
+        assert!(io_service.deregister(current).is_none());
+        assert_eq!(Some(()), io_service.dispatch(second, |_, _| Ok(())).unwrap());
+    }
+
+    #[test]
+    fn should_register_and_deregister_endpoint_group() {
+        let addrs: Vec<SocketAddr> = vec!["10.0.0.1:80".parse().unwrap()];
+        let mut io_service = DirectSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_dns_resolver(StaticDnsResolver(addrs.clone()));
+        let endpoint = || MockEndpoint {
+            connection_info: ConnectionInfo::new("example.com", 80),
+            reachable: addrs[0],
+            attempts: Rc::default(),
+            recreate: true,
+        };
+
+        assert!(
+            io_service
+                .register_group(0, |seq: &u64| *seq, |_, _| Ok(endpoint()))
+                .is_err()
+        );
+
+        // legs are rolled back if any of them fails to register
+        let err = io_service
+            .register_group(
+                3,
+                |seq: &u64| *seq,
+                |leg, _| match leg {
+                    2 => Err(io::Error::other("failed")),
+                    _ => Ok(endpoint()),
+                },
+            )
+            .err()
+            .unwrap();
+        assert_eq!("failed", err.to_string());
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(0, io_service.iter().count());
+
+        let group = io_service
+            .register_group(2, |seq: &u64| *seq, |_, _| Ok(endpoint()))
+            .unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+        assert_eq!(2, group.leg_handles().count());
+        assert!(group.leg_handles().all(|leg| leg.token() != group.handle().token()));
+        assert_eq!(2, io_service.iter().count());
+
+        assert_eq!(2, io_service.deregister_group(group).len());
+        assert_eq!(0, io_service.iter().count());
+    }
+
+    #[test]
+    fn should_interleave_address_families() {
+        let addrs: Vec<SocketAddr> = ["[::1]:80", "[::2]:80", "[::3]:80", "10.0.0.1:80", "10.0.0.2:80"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect();
+        let interleaved: Vec<_> = interleave_address_families(addrs.clone()).into_iter().collect();
+        assert_eq!(vec![addrs[0], addrs[3], addrs[1], addrs[4], addrs[2]], interleaved);
+        assert!(interleave_address_families(Vec::new()).is_empty());
+    }
 }
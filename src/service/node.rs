@@ -1,35 +1,56 @@
 use crate::service::Handle;
+use crate::service::pending::{PendingEndpoint, Resolution};
+use crate::service::select::SelectorToken;
 use crate::service::time::TimeSource;
+use std::collections::VecDeque;
 use std::net::SocketAddr;
+use std::ops::Index;
 use std::time::Duration;
 
 pub struct IONode<S, E> {
     pub stream: S,
     pub endpoint: Option<(Handle, E)>,
+    /// Generation of the handle returned when the endpoint was registered, handles with older
+    /// generation belong to the endpoint that has used the same token before.
+    pub first_generation: u32,
     pub ttl: Duration,
     pub disconnect_time_ns: u64,
     pub addr: SocketAddr,
+    /// Remaining resolved addresses to try if this connection attempt fails, cleared once connected.
+    pub addrs: VecDeque<SocketAddr>,
+    pub connect_time_ns: u64,
+    /// Number of consecutive reconnect attempts, reset once connected.
+    pub attempt: u32,
 }
 
 impl<S, E> IONode<S, E> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new<TS>(
         stream: S,
         handle: Handle,
+        first_generation: u32,
         endpoint: E,
         ttl: Option<Duration>,
         ts: &TS,
         addr: SocketAddr,
+        addrs: VecDeque<SocketAddr>,
+        attempt: u32,
     ) -> IONode<S, E>
     where
         TS: TimeSource,
     {
         let ttl = ttl.map_or(u64::MAX, |ttl| ttl.as_nanos() as u64);
+        let now = ts.current_time_nanos();
         Self {
             stream,
             endpoint: Some((handle, endpoint)),
+            first_generation,
             ttl: Duration::from_nanos(ttl),
-            disconnect_time_ns: ts.current_time_nanos().saturating_add(ttl),
+            disconnect_time_ns: now.saturating_add(ttl),
             addr,
+            addrs,
+            connect_time_ns: now,
+            attempt,
         }
     }
 
@@ -43,6 +64,13 @@ impl<S, E> IONode<S, E> {
         unsafe { (&mut self.stream, self.endpoint.as_mut().unwrap_unchecked()) }
     }
 
+    /// Returns `true` if the `handle` has been issued for this endpoint, regardless of the
+    /// connection generation.
+    #[inline]
+    pub fn owns(&self, handle: Handle) -> bool {
+        handle.issued_between(self.first_generation, self.as_endpoint().0)
+    }
+
     pub const fn as_stream(&self) -> &S {
         &self.stream
     }
@@ -65,4 +93,165 @@ impl<S, E> IONode<S, E> {
         // SAFETY: safe to call as endpoint will never be None
         unsafe { self.endpoint.take().unwrap_unchecked() }
     }
+
+    /// Turn the node back into the pending endpoint that keeps the remaining resolved addresses.
+    pub fn into_pending<Q>(mut self, now: u64) -> PendingEndpoint<Q, E> {
+        let addrs = std::mem::take(&mut self.addrs);
+        let first_generation = self.first_generation;
+        let attempt = self.attempt;
+        let (handle, endpoint) = self.into_endpoint();
+        PendingEndpoint {
+            handle,
+            first_generation,
+            resolution: Resolution::Resolved(addrs),
+            endpoint,
+            attempt,
+            next_attempt_ns: now,
+        }
+    }
+}
+
+/// Dense storage of the [`IONode`]s indexed directly by their token. The `IOService` recycles the
+/// tokens of removed endpoints, so the storage stays compact and is iterated in token order.
+pub struct NodeSlab<S, E> {
+    slots: Vec<Option<IONode<S, E>>>,
+    len: usize,
+}
+
+impl<S, E> Default for NodeSlab<S, E> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<S, E> NodeSlab<S, E> {
+    /// Number of nodes stored.
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn get(&self, token: SelectorToken) -> Option<&IONode<S, E>> {
+        self.slots.get(token as usize)?.as_ref()
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, token: SelectorToken) -> Option<&mut IONode<S, E>> {
+        self.slots.get_mut(token as usize)?.as_mut()
+    }
+
+    #[inline]
+    pub fn contains(&self, token: SelectorToken) -> bool {
+        self.get(token).is_some()
+    }
+
+    /// Store the node under the `token`, returning the node that has been stored there before.
+    pub fn insert(&mut self, token: SelectorToken, io_node: IONode<S, E>) -> Option<IONode<S, E>> {
+        let index = token as usize;
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, || None);
+        }
+        let previous = self.slots[index].replace(io_node);
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous
+    }
+
+    #[inline]
+    pub fn remove(&mut self, token: SelectorToken) -> Option<IONode<S, E>> {
+        let io_node = self.slots.get_mut(token as usize)?.take()?;
+        self.len -= 1;
+        Some(io_node)
+    }
+
+    /// Upper bound of the stored tokens, can be used to visit the nodes while removing some of them.
+    #[inline]
+    pub const fn token_bound(&self) -> SelectorToken {
+        self.slots.len() as SelectorToken
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &IONode<S, E>> {
+        self.slots.iter().flatten()
+    }
+
+    #[inline]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut IONode<S, E>> {
+        self.slots.iter_mut().flatten()
+    }
+
+    /// Keep only the nodes for which `f` returns `true`, visiting them in token order.
+    #[inline]
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(SelectorToken, &mut IONode<S, E>) -> bool,
+    {
+        for (token, slot) in self.slots.iter_mut().enumerate() {
+            if let Some(io_node) = slot {
+                if !f(token as SelectorToken, io_node) {
+                    *slot = None;
+                    self.len -= 1;
+                }
+            }
+        }
+    }
+}
+
+impl<S, E> Index<SelectorToken> for NodeSlab<S, E> {
+    type Output = IONode<S, E>;
+
+    #[inline]
+    fn index(&self, token: SelectorToken) -> &Self::Output {
+        self.get(token).expect("io node not found")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::time::SystemTimeClockSource;
+
+    fn io_node(token: SelectorToken) -> IONode<(), ()> {
+        let addr = "127.0.0.1:80".parse().unwrap();
+        IONode::new((), Handle::new(token, 0), 0, (), None, &SystemTimeClockSource, addr, VecDeque::new(), 0)
+    }
+
+    #[test]
+    fn should_store_nodes_by_token() {
+        let mut io_nodes = NodeSlab::default();
+        for token in [3, 0, 1] {
+            assert!(io_nodes.insert(token, io_node(token)).is_none());
+        }
+        assert_eq!(3, io_nodes.len());
+        assert!(io_nodes.contains(3));
+        assert!(!io_nodes.contains(2));
+        assert!(io_nodes.get(7).is_none());
+
+        // iteration follows the token order
+        let tokens = |io_nodes: &NodeSlab<(), ()>| {
+            io_nodes
+                .iter()
+                .map(|io_node| io_node.as_endpoint().0.token())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![0, 1, 3], tokens(&io_nodes));
+
+        io_nodes.retain(|token, _| token != 1);
+        assert_eq!(vec![0, 3], tokens(&io_nodes));
+        assert!(io_nodes.remove(3).is_some());
+        assert!(io_nodes.remove(3).is_none());
+        assert_eq!(1, io_nodes.len());
+        assert!(io_nodes.insert(0, io_node(0)).is_some());
+        assert_eq!(1, io_nodes.len());
+    }
 }
@@ -0,0 +1,113 @@
+use crate::service::Handle;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
+/// Addresses used to connect the pending endpoint.
+pub enum Resolution<Q> {
+    /// DNS query has not been issued yet, this happens once the endpoint is due to connect.
+    Unresolved,
+    /// DNS query in progress together with the time it was created.
+    Query(Q, u64),
+    /// Remaining resolved addresses to try before going back to DNS.
+    Resolved(VecDeque<SocketAddr>),
+}
+
+impl<Q> Resolution<Q> {
+    /// Remaining resolved addresses, empty if the endpoint has not been resolved yet.
+    #[inline]
+    pub fn into_addrs(self) -> VecDeque<SocketAddr> {
+        match self {
+            Resolution::Resolved(addrs) => addrs,
+            _ => VecDeque::new(),
+        }
+    }
+}
+
+/// Endpoint waiting to be (re)connected.
+pub struct PendingEndpoint<Q, E> {
+    pub handle: Handle,
+    /// Generation of the handle returned when the endpoint was registered.
+    pub first_generation: u32,
+    pub resolution: Resolution<Q>,
+    pub endpoint: E,
+    /// Number of consecutive reconnect attempts.
+    pub attempt: u32,
+    pub next_attempt_ns: u64,
+}
+
+impl<Q, E> PendingEndpoint<Q, E> {
+    /// Returns `true` if the `handle` has been issued for this endpoint, regardless of the
+    /// connection generation.
+    #[inline]
+    pub fn owns(&self, handle: Handle) -> bool {
+        handle.issued_between(self.first_generation, self.handle)
+    }
+}
+
+/// Queue of pending endpoints that also tracks the earliest time any of them is due to connect.
+pub struct PendingEndpoints<Q, E> {
+    queue: VecDeque<PendingEndpoint<Q, E>>,
+    next_check_ns: u64,
+}
+
+impl<Q, E> Default for PendingEndpoints<Q, E> {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            next_check_ns: u64::MAX,
+        }
+    }
+}
+
+impl<Q, E> PendingEndpoints<Q, E> {
+    #[inline]
+    pub fn push(&mut self, pending: PendingEndpoint<Q, E>) {
+        self.next_check_ns = self.next_check_ns.min(pending.next_attempt_ns);
+        self.queue.push_back(pending);
+    }
+
+    /// Returns `true` if any of the endpoints could be due to connect at `now`.
+    #[inline]
+    pub const fn is_due(&self, now: u64) -> bool {
+        now >= self.next_check_ns
+    }
+
+    /// Start checking the pending endpoints, returns how many of them should be popped from the
+    /// front. Any endpoint that is not ready is expected to be pushed back.
+    #[inline]
+    pub fn begin_check(&mut self) -> usize {
+        self.next_check_ns = u64::MAX;
+        self.queue.len()
+    }
+
+    #[inline]
+    pub fn pop_front(&mut self) -> Option<PendingEndpoint<Q, E>> {
+        self.queue.pop_front()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Remove the endpoint owning the `handle`, returning it together with its current handle.
+    pub fn remove(&mut self, handle: Handle) -> Option<(Handle, E)> {
+        let index = self.queue.iter().position(|pending| pending.owns(handle))?;
+        self.queue
+            .remove(index)
+            .map(|pending| (pending.handle, pending.endpoint))
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.queue.iter().any(|pending| pending.owns(handle))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PendingEndpoint<Q, E>> {
+        self.queue.iter()
+    }
+
+    #[cfg(test)]
+    pub fn front(&self) -> Option<&PendingEndpoint<Q, E>> {
+        self.queue.front()
+    }
+}
@@ -0,0 +1,179 @@
+//! Policies that control how quickly the [`IOService`](crate::service::IOService) reconnects
+//! the endpoints.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use boomnet::service::reconnect::{ExponentialBackoff, ReconnectPolicy};
+//!
+//! let policy = ExponentialBackoff::new(Duration::from_millis(100), Duration::from_secs(30))
+//!     .with_jitter(0.2)
+//!     .with_max_attempts(10);
+//! ```
+
+use crate::service::Handle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Default delay used by the [`IOService`](crate::service::IOService) before reconnecting the endpoint.
+pub const DEFAULT_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Decides how long the endpoint identified by `handle` should wait before the next connection
+/// attempt. The policy is shared by all endpoints registered with the service, while the attempts
+/// are counted for each endpoint separately and reset once the connection has been established.
+pub trait ReconnectPolicy {
+    /// Returns delay before the reconnect `attempt` (starting from `1`) or `None` if the endpoint
+    /// should not be reconnected anymore.
+    fn next_delay(&mut self, handle: Handle, attempt: u32) -> Option<Duration>;
+
+    /// Limit the number of consecutive reconnect attempts.
+    fn with_max_attempts(self, max_attempts: u32) -> MaxAttempts<Self>
+    where
+        Self: Sized,
+    {
+        MaxAttempts {
+            policy: self,
+            max_attempts,
+        }
+    }
+}
+
+/// Always wait the same amount of time before reconnecting.
+#[derive(Debug, Copy, Clone)]
+pub struct FixedDelay(Duration);
+
+impl FixedDelay {
+    pub const fn new(delay: Duration) -> Self {
+        Self(delay)
+    }
+}
+
+impl Default for FixedDelay {
+    fn default() -> Self {
+        Self(DEFAULT_RECONNECT_DELAY)
+    }
+}
+
+impl ReconnectPolicy for FixedDelay {
+    #[inline]
+    fn next_delay(&mut self, _handle: Handle, _attempt: u32) -> Option<Duration> {
+        Some(self.0)
+    }
+}
+
+/// Grow the delay by `multiplier` (default `2`) with each consecutive attempt, starting with
+/// `initial` and capped at `max`. Optional jitter will randomly shorten each delay by up to the
+/// configured fraction so that endpoints do not reconnect in lockstep.
+#[derive(Debug, Clone)]
+pub struct ExponentialBackoff {
+    initial: Duration,
+    max: Duration,
+    multiplier: f64,
+    jitter: f64,
+    seed: u64,
+}
+
+impl ExponentialBackoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        Self {
+            initial,
+            max,
+            multiplier: 2.0,
+            jitter: 0.0,
+            // xorshift state must be non-zero
+            seed: seed | 1,
+        }
+    }
+
+    /// Factor by which the delay grows with each attempt.
+    pub const fn with_multiplier(self, multiplier: f64) -> Self {
+        Self { multiplier, ..self }
+    }
+
+    /// Fraction (between `0` and `1`) by which each delay can be randomly shortened.
+    pub const fn with_jitter(self, jitter: f64) -> Self {
+        Self {
+            jitter: jitter.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    #[inline]
+    fn next_random(&mut self) -> f64 {
+        // xorshift64
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        (self.seed >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl ReconnectPolicy for ExponentialBackoff {
+    fn next_delay(&mut self, _handle: Handle, attempt: u32) -> Option<Duration> {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let delay = self.initial.as_secs_f64() * self.multiplier.powi(exponent);
+        let delay = delay.min(self.max.as_secs_f64());
+        let delay = delay * (1.0 - self.jitter * self.next_random());
+        Some(Duration::from_secs_f64(delay))
+    }
+}
+
+/// Gives up once the endpoint has failed to reconnect `max_attempts` times in a row, otherwise
+/// delegates to the wrapped policy.
+#[derive(Debug, Clone)]
+pub struct MaxAttempts<P> {
+    policy: P,
+    max_attempts: u32,
+}
+
+impl<P: ReconnectPolicy> ReconnectPolicy for MaxAttempts<P> {
+    #[inline]
+    fn next_delay(&mut self, handle: Handle, attempt: u32) -> Option<Duration> {
+        if attempt > self.max_attempts {
+            return None;
+        }
+        self.policy.next_delay(handle, attempt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_use_fixed_delay() {
+        let mut policy = FixedDelay::default();
+        assert_eq!(Some(DEFAULT_RECONNECT_DELAY), policy.next_delay(Handle::default(), 1));
+        assert_eq!(Some(DEFAULT_RECONNECT_DELAY), policy.next_delay(Handle::default(), 100));
+    }
+
+    #[test]
+    fn should_grow_delay_exponentially() {
+        let mut policy = ExponentialBackoff::new(Duration::from_millis(100), Duration::from_secs(1));
+        let delays: Vec<_> = (1..=6)
+            .map(|attempt| policy.next_delay(Handle::default(), attempt).unwrap())
+            .collect();
+        assert_eq!(vec![100, 200, 400, 800, 1000, 1000], delays.iter().map(Duration::as_millis).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn should_apply_jitter() {
+        let mut policy = ExponentialBackoff::new(Duration::from_secs(1), Duration::from_secs(1)).with_jitter(0.5);
+        for _ in 0..1000 {
+            let delay = policy.next_delay(Handle::default(), 1).unwrap();
+            assert!(delay <= Duration::from_secs(1));
+            assert!(delay >= Duration::from_millis(500));
+        }
+    }
+
+    #[test]
+    fn should_give_up_after_max_attempts() {
+        let mut policy = FixedDelay::default().with_max_attempts(2);
+        assert!(policy.next_delay(Handle::default(), 1).is_some());
+        assert!(policy.next_delay(Handle::default(), 2).is_some());
+        assert!(policy.next_delay(Handle::default(), 3).is_none());
+    }
+}
@@ -0,0 +1,522 @@
+//! Runtime that spreads the endpoints across several [`IOService`] shards, each running on its own
+//! thread pinned to a dedicated CPU.
+//!
+//! The [`IOService`] is not `Send`, so every shard builds its own service on the shard thread using
+//! the provided factory. Endpoints are assigned to the shards by the user supplied sharding
+//! function and are then addressed by the [`ShardHandle`], which identifies both the shard and the
+//! endpoint within that shard.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! use std::io;
+//! use std::net::SocketAddr;
+//! use boomnet::service::IntoIOService;
+//! use boomnet::service::endpoint::Endpoint;
+//! use boomnet::service::runtime::ShardedRuntime;
+//! use boomnet::service::select::direct::DirectSelector;
+//! use boomnet::stream::{ConnectionInfo, ConnectionInfoProvider};
+//! use boomnet::stream::tcp::TcpStream;
+//!
+//! struct FeedEndpoint {
+//!     id: usize,
+//!     connection_info: ConnectionInfo,
+//! }
+//!
+//! impl ConnectionInfoProvider for FeedEndpoint {
+//!     fn connection_info(&self) -> &ConnectionInfo {
+//!         &self.connection_info
+//!     }
+//! }
+//!
+//! impl Endpoint for FeedEndpoint {
+//!     type Target = TcpStream;
+//!
+//!     fn create_target(&mut self, addr: SocketAddr) -> io::Result<Option<Self::Target>> {
+//!         self.connection_info.clone().into_tcp_stream_with_addr(addr).map(Some)
+//!     }
+//! }
+//!
+//! fn main() -> io::Result<()> {
+//!     let mut runtime = ShardedRuntime::new(
+//!         [2, 3],
+//!         |_shard| Ok(DirectSelector::new()?.into_io_service()),
+//!         |_stream, _endpoint: &mut FeedEndpoint| Ok(()),
+//!         |endpoint| endpoint.id,
+//!     )?;
+//!
+//!     let handle = runtime.register(FeedEndpoint {
+//!         id: 1,
+//!         connection_info: ConnectionInfo::new("127.0.0.1", 9000),
+//!     })?;
+//!
+//!     runtime.dispatch(handle, move |_stream, endpoint| {
+//!         println!("endpoint {} is running on shard {}", endpoint.id, handle.shard());
+//!         Ok(())
+//!     }).ok();
+//!
+//!     runtime.shutdown()
+//! }
+//! ```
+
+use crate::service::command::{CommandSender, SendError};
+use crate::service::dns::DnsResolver;
+use crate::service::endpoint::{DisconnectReason, Endpoint};
+use crate::service::select::Selector;
+use crate::service::time::TimeSource;
+use crate::service::{Handle, IOService, command};
+use core_affinity::CoreId;
+use log::{info, warn};
+use std::io;
+use std::sync::mpsc::{Receiver, Sender, SyncSender, TryRecvError};
+use std::thread;
+use std::thread::JoinHandle;
+
+/// Number of dispatched actions each shard can hold before [`ShardedRuntime::dispatch`] starts
+/// reporting backpressure.
+const DEFAULT_DISPATCH_CAPACITY: usize = 1024;
+
+/// Number of pending registration requests per shard.
+const CONTROL_CAPACITY: usize = 64;
+
+/// Number of poll errors held for [`ShardedRuntime::drain_errors`], any further errors are only
+/// logged until the errors are drained.
+const ERRORS_CAPACITY: usize = 64;
+
+/// Action dispatched to the endpoint owned by one of the shards.
+pub type Dispatch<E> = Box<dyn FnOnce(&mut <E as Endpoint>::Target, &mut E) -> io::Result<()> + Send>;
+
+/// Endpoint handle that also identifies the shard owning the endpoint.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ShardHandle {
+    shard: usize,
+    handle: Handle,
+}
+
+impl ShardHandle {
+    /// Index of the shard the endpoint has been assigned to.
+    #[inline]
+    pub const fn shard(&self) -> usize {
+        self.shard
+    }
+
+    /// Handle of the endpoint within the shard [`IOService`].
+    #[inline]
+    pub const fn handle(&self) -> Handle {
+        self.handle
+    }
+}
+
+/// Requests handled by the shard thread in between the polls.
+enum Request<E> {
+    Register(E, SyncSender<io::Result<Handle>>),
+    Deregister(Handle, SyncSender<Option<E>>),
+}
+
+struct Shard<E: Endpoint> {
+    requests: SyncSender<Request<E>>,
+    dispatcher: CommandSender<Dispatch<E>>,
+    thread: JoinHandle<()>,
+}
+
+/// Owns several [`IOService`] shards, each polled on its own thread pinned to the configured CPU,
+/// and routes the registration, deregistration and dispatch requests to the shard owning the
+/// endpoint. Endpoints removed by the shards (see [`IOService::drain_removed`]) are handed back
+/// through [`ShardedRuntime::drain_removed`]. Shards keep running when the poll fails, the errors
+/// are logged and handed back through [`ShardedRuntime::drain_errors`].
+///
+/// Dropping the runtime stops all the shards and waits for the shard threads to finish.
+pub struct ShardedRuntime<E: Endpoint> {
+    shards: Vec<Shard<E>>,
+    sharding: Box<dyn Fn(&E) -> usize + Send>,
+    removed: Receiver<(ShardHandle, E, DisconnectReason)>,
+    errors: Receiver<(usize, io::Error)>,
+}
+
+impl<E> ShardedRuntime<E>
+where
+    E: Endpoint + Send + 'static,
+    E::Target: 'static,
+{
+    /// Start one shard for each CPU in `cpu_ids`, the same CPU can be listed more than once. The
+    /// [`IOService`] of each shard is created on the shard thread by the `io_service_factory`,
+    /// which is passed the shard index, and polled with a clone of the `action`. The `sharding`
+    /// function returns the index of the shard the endpoint should be registered with, modulo the
+    /// number of shards.
+    ///
+    /// The runtime delivers the dispatched actions through the shard service commands (see
+    /// [`IOService::with_commands`]), so any commands configured by the factory are replaced.
+    pub fn new<S, TS, D, F, A, H>(
+        cpu_ids: impl IntoIterator<Item = usize>,
+        io_service_factory: F,
+        action: A,
+        sharding: H,
+    ) -> io::Result<Self>
+    where
+        S: Selector<Target = E::Target> + 'static,
+        TS: TimeSource + 'static,
+        D: DnsResolver + 'static,
+        F: Fn(usize) -> io::Result<IOService<S, E, (), TS, D>> + Clone + Send + 'static,
+        A: FnMut(&mut E::Target, &mut E) -> io::Result<()> + Clone + Send + 'static,
+        H: Fn(&E) -> usize + Send + 'static,
+    {
+        let cpu_set =
+            core_affinity::get_core_ids().ok_or_else(|| io::Error::other("unable to retrieve available cpu set"))?;
+        let (removed_tx, removed) = std::sync::mpsc::channel();
+        let (errors_tx, errors) = std::sync::mpsc::sync_channel(ERRORS_CAPACITY);
+        let mut shards = Vec::new();
+        for (index, cpu_id) in cpu_ids.into_iter().enumerate() {
+            let core_id = CoreId { id: cpu_id };
+            if !cpu_set.contains(&core_id) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("core id {cpu_id} not present in the available cpu set"),
+                ));
+            }
+            shards.push(Shard::start(
+                index,
+                core_id,
+                io_service_factory.clone(),
+                action.clone(),
+                removed_tx.clone(),
+                errors_tx.clone(),
+            )?);
+        }
+        if shards.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "at least one cpu id is required"));
+        }
+        Ok(Self {
+            shards,
+            sharding: Box::new(sharding),
+            removed,
+            errors,
+        })
+    }
+
+    /// Number of shards owned by the runtime.
+    #[inline]
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Register a new [`Endpoint`] with the shard selected by the sharding function and return
+    /// a handle to the created endpoint. Blocks until the shard thread has accepted the endpoint.
+    pub fn register(&mut self, endpoint: E) -> io::Result<ShardHandle> {
+        let shard = (self.sharding)(&endpoint) % self.shards.len();
+        let (reply_tx, reply) = std::sync::mpsc::sync_channel(1);
+        self.shards[shard].request(Request::Register(endpoint, reply_tx))?;
+        let handle = reply.recv().map_err(|_| shard_stopped())??;
+        Ok(ShardHandle { shard, handle })
+    }
+
+    /// Deregister [`Endpoint`] from the shard owning it. Blocks until the shard thread has removed
+    /// the endpoint, which is then returned unless it was not found.
+    pub fn deregister(&mut self, handle: ShardHandle) -> io::Result<Option<E>> {
+        let (reply_tx, reply) = std::sync::mpsc::sync_channel(1);
+        self.shards[handle.shard].request(Request::Deregister(handle.handle, reply_tx))?;
+        reply.recv().map_err(|_| shard_stopped())
+    }
+
+    /// Dispatch the `action` to the endpoint identified by `handle`. The action is invoked on the
+    /// shard thread during its next poll if the endpoint is connected at that time, is held until
+    /// the connection has been established if the endpoint is connecting or reconnecting, and is
+    /// dropped if the endpoint no longer exists. Returning error from the action will disconnect
+    /// the endpoint the same way as failing inside `poll` does. If the shard queue is full the
+    /// action is handed back as part of the error.
+    pub fn dispatch<F>(&self, handle: ShardHandle, action: F) -> Result<(), SendError<Dispatch<E>>>
+    where
+        F: FnOnce(&mut E::Target, &mut E) -> io::Result<()> + Send + 'static,
+    {
+        self.shards[handle.shard]
+            .dispatcher
+            .send(handle.handle, Box::new(action))
+    }
+
+    /// Drain endpoints that have been removed by the shards, together with their handle and the
+    /// reason they have been disconnected.
+    #[inline]
+    pub fn drain_removed(&mut self) -> impl Iterator<Item = (ShardHandle, E, DisconnectReason)> + '_ {
+        self.removed.try_iter()
+    }
+
+    /// Drain errors the shards have failed to poll with, together with the index of the shard.
+    /// The endpoints of the shard are left in place and the shard carries on polling them.
+    #[inline]
+    pub fn drain_errors(&mut self) -> impl Iterator<Item = (usize, io::Error)> + '_ {
+        self.errors.try_iter()
+    }
+}
+
+impl<E: Endpoint> ShardedRuntime<E> {
+    /// Stop all the shards and wait for the shard threads to finish, returning error if any of
+    /// the shard threads has panicked.
+    pub fn shutdown(mut self) -> io::Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> io::Result<()> {
+        // dropping the request senders tells the shard threads to stop
+        let threads: Vec<_> = self.shards.drain(..).map(|shard| shard.thread).collect();
+        let mut result = Ok(());
+        for thread in threads {
+            if thread.join().is_err() {
+                result = Err(io::Error::other("shard thread has panicked"));
+            }
+        }
+        result
+    }
+}
+
+impl<E: Endpoint> Drop for ShardedRuntime<E> {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+impl<E> Shard<E>
+where
+    E: Endpoint + Send + 'static,
+    E::Target: 'static,
+{
+    fn start<S, TS, D, F, A>(
+        index: usize,
+        core_id: CoreId,
+        io_service_factory: F,
+        mut action: A,
+        removed: Sender<(ShardHandle, E, DisconnectReason)>,
+        errors: SyncSender<(usize, io::Error)>,
+    ) -> io::Result<Self>
+    where
+        S: Selector<Target = E::Target> + 'static,
+        TS: TimeSource + 'static,
+        D: DnsResolver + 'static,
+        F: Fn(usize) -> io::Result<IOService<S, E, (), TS, D>> + Send + 'static,
+        A: FnMut(&mut E::Target, &mut E) -> io::Result<()> + Send + 'static,
+    {
+        let (requests_tx, requests) = std::sync::mpsc::sync_channel(CONTROL_CAPACITY);
+        let (started_tx, started) = std::sync::mpsc::sync_channel(1);
+        let (dispatcher, dispatch_receiver) = command::channel(DEFAULT_DISPATCH_CAPACITY);
+        let thread = thread::Builder::new()
+            .name(format!("io-shard-{index}"))
+            .spawn(move || {
+                if core_affinity::set_for_current(core_id) {
+                    info!("successfully pinned shard {index} to core {}", core_id.id);
+                }
+                let mut io_service = match io_service_factory(index) {
+                    Ok(io_service) => io_service
+                        .with_commands(dispatch_receiver, |target, endpoint, action: Dispatch<E>| {
+                            action(target, endpoint)
+                        }),
+                    Err(err) => {
+                        let _ = started_tx.send(Err(err));
+                        return;
+                    }
+                };
+                let _ = started_tx.send(Ok(()));
+                loop {
+                    loop {
+                        match requests.try_recv() {
+                            Ok(Request::Register(endpoint, reply)) => {
+                                let _ = reply.send(io_service.register(endpoint));
+                            }
+                            Ok(Request::Deregister(handle, reply)) => {
+                                let _ = reply.send(io_service.deregister(handle));
+                            }
+                            Err(TryRecvError::Empty) => break,
+                            Err(TryRecvError::Disconnected) => return,
+                        }
+                    }
+                    if let Err(err) = io_service.poll(&mut action) {
+                        warn!("shard {index} failed to poll: {err}");
+                        let _ = errors.try_send((index, err));
+                    }
+                    for (handle, endpoint, reason) in io_service.drain_removed() {
+                        let _ = removed.send((ShardHandle { shard: index, handle }, endpoint, reason));
+                    }
+                }
+            })?;
+        started.recv().map_err(|_| shard_stopped())??;
+        Ok(Self {
+            requests: requests_tx,
+            dispatcher,
+            thread,
+        })
+    }
+
+    fn request(&self, request: Request<E>) -> io::Result<()> {
+        self.requests.send(request).map_err(|_| shard_stopped())
+    }
+}
+
+#[cold]
+fn shard_stopped() -> io::Error {
+    io::Error::other("shard has stopped")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::IntoIOService;
+    use crate::service::dns::BlockingDnsResolver;
+    use crate::service::node::{IONode, NodeSlab};
+    use crate::service::select::SelectorToken;
+    use crate::service::select::direct::DirectSelector;
+    use crate::service::time::SystemTimeClockSource;
+    use crate::stream::tcp::TcpStream;
+    use crate::stream::{ConnectionInfo, ConnectionInfoProvider};
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::time::Duration;
+
+    struct TestEndpoint {
+        id: u8,
+        connection_info: ConnectionInfo,
+    }
+
+    impl ConnectionInfoProvider for TestEndpoint {
+        fn connection_info(&self) -> &ConnectionInfo {
+            &self.connection_info
+        }
+    }
+
+    impl Endpoint for TestEndpoint {
+        type Target = TcpStream;
+
+        fn create_target(&mut self, addr: SocketAddr) -> io::Result<Option<Self::Target>> {
+            self.connection_info.clone().into_tcp_stream_with_addr(addr).map(Some)
+        }
+    }
+
+    #[test]
+    fn should_route_requests_to_the_shard_owning_the_endpoint() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (received_tx, received) = std::sync::mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let received_tx = received_tx.clone();
+                thread::spawn(move || {
+                    let mut id = [0u8; 1];
+                    stream.unwrap().read_exact(&mut id).unwrap();
+                    received_tx.send(id[0]).unwrap();
+                });
+            }
+        });
+
+        let cpu_id = core_affinity::get_core_ids().unwrap()[0].id;
+        let mut runtime = ShardedRuntime::new(
+            [cpu_id, cpu_id],
+            |_| Ok(DirectSelector::new()?.into_io_service()),
+            |_, _| Ok(()),
+            |endpoint: &TestEndpoint| endpoint.id as usize,
+        )
+        .unwrap();
+        assert_eq!(2, runtime.shards());
+
+        let handles: Vec<_> = (0..2)
+            .map(|id| {
+                runtime
+                    .register(TestEndpoint {
+                        id,
+                        connection_info: ConnectionInfo::new("127.0.0.1", port),
+                    })
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(0, handles[0].shard());
+        assert_eq!(1, handles[1].shard());
+
+        // actions are held until the endpoints have connected
+        for handle in &handles {
+            runtime
+                .dispatch(*handle, |stream, endpoint| stream.write_all(&[endpoint.id]))
+                .unwrap();
+        }
+        let mut ids: Vec<_> = (0..2)
+            .map(|_| received.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        ids.sort();
+        assert_eq!(vec![0, 1], ids);
+
+        assert_eq!(1, runtime.deregister(handles[1]).unwrap().unwrap().id);
+        assert!(runtime.deregister(handles[1]).unwrap().is_none());
+        runtime.shutdown().unwrap();
+    }
+
+    /// Selector that fails the first poll.
+    struct FlakySelector {
+        selector: DirectSelector<TcpStream>,
+        failed: bool,
+    }
+
+    impl Selector for FlakySelector {
+        type Target = TcpStream;
+
+        fn register<E>(&mut self, token: SelectorToken, io_node: &mut IONode<Self::Target, E>) -> io::Result<()> {
+            self.selector.register(token, io_node)
+        }
+
+        fn unregister<E>(&mut self, io_node: &mut IONode<Self::Target, E>) -> io::Result<()> {
+            self.selector.unregister(io_node)
+        }
+
+        fn poll<E>(&mut self, io_nodes: &mut NodeSlab<Self::Target, E>) -> io::Result<()> {
+            if !std::mem::replace(&mut self.failed, true) {
+                return Err(io::Error::other("poll failed"));
+            }
+            self.selector.poll(io_nodes)
+        }
+
+        fn next_token(&mut self) -> SelectorToken {
+            self.selector.next_token()
+        }
+    }
+
+    #[test]
+    fn should_keep_shard_running_when_poll_fails() {
+        let cpu_id = core_affinity::get_core_ids().unwrap()[0].id;
+        let mut runtime = ShardedRuntime::new(
+            [cpu_id],
+            |_| {
+                let selector = FlakySelector {
+                    selector: DirectSelector::new()?,
+                    failed: false,
+                };
+                Ok(IOService::new(selector, SystemTimeClockSource, BlockingDnsResolver))
+            },
+            |_, _| Ok(()),
+            |endpoint: &TestEndpoint| endpoint.id as usize,
+        )
+        .unwrap();
+
+        let error = loop {
+            if let Some(error) = runtime.drain_errors().next() {
+                break error;
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(0, error.0);
+        assert_eq!("poll failed", error.1.to_string());
+
+        // shard is still accepting endpoints
+        let handle = runtime
+            .register(TestEndpoint {
+                id: 0,
+                connection_info: ConnectionInfo::new("127.0.0.1", 1),
+            })
+            .unwrap();
+        assert_eq!(0, runtime.deregister(handle).unwrap().unwrap().id);
+        runtime.shutdown().unwrap();
+    }
+
+    #[test]
+    fn should_reject_unknown_cpu_id() {
+        let result = ShardedRuntime::new(
+            [usize::MAX],
+            |_| Ok(DirectSelector::new()?.into_io_service()),
+            |_, _| Ok(()),
+            |endpoint: &TestEndpoint| endpoint.id as usize,
+        );
+        assert_eq!(io::ErrorKind::InvalidInput, result.err().unwrap().kind());
+    }
+}
@@ -0,0 +1,375 @@
+//! Selector that uses Linux `epoll` directly, without going through `mio`.
+
+use std::io;
+use std::marker::PhantomData;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::time::Duration;
+
+use crate::service::dns::BlockingDnsResolver;
+use crate::service::endpoint::{Context, Endpoint, EndpointWithContext};
+use crate::service::node::{IONode, NodeSlab};
+use crate::service::select::{Selectable, Selector, SelectorToken};
+use crate::service::time::SystemTimeClockSource;
+use crate::service::{IOService, IntoIOService, IntoIOServiceWithContext};
+
+const DEFAULT_EVENTS_CAPACITY: usize = 1024;
+
+/// `_IOW(0x8A, 0x01, struct epoll_params)`, available since Linux 6.9.
+const EPIOCSPARAMS: libc::c_ulong = 0x4008_8A01;
+
+/// Mirrors `struct epoll_params` from `linux/eventpoll.h`.
+#[repr(C)]
+struct EpollParams {
+    busy_poll_usecs: u32,
+    busy_poll_budget: u16,
+    prefer_busy_poll: u8,
+    pad: u8,
+}
+
+/// Selector backed by `epoll`. By default the sockets are registered in edge-triggered mode for
+/// both read and write readiness, so there is no need to modify the registration once the
+/// connection has been established. In level-triggered mode the socket is first registered for
+/// write readiness only and then switched to read readiness once connected, the same way
+/// [`MioSelector`](crate::service::select::mio::MioSelector) does.
+///
+/// The `IOService` created from the selector only invokes the poll action for the endpoints with
+/// readiness (see [`IOService::with_ready_only`]), which can be turned off if the action has to
+/// run on every poll.
+pub struct EpollSelector<S> {
+    epoll: OwnedFd,
+    events: Vec<libc::epoll_event>,
+    ready: Vec<SelectorToken>,
+    next_token: u32,
+    edge_triggered: bool,
+    exclusive: bool,
+    phantom: PhantomData<S>,
+}
+
+impl<S> EpollSelector<S> {
+    pub fn new() -> io::Result<EpollSelector<S>> {
+        // SAFETY: no pointers are passed
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            // SAFETY: we have just created the descriptor and nothing else owns it
+            epoll: unsafe { OwnedFd::from_raw_fd(fd) },
+            events: Vec::with_capacity(DEFAULT_EVENTS_CAPACITY),
+            ready: Vec::new(),
+            next_token: 0,
+            edge_triggered: true,
+            exclusive: false,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Use edge-triggered (default) or level-triggered notifications.
+    pub fn with_edge_triggered(self, edge_triggered: bool) -> Self {
+        Self { edge_triggered, ..self }
+    }
+
+    /// Register the sockets with `EPOLLEXCLUSIVE` so that only one of the epoll instances waiting
+    /// on the same socket is woken up. The kernel does not allow exclusive registration to be
+    /// modified, so this is only supported in edge-triggered mode and `register` will fail
+    /// otherwise.
+    pub fn with_exclusive(self, exclusive: bool) -> Self {
+        Self { exclusive, ..self }
+    }
+
+    /// Maximum number of events returned by a single poll.
+    pub fn with_events_capacity(self, capacity: usize) -> Self {
+        Self {
+            events: Vec::with_capacity(capacity.max(1)),
+            ..self
+        }
+    }
+
+    /// Enable busy polling of the network device queues for up to `timeout` (with microsecond
+    /// precision) and at most `budget` packets per poll when there are no events ready. With
+    /// `prefer_busy_poll` the device interrupts are deferred while the application keeps polling.
+    /// Requires Linux 6.9 or newer.
+    pub fn with_busy_poll(self, timeout: Duration, budget: u16, prefer_busy_poll: bool) -> io::Result<Self> {
+        let params = EpollParams {
+            busy_poll_usecs: timeout.as_micros().min(u32::MAX as u128) as u32,
+            busy_poll_budget: budget,
+            prefer_busy_poll: prefer_busy_poll as u8,
+            pad: 0,
+        };
+        // SAFETY: params outlive the call and match the layout expected by the kernel
+        let rc = unsafe { libc::ioctl(self.epoll.as_raw_fd(), EPIOCSPARAMS, &params as *const EpollParams) };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(self)
+    }
+
+    #[inline]
+    fn ctl(&self, op: libc::c_int, fd: RawFd, events: u32, token: SelectorToken) -> io::Result<()> {
+        let mut event = libc::epoll_event {
+            events,
+            u64: token as u64,
+        };
+        // SAFETY: event is valid for the duration of the call
+        let rc = unsafe { libc::epoll_ctl(self.epoll.as_raw_fd(), op, fd, &mut event) };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl<S: Selectable + AsRawFd> Selector for EpollSelector<S> {
+    type Target = S;
+
+    fn register<E>(&mut self, selector_token: SelectorToken, io_node: &mut IONode<Self::Target, E>) -> io::Result<()> {
+        let events = match (self.edge_triggered, self.exclusive) {
+            // EPOLLRDHUP cannot be combined with EPOLLEXCLUSIVE, peer shutdown is still reported as EPOLLIN
+            (true, true) => libc::EPOLLIN | libc::EPOLLOUT | libc::EPOLLET | libc::EPOLLEXCLUSIVE,
+            (true, false) => libc::EPOLLIN | libc::EPOLLOUT | libc::EPOLLRDHUP | libc::EPOLLET,
+            (false, false) => libc::EPOLLOUT,
+            (false, true) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "EPOLLEXCLUSIVE requires edge-triggered mode"));
+            }
+        };
+        self.ctl(libc::EPOLL_CTL_ADD, io_node.as_stream().as_raw_fd(), events as u32, selector_token)
+    }
+
+    fn unregister<E>(&mut self, io_node: &mut IONode<Self::Target, E>) -> io::Result<()> {
+        self.ctl(libc::EPOLL_CTL_DEL, io_node.as_stream().as_raw_fd(), 0, 0)
+    }
+
+    fn poll<E>(&mut self, io_nodes: &mut NodeSlab<Self::Target, E>) -> io::Result<()> {
+        self.ready.clear();
+        // SAFETY: kernel writes at most `capacity` events into the buffer
+        let count = unsafe {
+            libc::epoll_wait(self.epoll.as_raw_fd(), self.events.as_mut_ptr(), self.events.capacity() as libc::c_int, 0)
+        };
+        if count < 0 {
+            let err = io::Error::last_os_error();
+            return match err.kind() {
+                io::ErrorKind::Interrupted => Ok(()),
+                _ => Err(err),
+            };
+        }
+        // SAFETY: kernel has initialised `count` events
+        unsafe { self.events.set_len(count as usize) };
+        for event in &self.events {
+            let token = event.u64 as SelectorToken;
+            self.ready.push(token);
+            let flags = event.events as libc::c_int;
+            let io_node = io_nodes
+                .get_mut(token)
+                .ok_or_else(|| io::Error::other("io node not found"))?;
+            let fd = io_node.as_stream().as_raw_fd();
+            let stream = io_node.as_stream_mut();
+            // failed connection is reported by the service when checking the connecting endpoints
+            if flags & libc::EPOLLOUT != 0 && stream.connected().unwrap_or(false) {
+                stream.make_writable()?;
+                if !self.edge_triggered {
+                    self.ctl(libc::EPOLL_CTL_MOD, fd, libc::EPOLLIN as u32, token)?;
+                }
+            }
+            // errors and hang-ups are surfaced by the next read
+            if flags & (libc::EPOLLIN | libc::EPOLLRDHUP | libc::EPOLLHUP | libc::EPOLLERR) != 0 {
+                stream.make_readable()?;
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn next_token(&mut self) -> SelectorToken {
+        let token = self.next_token;
+        self.next_token += 1;
+        token
+    }
+
+    #[inline]
+    fn ready(&self) -> Option<&[SelectorToken]> {
+        Some(&self.ready)
+    }
+}
+
+impl<E: Endpoint> IntoIOService<E> for EpollSelector<E::Target> {
+    fn into_io_service(self) -> IOService<Self, E, (), SystemTimeClockSource, BlockingDnsResolver>
+    where
+        Self: Selector,
+        Self: Sized,
+    {
+        IOService::new(self, SystemTimeClockSource, BlockingDnsResolver).with_ready_only(true)
+    }
+}
+
+impl<C: Context, E: EndpointWithContext<C>> IntoIOServiceWithContext<E, C> for EpollSelector<E::Target> {
+    fn into_io_service_with_context(self) -> IOService<Self, E, C, SystemTimeClockSource, BlockingDnsResolver>
+    where
+        Self: Selector,
+        Self: Sized,
+    {
+        IOService::new(self, SystemTimeClockSource, BlockingDnsResolver).with_ready_only(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::endpoint::DisconnectReason;
+    use crate::stream::tcp::TcpStream;
+    use crate::testing::{EchoEndpoint, StaticDnsResolver, receive_all, roundtrip, serve};
+    use std::net::TcpListener;
+
+    #[test]
+    fn should_poll_in_edge_triggered_mode() {
+        assert_eq!(b"hello", roundtrip(EpollSelector::<TcpStream>::new().unwrap(), b"hello").as_slice());
+    }
+
+    #[test]
+    fn should_poll_in_level_triggered_mode() {
+        assert_eq!(
+            b"hello",
+            roundtrip(EpollSelector::<TcpStream>::new().unwrap().with_edge_triggered(false), b"hello").as_slice()
+        );
+    }
+
+    #[test]
+    fn should_poll_with_exclusive_registration() {
+        assert_eq!(
+            b"hello",
+            roundtrip(EpollSelector::<TcpStream>::new().unwrap().with_exclusive(true), b"hello").as_slice()
+        );
+    }
+
+    #[test]
+    fn should_try_next_address_when_connection_refused() {
+        let refused = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let addr = serve(b"hello");
+        let mut io_service = EpollSelector::new()
+            .unwrap()
+            .into_io_service()
+            .with_dns_resolver(StaticDnsResolver(vec![refused, addr]));
+        io_service
+            .register(EchoEndpoint::<TcpStream>::new(addr.port()))
+            .unwrap();
+        assert_eq!(b"hello", receive_all(&mut io_service).as_slice());
+    }
+
+    #[test]
+    fn should_only_poll_endpoints_with_readiness() {
+        // connection is established by the listener backlog, the peer never sends anything
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut io_service = EpollSelector::<TcpStream>::new().unwrap().into_io_service();
+        io_service.register(EchoEndpoint::<TcpStream>::new(port)).unwrap();
+        while io_service.iter().count() == 0 || !io_service.connecting.is_empty() {
+            io_service.poll(|_, _| Ok(())).unwrap();
+        }
+
+        // the endpoint is polled once connected and then not again until there is readiness
+        let mut polled = 0;
+        for _ in 0..10 {
+            io_service
+                .poll(|_, _| {
+                    polled += 1;
+                    Ok(())
+                })
+                .unwrap();
+        }
+        assert!(polled <= 1, "idle endpoint polled {polled} times");
+    }
+
+    #[test]
+    fn should_keep_polling_endpoint_reading_once_per_poll() {
+        use std::io::{Read, Write};
+        use std::time::Instant;
+
+        const PAYLOAD: &[u8] = &[42; 64];
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(PAYLOAD).unwrap();
+            // keep the connection open so that no more readiness is reported
+            let _ = stream.read(&mut [0u8; 1]);
+        });
+
+        let mut io_service = EpollSelector::<TcpStream>::new().unwrap().into_io_service();
+        io_service.register(EchoEndpoint::<TcpStream>::new(port)).unwrap();
+
+        let mut received = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while received.len() < PAYLOAD.len() && Instant::now() < deadline {
+            io_service
+                .poll(|stream, _| {
+                    let mut buf = [0u8; 16];
+                    match stream.read(&mut buf) {
+                        Ok(read) => received.extend_from_slice(&buf[..read]),
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                        Err(err) => return Err(err),
+                    }
+                    Ok(())
+                })
+                .unwrap();
+        }
+        assert_eq!(PAYLOAD, received.as_slice());
+    }
+
+    #[cfg(feature = "ws")]
+    #[test]
+    fn should_complete_websocket_handshake_when_polling_ready_endpoints_only() {
+        use crate::ws::{Websocket, WebsocketFrame};
+        use std::time::Instant;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut ws = Websocket::accept(stream);
+            while !ws.handshake_complete() {
+                ws.receive_next().transpose().unwrap();
+            }
+            ws.send_text(true, Some(b"hello")).unwrap();
+            // keep the connection open until the client goes away
+            while ws.read_batch().is_ok() {}
+        });
+
+        let mut io_service = EpollSelector::<Websocket<TcpStream>>::new().unwrap().into_io_service();
+        assert!(io_service.ready_only);
+        io_service
+            .register(EchoEndpoint::<Websocket<TcpStream>>::new(port))
+            .unwrap();
+
+        let mut received = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while received.is_empty() && Instant::now() < deadline {
+            io_service
+                .poll(|ws, _| {
+                    for frame in ws.read_batch()? {
+                        if let WebsocketFrame::Text(_, data) = frame? {
+                            received.extend_from_slice(data);
+                        }
+                    }
+                    Ok(())
+                })
+                .unwrap();
+        }
+        assert_eq!(b"hello", received.as_slice());
+    }
+
+    #[test]
+    fn should_reject_exclusive_registration_in_level_triggered_mode() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut io_service = EpollSelector::new()
+            .unwrap()
+            .with_edge_triggered(false)
+            .with_exclusive(true)
+            .into_io_service();
+        let mut endpoint = EchoEndpoint::<TcpStream>::new(port);
+        endpoint.recreate = false;
+        io_service.register(endpoint).unwrap();
+        io_service.poll(|_, _| Ok(())).unwrap();
+        let (_, _, reason) = io_service.drain_removed().next().unwrap();
+        assert!(matches!(reason, DisconnectReason::IO(err) if err.kind() == io::ErrorKind::InvalidInput));
+    }
+}
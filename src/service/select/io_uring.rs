@@ -0,0 +1,369 @@
+//! Selector that uses Linux `io_uring` to receive data on behalf of the streams.
+//!
+//! Unlike readiness based selectors, the kernel performs the receive itself using multishot
+//! `recv` operations that pick buffers from a ring shared with the kernel. The buffer holding the
+//! received bytes is handed over to the stream through [`IoUringSource`] and replaced in the ring
+//! with another one from the [`BufferPoolRef`]. Multishot receive with provided buffer rings
+//! requires Linux 6.0 or newer.
+
+use std::alloc::{Layout, alloc_zeroed, dealloc};
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::os::fd::AsRawFd;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU16, Ordering};
+
+use io_uring::types::{BufRingEntry, CancelBuilder, Fd};
+use io_uring::{IoUring, cqueue, opcode};
+
+use crate::buffer::{BufferPoolRef, ReadBuffer, default_buffer_pool_ref};
+use crate::service::dns::BlockingDnsResolver;
+use crate::service::endpoint::{Context, Endpoint, EndpointWithContext};
+use crate::service::node::{IONode, NodeSlab};
+use crate::service::select::{Selectable, Selector, SelectorToken};
+use crate::service::time::SystemTimeClockSource;
+use crate::service::{Handle, IOService, IntoIOService, IntoIOServiceWithContext};
+use crate::stream::io_uring::{IoUringSource, RecvBuffer};
+
+const DEFAULT_ENTRIES: u32 = 256;
+const DEFAULT_BUFFERS: u16 = 256;
+const DEFAULT_BUFFER_SIZE: usize = 4096;
+
+/// Buffer group id of the provided buffer ring.
+const BUFFER_GROUP: u16 = 0;
+
+/// User data of the completions that do not belong to any stream.
+const CANCEL_USER_DATA: u64 = u64::MAX;
+
+/// Ring of buffers registered with the kernel, the multishot `recv` picks the next available
+/// buffer and the selector hands it over to the stream, replacing it with a buffer from the pool.
+struct BufRing {
+    ring: NonNull<BufRingEntry>,
+    layout: Layout,
+    // the kernel writes into the heap memory of the buffers through the addresses published to
+    // the ring, so the buffer contents must not be borrowed until the completion has been reaped
+    buffers: Box<[Vec<u8>]>,
+    pool: BufferPoolRef,
+    buffer_size: usize,
+    tail: u16,
+    mask: u16,
+}
+
+impl BufRing {
+    fn new(entries: u16, buffer_size: usize, pool: BufferPoolRef) -> io::Result<Self> {
+        if !entries.is_power_of_two() || entries > 1 << 15 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "number of buffers must be a power of two not greater than 32768",
+            ));
+        }
+        if buffer_size == 0 || buffer_size > u32::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid buffer size"));
+        }
+        // the ring has to be page aligned
+        let layout = Layout::from_size_align(entries as usize * size_of::<BufRingEntry>(), 4096)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid buffer ring layout"))?;
+        // SAFETY: layout has non-zero size
+        let ring = NonNull::new(unsafe { alloc_zeroed(layout) } as *mut BufRingEntry)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::OutOfMemory))?;
+        let mut buf_ring = Self {
+            ring,
+            layout,
+            buffers: (0..entries).map(|_| pool.acquire_bytes(buffer_size)).collect(),
+            pool,
+            buffer_size,
+            tail: 0,
+            mask: entries - 1,
+        };
+        for bid in 0..entries {
+            buf_ring.push(bid);
+        }
+        buf_ring.publish();
+        Ok(buf_ring)
+    }
+
+    #[inline]
+    const fn entries(&self) -> u16 {
+        self.mask + 1
+    }
+
+    /// Take the buffer the kernel has received `len` bytes into and give the ring a new one.
+    #[inline]
+    fn take(&mut self, bid: u16, len: usize) -> RecvBuffer {
+        let bytes = mem::replace(&mut self.buffers[bid as usize], self.pool.acquire_bytes(self.buffer_size));
+        self.recycle(bid);
+        self.pool.wrap(ReadBuffer::from_filled(bytes, len))
+    }
+
+    /// Return the buffer to the ring.
+    #[inline]
+    fn recycle(&mut self, bid: u16) {
+        self.push(bid);
+        self.publish();
+    }
+
+    #[inline]
+    fn push(&mut self, bid: u16) {
+        // the address is derived from the exclusive borrow as the kernel is going to write to it
+        let addr = self.buffers[bid as usize].as_mut_ptr() as u64;
+        // SAFETY: index is masked so it is always within the ring allocation
+        let entry = unsafe { &mut *self.ring.as_ptr().add((self.tail & self.mask) as usize) };
+        entry.set_addr(addr);
+        entry.set_len(self.buffer_size as u32);
+        entry.set_bid(bid);
+        self.tail = self.tail.wrapping_add(1);
+    }
+
+    #[inline]
+    fn publish(&self) {
+        // SAFETY: the tail overlaps the first entry which is valid for the lifetime of the ring,
+        // the kernel reads it concurrently so it has to be updated atomically
+        unsafe {
+            let tail = BufRingEntry::tail(self.ring.as_ptr()) as *const AtomicU16;
+            (*tail).store(self.tail, Ordering::Release);
+        }
+    }
+}
+
+impl Drop for BufRing {
+    fn drop(&mut self) {
+        // SAFETY: allocated in `BufRing::new` with the same layout
+        unsafe { dealloc(self.ring.as_ptr() as *mut u8, self.layout) }
+    }
+}
+
+/// Selector backed by `io_uring`. Each registered stream has a multishot `recv` armed for its
+/// socket, completions are delivered to the stream via [`IoUringSource::on_recv`] when the
+/// selector is polled, so the subsequent read on the stream does not need to enter the kernel.
+/// Writes are not submitted through the ring and are performed directly by the stream.
+pub struct IoUringSelector<S> {
+    // declared first so that the ring is closed before the buffers are released
+    ring: IoUring,
+    buf_ring: BufRing,
+    rearm: Vec<SelectorToken>,
+    ready: Vec<SelectorToken>,
+    next_token: u32,
+    phantom: PhantomData<S>,
+}
+
+impl<S> IoUringSelector<S> {
+    pub fn new() -> io::Result<IoUringSelector<S>> {
+        Self::with_capacity(DEFAULT_ENTRIES, DEFAULT_BUFFERS, DEFAULT_BUFFER_SIZE)
+    }
+
+    /// Create selector with submission queue of `entries` and `buffers` provided buffers of
+    /// `buffer_size` bytes each, the number of buffers must be a power of two. The buffers are
+    /// acquired from the default per-thread buffer pool.
+    pub fn with_capacity(entries: u32, buffers: u16, buffer_size: usize) -> io::Result<IoUringSelector<S>> {
+        Self::with_buffer_pool(entries, buffers, buffer_size, default_buffer_pool_ref())
+    }
+
+    /// Same as [`IoUringSelector::with_capacity`] but the provided buffers are acquired from the `pool`.
+    pub fn with_buffer_pool(
+        entries: u32,
+        buffers: u16,
+        buffer_size: usize,
+        pool: BufferPoolRef,
+    ) -> io::Result<IoUringSelector<S>> {
+        let ring = IoUring::new(entries)?;
+        let buf_ring = BufRing::new(buffers, buffer_size, pool)?;
+        // SAFETY: the ring memory stays valid until it is unregistered when the selector is dropped
+        unsafe {
+            ring.submitter().register_buf_ring_with_flags(
+                buf_ring.ring.as_ptr() as u64,
+                buf_ring.entries(),
+                BUFFER_GROUP,
+                0,
+            )?;
+        }
+        Ok(Self {
+            ring,
+            buf_ring,
+            rearm: Vec::new(),
+            ready: Vec::new(),
+            next_token: 0,
+            phantom: PhantomData,
+        })
+    }
+
+    #[inline]
+    fn push(&mut self, entry: &io_uring::squeue::Entry) -> io::Result<()> {
+        // SAFETY: the entries do not reference any user memory other than the registered buffer ring
+        if unsafe { self.ring.submission().push(entry) }.is_err() {
+            // make space by submitting what is already queued
+            self.ring.submit()?;
+            unsafe { self.ring.submission().push(entry) }
+                .map_err(|_| io::Error::other("io_uring submission queue is full"))?;
+        }
+        Ok(())
+    }
+
+    /// Arm multishot receive, the user data carries the handle generation next to the token so
+    /// that late completions of the previous connection are not delivered to the one that has
+    /// reused the token.
+    #[inline]
+    fn arm_recv(&mut self, handle: Handle, fd: Fd) -> io::Result<()> {
+        let user_data = (handle.generation() as u64) << 32 | handle.token() as u64;
+        let entry = opcode::RecvMulti::new(fd, BUFFER_GROUP).build().user_data(user_data);
+        self.push(&entry)
+    }
+}
+
+impl<S> Drop for IoUringSelector<S> {
+    fn drop(&mut self) {
+        let _ = self.ring.submitter().unregister_buf_ring(BUFFER_GROUP);
+    }
+}
+
+impl<S: Selectable + AsRawFd + IoUringSource> Selector for IoUringSelector<S> {
+    type Target = S;
+
+    fn register<E>(&mut self, _selector_token: SelectorToken, io_node: &mut IONode<Self::Target, E>) -> io::Result<()> {
+        self.arm_recv(io_node.as_endpoint().0, Fd(io_node.as_stream().as_raw_fd()))?;
+        self.ring.submit()?;
+        Ok(())
+    }
+
+    fn unregister<E>(&mut self, io_node: &mut IONode<Self::Target, E>) -> io::Result<()> {
+        let fd = Fd(io_node.as_stream().as_raw_fd());
+        let entry = opcode::AsyncCancel2::new(CancelBuilder::fd(fd).all())
+            .build()
+            .user_data(CANCEL_USER_DATA);
+        self.push(&entry)?;
+        self.ring.submit()?;
+        Ok(())
+    }
+
+    fn poll<E>(&mut self, io_nodes: &mut NodeSlab<Self::Target, E>) -> io::Result<()> {
+        // any completions that are already available are reaped without entering the kernel
+        if self.ring.completion().is_empty() {
+            self.ring.submit()?;
+        }
+        self.ready.clear();
+        let Self {
+            ring,
+            buf_ring,
+            rearm,
+            ready,
+            ..
+        } = self;
+        for cqe in ring.completion() {
+            if cqe.user_data() == CANCEL_USER_DATA {
+                continue;
+            }
+            let token = cqe.user_data() as SelectorToken;
+            let generation = (cqe.user_data() >> 32) as u32;
+            let result = cqe.result();
+            let flags = cqe.flags();
+            // completions of streams that have been unregistered still need their buffer back
+            let io_node = io_nodes
+                .get_mut(token)
+                .filter(|io_node| io_node.as_endpoint().0.generation() == generation);
+            if io_node.is_some() {
+                ready.push(token);
+            }
+            let resubmit = match (io_node, cqueue::buffer_select(flags)) {
+                (Some(io_node), Some(bid)) => {
+                    io_node
+                        .as_stream_mut()
+                        .on_recv(Ok(Some(buf_ring.take(bid, result as usize))));
+                    true
+                }
+                (None, Some(bid)) => {
+                    buf_ring.recycle(bid);
+                    false
+                }
+                (Some(io_node), None) => match result {
+                    0 => {
+                        io_node.as_stream_mut().on_recv(Ok(None));
+                        false
+                    }
+                    // the ring has run out of buffers, the receive has to be submitted again
+                    err if -err == libc::ENOBUFS => true,
+                    err => {
+                        io_node.as_stream_mut().on_recv(Err(io::Error::from_raw_os_error(-err)));
+                        false
+                    }
+                },
+                (None, None) => false,
+            };
+            // multishot receive has terminated
+            if resubmit && !cqueue::more(flags) {
+                rearm.push(token);
+            }
+        }
+        if !self.rearm.is_empty() {
+            for i in 0..self.rearm.len() {
+                let io_node = &io_nodes[self.rearm[i]];
+                self.arm_recv(io_node.as_endpoint().0, Fd(io_node.as_stream().as_raw_fd()))?;
+            }
+            self.rearm.clear();
+            self.ring.submit()?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn next_token(&mut self) -> SelectorToken {
+        let token = self.next_token;
+        self.next_token += 1;
+        token
+    }
+
+    #[inline]
+    fn ready(&self) -> Option<&[SelectorToken]> {
+        Some(&self.ready)
+    }
+}
+
+impl<E: Endpoint> IntoIOService<E> for IoUringSelector<E::Target> {
+    fn into_io_service(self) -> IOService<Self, E, (), SystemTimeClockSource, BlockingDnsResolver>
+    where
+        Self: Selector,
+        Self: Sized,
+    {
+        IOService::new(self, SystemTimeClockSource, BlockingDnsResolver)
+    }
+}
+
+impl<C: Context, E: EndpointWithContext<C>> IntoIOServiceWithContext<E, C> for IoUringSelector<E::Target> {
+    fn into_io_service_with_context(self) -> IOService<Self, E, C, SystemTimeClockSource, BlockingDnsResolver>
+    where
+        Self: Selector,
+        Self: Sized,
+    {
+        IOService::new(self, SystemTimeClockSource, BlockingDnsResolver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::io_uring::IoUringStream;
+    use crate::stream::tcp::TcpStream;
+    use crate::testing::roundtrip;
+
+    #[test]
+    fn should_receive_until_peer_closes() {
+        let received = roundtrip(IoUringSelector::<IoUringStream<TcpStream>>::new().unwrap(), b"hello");
+        assert_eq!(b"hello", received.as_slice());
+    }
+
+    #[test]
+    fn should_resubmit_recv_when_buffers_run_out() {
+        let payload = [7u8; 64 * 1024];
+        let payload: &'static [u8] = Box::leak(Box::new(payload));
+        let received =
+            roundtrip(IoUringSelector::<IoUringStream<TcpStream>>::with_capacity(8, 2, 64).unwrap(), payload);
+        assert_eq!(payload, received.as_slice());
+    }
+
+    #[test]
+    fn should_reject_buffer_count_that_is_not_power_of_two() {
+        let err = IoUringSelector::<IoUringStream<TcpStream>>::with_capacity(8, 3, 64)
+            .err()
+            .unwrap();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+}
@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 use std::io;
 use std::marker::PhantomData;
 use std::time::Duration;
@@ -8,7 +7,7 @@ use mio::{Events, Interest, Poll, Token};
 
 use crate::service::dns::BlockingDnsResolver;
 use crate::service::endpoint::{Context, Endpoint, EndpointWithContext};
-use crate::service::node::IONode;
+use crate::service::node::{IONode, NodeSlab};
 use crate::service::select::{Selectable, Selector, SelectorToken};
 use crate::service::time::SystemTimeClockSource;
 use crate::service::{IOService, IntoIOService, IntoIOServiceWithContext};
@@ -18,6 +17,7 @@ const NO_WAIT: Option<Duration> = Some(Duration::from_millis(0));
 pub struct MioSelector<S> {
     poll: Poll,
     events: Events,
+    ready: Vec<SelectorToken>,
     next_token: u32,
     phantom: PhantomData<S>,
 }
@@ -27,6 +27,7 @@ impl<S> MioSelector<S> {
         Ok(Self {
             poll: Poll::new()?,
             events: Events::with_capacity(1024),
+            ready: Vec::new(),
             next_token: 0,
             phantom: PhantomData,
         })
@@ -48,15 +49,18 @@ impl<S: Source + Selectable> Selector for MioSelector<S> {
         self.poll.registry().deregister(io_node.as_stream_mut())
     }
 
-    fn poll<E>(&mut self, io_nodes: &mut HashMap<SelectorToken, IONode<Self::Target, E>>) -> io::Result<()> {
+    fn poll<E>(&mut self, io_nodes: &mut NodeSlab<Self::Target, E>) -> io::Result<()> {
         self.poll.poll(&mut self.events, NO_WAIT)?;
+        self.ready.clear();
         for ev in self.events.iter() {
             let token = ev.token();
+            self.ready.push(token.0 as SelectorToken);
             let stream = io_nodes
-                .get_mut(&(token.0 as SelectorToken))
+                .get_mut(token.0 as SelectorToken)
                 .ok_or_else(|| io::Error::other("io node not found"))?
                 .as_stream_mut();
-            if ev.is_writable() && stream.connected()? {
+            // failed connection is reported by the service when checking the connecting endpoints
+            if ev.is_writable() && stream.connected().unwrap_or(false) {
                 stream.make_writable()?;
                 self.poll.registry().reregister(stream, token, Interest::READABLE)?;
             }
@@ -73,6 +77,11 @@ impl<S: Source + Selectable> Selector for MioSelector<S> {
         self.next_token += 1;
         token
     }
+
+    #[inline]
+    fn ready(&self) -> Option<&[SelectorToken]> {
+        Some(&self.ready)
+    }
 }
 
 impl<E: Endpoint> IntoIOService<E> for MioSelector<E::Target> {
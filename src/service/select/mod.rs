@@ -1,10 +1,13 @@
 //! OS specific socket event notification mechanisms like `epoll`.
 
-use crate::service::node::IONode;
-use std::collections::HashMap;
+use crate::service::node::{IONode, NodeSlab};
 use std::io;
 
 pub mod direct;
+#[cfg(all(feature = "epoll", target_os = "linux"))]
+pub mod epoll;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub mod io_uring;
 #[cfg(feature = "mio")]
 pub mod mio;
 
@@ -17,6 +20,14 @@ pub trait Selectable {
     fn make_writable(&mut self) -> io::Result<()>;
 
     fn make_readable(&mut self) -> io::Result<()>;
+
+    /// Returns `true` if the stream has buffered data that will not be reported by the selector,
+    /// such as writes that have not been flushed yet or bytes that have been received but not
+    /// read. Such streams are polled by the [`IOService`](crate::service::IOService) even when
+    /// the selector has not reported them as ready.
+    fn has_pending_io(&self) -> bool {
+        false
+    }
 }
 
 pub trait Selector {
@@ -26,7 +37,14 @@ pub trait Selector {
 
     fn unregister<E>(&mut self, io_node: &mut IONode<Self::Target, E>) -> io::Result<()>;
 
-    fn poll<E>(&mut self, io_nodes: &mut HashMap<SelectorToken, IONode<Self::Target, E>>) -> io::Result<()>;
+    fn poll<E>(&mut self, io_nodes: &mut NodeSlab<Self::Target, E>) -> io::Result<()>;
 
     fn next_token(&mut self) -> SelectorToken;
+
+    /// Tokens of the sockets that have had events during the last [`Selector::poll`], in no
+    /// particular order. Selectors that do not track readiness return `None`, in which case every
+    /// socket has to be treated as ready.
+    fn ready(&self) -> Option<&[SelectorToken]> {
+        None
+    }
 }
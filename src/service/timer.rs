@@ -0,0 +1,342 @@
+//! Hashed timer wheel used by the [`IOService`](crate::service::IOService) to drive scheduled
+//! callbacks.
+//!
+//! Timers are bucketed into slots by their deadline rounded down to the wheel resolution, so
+//! scheduling and cancelling is `O(1)` while expiring only visits the slots the time has moved
+//! past since the last call. Timers that are further in the future than one revolution of the
+//! wheel stay in their slot until the wheel comes around enough times. Expired timers that can not
+//! fire yet can be parked outside the wheel until they are explicitly rescheduled.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Default resolution of the timer wheel.
+pub const DEFAULT_TIMER_RESOLUTION: Duration = Duration::from_millis(1);
+
+/// Default number of slots in the timer wheel.
+const DEFAULT_SLOTS: usize = 512;
+
+/// Tick recorded for the timers that have been parked.
+const PARKED_TICK: u64 = u64::MAX;
+
+/// Identifies a scheduled timer so that it can be cancelled.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(transparent)]
+pub struct TimerId(u64);
+
+/// Timer that has been scheduled with the [`TimerWheel`].
+#[derive(Debug)]
+pub struct Timer<T> {
+    id: TimerId,
+    deadline_ns: u64,
+    period_ns: Option<u64>,
+    payload: T,
+}
+
+impl<T> Timer<T> {
+    /// Get timer id.
+    pub const fn id(&self) -> TimerId {
+        self.id
+    }
+
+    /// Time at which the timer is due.
+    pub const fn deadline_ns(&self) -> u64 {
+        self.deadline_ns
+    }
+
+    /// Returns `true` if the timer will be rearmed after it has expired.
+    pub const fn is_periodic(&self) -> bool {
+        self.period_ns.is_some()
+    }
+
+    /// Get the user payload.
+    pub const fn payload(&self) -> &T {
+        &self.payload
+    }
+
+    /// Get mutable reference to the user payload.
+    pub fn payload_mut(&mut self) -> &mut T {
+        &mut self.payload
+    }
+}
+
+/// Hashed timer wheel holding one-shot and periodic timers with arbitrary payload.
+pub struct TimerWheel<T> {
+    slots: Vec<Vec<Timer<T>>>,
+    resolution_ns: u64,
+    /// Next tick that has not been processed yet.
+    current_tick: u64,
+    /// Tick each of the scheduled timers has been placed at.
+    ticks: HashMap<TimerId, u64>,
+    /// Timers that are not due until rescheduled with [`TimerWheel::unpark`].
+    parked: Vec<Timer<T>>,
+    next_id: u64,
+}
+
+impl<T> Default for TimerWheel<T> {
+    fn default() -> Self {
+        Self::with_resolution(DEFAULT_TIMER_RESOLUTION)
+    }
+}
+
+impl<T> TimerWheel<T> {
+    /// Create timer wheel with given `resolution` and number of `slots`. Timers can fire up to
+    /// `resolution` later than requested.
+    pub fn new(resolution: Duration, slots: usize) -> Self {
+        assert!(slots > 0, "timer wheel must have at least one slot");
+        Self {
+            slots: (0..slots).map(|_| Vec::new()).collect(),
+            resolution_ns: (resolution.as_nanos() as u64).max(1),
+            current_tick: 0,
+            ticks: HashMap::new(),
+            parked: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Create timer wheel with given `resolution` and the default number of slots.
+    pub fn with_resolution(resolution: Duration) -> Self {
+        Self::new(resolution, DEFAULT_SLOTS)
+    }
+
+    /// Number of scheduled timers, including the parked ones.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    /// Returns `true` if there are no scheduled timers, including the parked ones.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    /// Returns `true` if any of the timers could be due at `now`, parked timers are never due.
+    #[inline]
+    pub fn is_due(&self, now_ns: u64) -> bool {
+        self.ticks.len() > self.parked.len() && now_ns >= self.current_tick.saturating_mul(self.resolution_ns)
+    }
+
+    /// Schedule timer that fires once at `deadline_ns`.
+    pub fn schedule(&mut self, deadline_ns: u64, payload: T) -> TimerId {
+        self.insert(deadline_ns, None, payload)
+    }
+
+    /// Schedule timer that fires at `deadline_ns` and then every `period` until cancelled.
+    pub fn schedule_periodic(&mut self, deadline_ns: u64, period: Duration, payload: T) -> TimerId {
+        let period_ns = (period.as_nanos() as u64).max(1);
+        self.insert(deadline_ns, Some(period_ns), payload)
+    }
+
+    /// Cancel the timer, returns its payload if the timer was still scheduled.
+    pub fn cancel(&mut self, id: TimerId) -> Option<T> {
+        let tick = self.ticks.remove(&id)?;
+        if tick == PARKED_TICK {
+            let index = self.parked.iter().position(|timer| timer.id == id)?;
+            return Some(self.parked.swap_remove(index).payload);
+        }
+        let index = self.slot(tick);
+        let slot = &mut self.slots[index];
+        let index = slot.iter().position(|timer| timer.id == id)?;
+        Some(slot.swap_remove(index).payload)
+    }
+
+    /// Move all timers that are due at `now_ns` into `expired`, in no particular order. Periodic
+    /// timers have to be passed back to [`TimerWheel::rearm`] once they have been handled.
+    pub fn expire(&mut self, now_ns: u64, expired: &mut Vec<Timer<T>>) {
+        let now_tick = now_ns / self.resolution_ns;
+        if now_tick < self.current_tick {
+            return;
+        }
+        // no need to visit any slot more than once
+        let first_tick = self
+            .current_tick
+            .max((now_tick + 1).saturating_sub(self.slots.len() as u64));
+        for tick in first_tick..=now_tick {
+            let index = self.slot(tick);
+            let slot = &mut self.slots[index];
+            let mut i = 0;
+            while i < slot.len() {
+                if slot[i].deadline_ns / self.resolution_ns <= now_tick {
+                    let timer = slot.swap_remove(i);
+                    self.ticks.remove(&timer.id);
+                    expired.push(timer);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        self.current_tick = now_tick + 1;
+    }
+
+    /// Schedule expired periodic timer again, one period after its previous deadline or, if the
+    /// timer has fallen behind, one period from `now_ns`. One-shot timers are dropped and `false`
+    /// is returned.
+    pub fn rearm(&mut self, mut timer: Timer<T>, now_ns: u64) -> bool {
+        let Some(period_ns) = timer.period_ns else {
+            return false;
+        };
+        timer.deadline_ns = timer.deadline_ns.saturating_add(period_ns);
+        if timer.deadline_ns <= now_ns {
+            timer.deadline_ns = now_ns.saturating_add(period_ns);
+        }
+        self.place(timer);
+        true
+    }
+
+    /// Schedule expired timer again at `deadline_ns` keeping its id and period.
+    pub fn defer(&mut self, mut timer: Timer<T>, deadline_ns: u64) {
+        timer.deadline_ns = deadline_ns;
+        self.place(timer);
+    }
+
+    /// Keep expired timer outside the wheel, keeping its id and period, until it is rescheduled
+    /// with [`TimerWheel::unpark`] or dropped with [`TimerWheel::retain_parked`].
+    pub fn park(&mut self, timer: Timer<T>) {
+        self.ticks.insert(timer.id, PARKED_TICK);
+        self.parked.push(timer);
+    }
+
+    /// Schedule parked timers for which `unpark` returns `true` to fire at `now_ns`.
+    pub fn unpark(&mut self, now_ns: u64, mut unpark: impl FnMut(&T) -> bool) {
+        let mut i = 0;
+        while i < self.parked.len() {
+            if unpark(&self.parked[i].payload) {
+                let timer = self.parked.swap_remove(i);
+                self.defer(timer, now_ns);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Drop parked timers for which `retain` returns `false`.
+    pub fn retain_parked(&mut self, mut retain: impl FnMut(&T) -> bool) {
+        let ticks = &mut self.ticks;
+        self.parked.retain(|timer| {
+            let retained = retain(&timer.payload);
+            if !retained {
+                ticks.remove(&timer.id);
+            }
+            retained
+        });
+    }
+
+    fn insert(&mut self, deadline_ns: u64, period_ns: Option<u64>, payload: T) -> TimerId {
+        let id = TimerId(self.next_id);
+        self.next_id += 1;
+        self.place(Timer {
+            id,
+            deadline_ns,
+            period_ns,
+            payload,
+        });
+        id
+    }
+
+    fn place(&mut self, timer: Timer<T>) {
+        // timers in the past are picked up with the next tick
+        let tick = (timer.deadline_ns / self.resolution_ns).max(self.current_tick);
+        self.ticks.insert(timer.id, tick);
+        let index = self.slot(tick);
+        self.slots[index].push(timer);
+    }
+
+    #[inline]
+    fn slot(&self, tick: u64) -> usize {
+        (tick % self.slots.len() as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    fn expire(wheel: &mut TimerWheel<&'static str>, now_ns: u64) -> Vec<&'static str> {
+        let mut expired = Vec::new();
+        wheel.expire(now_ns, &mut expired);
+        let mut payloads: Vec<_> = expired.iter().map(|timer| *timer.payload()).collect();
+        payloads.sort();
+        for timer in expired {
+            wheel.rearm(timer, now_ns);
+        }
+        payloads
+    }
+
+    #[test]
+    fn should_expire_timers_in_order() {
+        let mut wheel = TimerWheel::new(Duration::from_millis(1), 8);
+        wheel.schedule(5 * MS, "a");
+        wheel.schedule(10 * MS, "b");
+        // beyond one revolution of the wheel
+        wheel.schedule(100 * MS, "c");
+        assert_eq!(3, wheel.len());
+
+        assert!(expire(&mut wheel, 4 * MS).is_empty());
+        assert_eq!(vec!["a"], expire(&mut wheel, 5 * MS));
+        assert_eq!(vec!["b"], expire(&mut wheel, 50 * MS));
+        assert!(expire(&mut wheel, 99 * MS).is_empty());
+        assert_eq!(vec!["c"], expire(&mut wheel, 1000 * MS));
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn should_rearm_periodic_timer() {
+        let mut wheel = TimerWheel::new(Duration::from_millis(1), 8);
+        wheel.schedule_periodic(10 * MS, Duration::from_millis(10), "tick");
+
+        assert_eq!(vec!["tick"], expire(&mut wheel, 10 * MS));
+        assert!(expire(&mut wheel, 19 * MS).is_empty());
+        assert_eq!(vec!["tick"], expire(&mut wheel, 20 * MS));
+        // missed periods are skipped rather than fired in a burst
+        assert_eq!(vec!["tick"], expire(&mut wheel, 75 * MS));
+        assert!(expire(&mut wheel, 84 * MS).is_empty());
+        assert_eq!(vec!["tick"], expire(&mut wheel, 85 * MS));
+        assert_eq!(1, wheel.len());
+    }
+
+    #[test]
+    fn should_cancel_timer() {
+        let mut wheel = TimerWheel::new(Duration::from_millis(1), 8);
+        let id = wheel.schedule(5 * MS, "a");
+        wheel.schedule(5 * MS, "b");
+        assert_eq!(Some("a"), wheel.cancel(id));
+        assert_eq!(None, wheel.cancel(id));
+        assert_eq!(vec!["b"], expire(&mut wheel, 5 * MS));
+    }
+
+    #[test]
+    fn should_not_fire_parked_timers_until_unparked() {
+        let mut wheel = TimerWheel::new(Duration::from_millis(1), 8);
+        wheel.schedule(5 * MS, "a");
+        wheel.schedule(5 * MS, "b");
+        let cancelled = wheel.schedule(5 * MS, "c");
+        let mut expired = Vec::new();
+        wheel.expire(5 * MS, &mut expired);
+        for timer in expired {
+            wheel.park(timer);
+        }
+        assert_eq!(3, wheel.len());
+        assert!(!wheel.is_due(100 * MS));
+        assert!(expire(&mut wheel, 100 * MS).is_empty());
+
+        assert_eq!(Some("c"), wheel.cancel(cancelled));
+        wheel.unpark(200 * MS, |payload| *payload == "a");
+        assert!(wheel.is_due(200 * MS));
+        assert_eq!(vec!["a"], expire(&mut wheel, 200 * MS));
+
+        wheel.retain_parked(|payload| *payload != "b");
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn should_fire_timer_scheduled_in_the_past_on_next_tick() {
+        let mut wheel = TimerWheel::new(Duration::from_millis(1), 8);
+        assert!(expire(&mut wheel, 100 * MS).is_empty());
+        wheel.schedule(50 * MS, "late");
+        assert!(wheel.is_due(101 * MS));
+        assert_eq!(vec!["late"], expire(&mut wheel, 101 * MS));
+    }
+}
@@ -1,12 +1,15 @@
 //! Stream that is buffering data written to it.
 
 use crate::service::select::Selectable;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use crate::stream::io_uring::{IoUringSource, RecvBuffer};
 use crate::stream::{ConnectionInfo, ConnectionInfoProvider};
 #[cfg(feature = "mio")]
 use mio::{Interest, Registry, Token, event::Source};
 use std::io;
 use std::io::{ErrorKind, Read, Write};
 use std::mem::MaybeUninit;
+use std::os::fd::{AsRawFd, RawFd};
 
 /// Default buffer size in bytes.
 pub const DEFAULT_BUFFER_SIZE: usize = 1024;
@@ -130,6 +133,23 @@ impl<S: Selectable> Selectable for BufferedStream<S> {
     fn make_readable(&mut self) -> io::Result<()> {
         self.inner.make_readable()
     }
+
+    fn has_pending_io(&self) -> bool {
+        self.cursor > 0 || self.inner.has_pending_io()
+    }
+}
+
+impl<S: AsRawFd, const N: usize> AsRawFd for BufferedStream<S, N> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+impl<S: IoUringSource, const N: usize> IoUringSource for BufferedStream<S, N> {
+    fn on_recv(&mut self, result: io::Result<Option<RecvBuffer>>) {
+        self.inner.on_recv(result)
+    }
 }
 
 #[cfg(feature = "mio")]
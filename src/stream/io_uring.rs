@@ -0,0 +1,218 @@
+//! Stream that can be used together with `IoUringSelector`.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! use boomnet::stream::ConnectionInfo;
+//! use boomnet::stream::io_uring::IntoIoUringStream;
+//! use boomnet::stream::tls::IntoTlsStream;
+//! use boomnet::ws::IntoWebsocket;
+//!
+//! let mut ws = ConnectionInfo::new("stream.binance.com", 9443)
+//!     .into_tcp_stream().unwrap()
+//!     .into_io_uring_stream()
+//!     .into_tls_stream().unwrap()
+//!     .into_websocket("/ws");
+//! ```
+
+use crate::buffer::{BufferPoolRef, OwnedReadBuffer, default_buffer_pool_ref};
+use crate::service::select::Selectable;
+use crate::stream::{ConnectionInfo, ConnectionInfoProvider};
+use std::io;
+use std::io::ErrorKind::WouldBlock;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+
+/// Size of the chunk used by the inbound buffer.
+const CHUNK_SIZE: usize = 4096;
+
+/// Buffer the kernel has received the data into, acquired from the [`BufferPoolRef`] of the
+/// selector and returned to it once dropped.
+pub type RecvBuffer = OwnedReadBuffer<CHUNK_SIZE>;
+
+/// Implemented by streams that can receive data completed by the
+/// [`IoUringSelector`](crate::service::select::io_uring::IoUringSelector). Stream wrappers
+/// forward the completion to the stream they wrap.
+pub trait IoUringSource {
+    /// Called with the buffer holding the received bytes, `None` once the peer has closed the
+    /// connection or the error the receive operation has failed with.
+    fn on_recv(&mut self, result: io::Result<Option<RecvBuffer>>);
+}
+
+/// Receives data using multishot `recv` submitted by the `IoUringSelector`. The provided buffer
+/// the kernel has received the data into becomes the inbound buffer of the stream (the previous
+/// one is released to the [`BufferPoolRef`] and replaces it in the ring), so the data is copied
+/// only once when the stream is read. Writes are sent directly on the non-blocking socket.
+#[derive(Debug)]
+pub struct IoUringStream<S> {
+    inner: S,
+    inbound: OwnedReadBuffer<CHUNK_SIZE>,
+    error: Option<io::Error>,
+    eof: bool,
+}
+
+impl<S> IoUringStream<S> {
+    /// Wrap the `stream` using the default per-thread buffer pool.
+    pub fn new(stream: S) -> Self {
+        Self::with_buffer_pool(stream, &default_buffer_pool_ref())
+    }
+
+    /// Wrap the `stream` acquiring the inbound buffer from the provided `pool`.
+    pub fn with_buffer_pool(stream: S, pool: &BufferPoolRef) -> Self {
+        Self {
+            inner: stream,
+            inbound: pool.acquire(),
+            error: None,
+            eof: false,
+        }
+    }
+}
+
+impl<S> IoUringSource for IoUringStream<S> {
+    fn on_recv(&mut self, result: io::Result<Option<RecvBuffer>>) {
+        match result {
+            Ok(None) => self.eof = true,
+            Ok(Some(buffer)) if self.inbound.available() == 0 => self.inbound = buffer,
+            // previously received data has not been read yet
+            Ok(Some(buffer)) => self.inbound.append(buffer.view()),
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
+
+impl<S> Read for IoUringStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.inbound.available();
+        if available > 0 {
+            let len = available.min(buf.len());
+            // SAFETY: we have checked there is enough bytes available
+            buf[..len].copy_from_slice(unsafe { self.inbound.consume_next_unchecked(len) });
+            return Ok(len);
+        }
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        if self.eof {
+            return Ok(0);
+        }
+        Err(io::Error::from(WouldBlock))
+    }
+}
+
+impl<S: Write> Write for IoUringStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: Selectable> Selectable for IoUringStream<S> {
+    fn connected(&mut self) -> io::Result<bool> {
+        self.inner.connected()
+    }
+
+    fn make_writable(&mut self) -> io::Result<()> {
+        self.inner.make_writable()
+    }
+
+    fn make_readable(&mut self) -> io::Result<()> {
+        self.inner.make_readable()
+    }
+
+    fn has_pending_io(&self) -> bool {
+        self.inbound.available() > 0 || self.error.is_some() || self.inner.has_pending_io()
+    }
+}
+
+impl<S: AsRawFd> AsRawFd for IoUringStream<S> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<S: ConnectionInfoProvider> ConnectionInfoProvider for IoUringStream<S> {
+    fn connection_info(&self) -> &ConnectionInfo {
+        self.inner.connection_info()
+    }
+}
+
+/// Trait to convert any non-blocking socket into [`IoUringStream`].
+pub trait IntoIoUringStream {
+    fn into_io_uring_stream(self) -> IoUringStream<Self>
+    where
+        Self: Sized;
+}
+
+impl<T> IntoIoUringStream for T
+where
+    T: Write + AsRawFd + ConnectionInfoProvider,
+{
+    fn into_io_uring_stream(self) -> IoUringStream<Self>
+    where
+        Self: Sized,
+    {
+        IoUringStream::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::ReadBuffer;
+    use std::io::Cursor;
+
+    fn recv(data: &[u8]) -> io::Result<Option<RecvBuffer>> {
+        let pool = default_buffer_pool_ref();
+        let mut bytes = pool.acquire_bytes(data.len());
+        bytes[..data.len()].copy_from_slice(data);
+        Ok(Some(pool.wrap(ReadBuffer::from_filled(bytes, data.len()))))
+    }
+
+    #[test]
+    fn should_read_received_data() {
+        let mut stream = IoUringStream::new(Cursor::new(Vec::<u8>::new()));
+        let mut buf = [0u8; 4];
+        assert_eq!(WouldBlock, stream.read(&mut buf).unwrap_err().kind());
+
+        stream.on_recv(recv(b"hello"));
+        stream.on_recv(recv(b" world"));
+        assert_eq!(4, stream.read(&mut buf).unwrap());
+        assert_eq!(b"hell", &buf);
+        let mut rest = Vec::new();
+        while let Ok(read) = stream.read(&mut buf) {
+            rest.extend_from_slice(&buf[..read]);
+        }
+        assert_eq!(b"o world", rest.as_slice());
+
+        stream.on_recv(recv(b"!"));
+        stream.on_recv(Ok(None));
+        assert_eq!(1, stream.read(&mut buf).unwrap());
+        assert_eq!(0, stream.read(&mut buf).unwrap());
+    }
+
+    #[test]
+    fn should_take_over_received_buffer_without_copying() {
+        let mut stream = IoUringStream::new(Cursor::new(Vec::<u8>::new()));
+        let buffer = recv(b"hello").unwrap().unwrap();
+        let data = buffer.view().as_ptr();
+
+        stream.on_recv(Ok(Some(buffer)));
+        assert_eq!(data, stream.inbound.view().as_ptr());
+        let mut buf = [0u8; 8];
+        assert_eq!(5, stream.read(&mut buf).unwrap());
+        assert_eq!(b"hello", &buf[..5]);
+    }
+
+    #[test]
+    fn should_report_recv_error_after_pending_data() {
+        let mut stream = IoUringStream::new(Cursor::new(Vec::<u8>::new()));
+        stream.on_recv(recv(b"hi"));
+        stream.on_recv(Err(io::Error::from_raw_os_error(libc::ECONNRESET)));
+        let mut buf = [0u8; 8];
+        assert_eq!(2, stream.read(&mut buf).unwrap());
+        assert_eq!(Some(libc::ECONNRESET), stream.read(&mut buf).unwrap_err().raw_os_error());
+    }
+}
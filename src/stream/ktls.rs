@@ -12,7 +12,7 @@ use openssl::ssl::{ErrorCode, SslOptions};
 use smallstr::SmallString;
 use std::io;
 use std::io::{ErrorKind, Read, Write};
-use std::os::fd::{AsRawFd, BorrowedFd};
+use std::os::fd::{AsRawFd, BorrowedFd, RawFd};
 use std::ptr::slice_from_raw_parts;
 
 /// Offloads TLS to the kernel (KTLS). Uses OpenSSL backend to configure KTLS post handshake (can change in the future).
@@ -243,6 +243,18 @@ impl<S: Selectable> Selectable for KtlsStream<S> {
     fn make_readable(&mut self) -> io::Result<()> {
         self.stream.make_readable()
     }
+
+    #[inline]
+    fn has_pending_io(&self) -> bool {
+        self.stream.has_pending_io()
+    }
+}
+
+impl<S: AsRawFd> AsRawFd for KtlsStream<S> {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
 }
 
 #[cfg(feature = "mio")]
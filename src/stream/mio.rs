@@ -68,6 +68,11 @@ impl Selectable for MioStream {
         self.can_read = true;
         Ok(())
     }
+
+    fn has_pending_io(&self) -> bool {
+        // more data may be available if the last read has filled the buffer
+        self.can_read || !self.buffer.is_empty()
+    }
 }
 
 impl Source for MioStream {
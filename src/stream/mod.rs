@@ -2,6 +2,8 @@
 
 use crate::inet::{FromSocketAddr, IntoNetworkInterface, ToSocketAddr};
 use crate::service::select::Selectable;
+use crate::stream::proxy::HttpProxy;
+use crate::stream::socks5::Socks5Proxy;
 use pnet::datalink::NetworkInterface;
 use socket2::{Domain, Protocol, Socket, Type};
 use std::fmt::{Display, Formatter};
@@ -11,12 +13,16 @@ use url::{ParseError, Url};
 
 pub mod buffer;
 pub mod file;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub mod io_uring;
 #[cfg(all(target_os = "linux", feature = "ktls"))]
 pub mod ktls;
 #[cfg(feature = "mio")]
 pub mod mio;
+pub mod proxy;
 pub mod record;
 pub mod replay;
+pub mod socks5;
 pub mod tcp;
 #[cfg(any(feature = "rustls", feature = "openssl"))]
 pub mod tls;
@@ -212,13 +218,37 @@ pub struct ConnectionInfo {
     net_iface_name: Option<String>,
     cpu: Option<usize>,
     socket_config: Option<fn(&Socket) -> io::Result<()>>,
+    proxy: Option<Proxy>,
+}
+
+/// Proxy used to reach the remote host.
+#[derive(Debug, Clone)]
+enum Proxy {
+    Http(HttpProxy),
+    Socks5(Socks5Proxy),
+}
+
+impl Proxy {
+    fn host(&self) -> &str {
+        match self {
+            Proxy::Http(proxy) => proxy.host(),
+            Proxy::Socks5(proxy) => proxy.host(),
+        }
+    }
+
+    fn port(&self) -> u16 {
+        match self {
+            Proxy::Http(proxy) => proxy.port(),
+            Proxy::Socks5(proxy) => proxy.port(),
+        }
+    }
 }
 
 impl ToSocketAddrs for ConnectionInfo {
     type Iter = vec::IntoIter<SocketAddr>;
 
     fn to_socket_addrs(&self) -> io::Result<Self::Iter> {
-        format!("{}:{}", self.host, self.port).to_socket_addrs()
+        (self.connect_host(), self.connect_port()).to_socket_addrs()
     }
 }
 
@@ -244,6 +274,7 @@ impl TryFrom<Url> for ConnectionInfo {
             net_iface_name: None,
             cpu: None,
             socket_config: None,
+            proxy: None,
         })
     }
 }
@@ -276,6 +307,7 @@ impl ConnectionInfo {
             net_iface_name: None,
             cpu: None,
             socket_config: None,
+            proxy: None,
         }
     }
 
@@ -315,6 +347,38 @@ impl ConnectionInfo {
         }
     }
 
+    /// Connect through the HTTP proxy instead of the remote host directly. The tunnel is
+    /// established by wrapping the tcp stream with [`IntoHttpProxyStream`](proxy::IntoHttpProxyStream).
+    pub fn with_http_proxy(self, host: impl AsRef<str>, port: u16) -> Self {
+        Self {
+            proxy: Some(Proxy::Http(HttpProxy::new(host, port))),
+            ..self
+        }
+    }
+
+    /// Connect through the SOCKS5 proxy instead of the remote host directly. The tunnel is
+    /// established by wrapping the tcp stream with [`IntoSocks5Stream`](socks5::IntoSocks5Stream).
+    pub fn with_socks5_proxy(self, host: impl AsRef<str>, port: u16) -> Self {
+        Self {
+            proxy: Some(Proxy::Socks5(Socks5Proxy::new(host, port))),
+            ..self
+        }
+    }
+
+    /// Connect through the SOCKS5 proxy that requires username/password authentication.
+    pub fn with_socks5_proxy_auth(
+        self,
+        host: impl AsRef<str>,
+        port: u16,
+        username: impl AsRef<str>,
+        password: impl AsRef<str>,
+    ) -> Self {
+        Self {
+            proxy: Some(Proxy::Socks5(Socks5Proxy::new(host, port).with_credentials(username, password))),
+            ..self
+        }
+    }
+
     /// Get host.
     pub fn host(&self) -> &str {
         &self.host
@@ -325,6 +389,38 @@ impl ConnectionInfo {
         self.port
     }
 
+    /// Get HTTP proxy, if configured.
+    pub fn http_proxy(&self) -> Option<&HttpProxy> {
+        match &self.proxy {
+            Some(Proxy::Http(proxy)) => Some(proxy),
+            _ => None,
+        }
+    }
+
+    /// Get SOCKS5 proxy, if configured.
+    pub fn socks5_proxy(&self) -> Option<&Socks5Proxy> {
+        match &self.proxy {
+            Some(Proxy::Socks5(proxy)) => Some(proxy),
+            _ => None,
+        }
+    }
+
+    /// Get host the socket connects to, this is either the proxy or the remote host.
+    pub fn connect_host(&self) -> &str {
+        match &self.proxy {
+            Some(proxy) => proxy.host(),
+            None => &self.host,
+        }
+    }
+
+    /// Get port the socket connects to, this is either the proxy or the remote port.
+    pub fn connect_port(&self) -> u16 {
+        match &self.proxy {
+            Some(proxy) => proxy.port(),
+            None => self.port,
+        }
+    }
+
     /// Get network interface address.
     pub fn net_iface(&self) -> Option<SocketAddr> {
         self.net_iface
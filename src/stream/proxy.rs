@@ -0,0 +1,438 @@
+//! Stream that tunnels the connection through HTTP proxy using `CONNECT` method.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! use boomnet::stream::ConnectionInfo;
+//! use boomnet::stream::proxy::IntoHttpProxyStream;
+//! use boomnet::stream::tls::IntoTlsStream;
+//! use boomnet::ws::IntoWebsocket;
+//!
+//! let mut ws = ConnectionInfo::new("stream.binance.com", 9443)
+//!     .with_http_proxy("proxy.internal", 3128)
+//!     .into_tcp_stream().unwrap()
+//!     .into_http_proxy_stream()
+//!     .into_tls_stream().unwrap()
+//!     .into_websocket("/ws");
+//! ```
+
+use crate::service::select::Selectable;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use crate::stream::io_uring::{IoUringSource, RecvBuffer};
+use crate::stream::{ConnectionInfo, ConnectionInfoProvider};
+#[cfg(feature = "mio")]
+use mio::{Interest, Registry, Token, event::Source};
+use std::io;
+use std::io::ErrorKind::{NotConnected, WouldBlock};
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+
+/// Maximum size of the proxy response headers.
+const MAX_RESPONSE_LEN: usize = 8192;
+
+/// HTTP proxy address used to tunnel the connection.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HttpProxy {
+    host: String,
+    port: u16,
+}
+
+impl HttpProxy {
+    pub fn new(host: impl AsRef<str>, port: u16) -> Self {
+        Self {
+            host: host.as_ref().to_string(),
+            port,
+        }
+    }
+
+    /// Get proxy host.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Get proxy port.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    SendingRequest {
+        request: Vec<u8>,
+        written: usize,
+    },
+    ReadingResponse(Vec<u8>),
+    /// Tunnel has been established, data written so far is being flushed.
+    FlushingWrites {
+        written: usize,
+    },
+    Connected,
+}
+
+/// Performs non-blocking `CONNECT` negotiation with the HTTP proxy before passing the data through. Any data written
+/// before the tunnel has been established is buffered and sent once the proxy accepts the request, ahead of any data
+/// written later on. If the [`ConnectionInfo`] has no proxy configured the stream acts as a pass-through.
+#[derive(Debug)]
+pub struct HttpProxyStream<S> {
+    inner: S,
+    state: State,
+    pending_write: Vec<u8>,
+    pending_read: Vec<u8>,
+}
+
+impl<S: ConnectionInfoProvider> HttpProxyStream<S> {
+    pub fn new(stream: S) -> Self {
+        let state = match stream.connection_info().http_proxy() {
+            Some(_) => State::SendingRequest {
+                request: connect_request(stream.connection_info()),
+                written: 0,
+            },
+            None => State::Connected,
+        };
+        Self {
+            inner: stream,
+            state,
+            pending_write: Vec::new(),
+            pending_read: Vec::new(),
+        }
+    }
+}
+
+impl<S> HttpProxyStream<S> {
+    /// Returns `true` once the proxy has established the tunnel.
+    #[inline]
+    pub const fn is_tunnel_established(&self) -> bool {
+        matches!(self.state, State::Connected)
+    }
+}
+
+impl<S: Read + Write> HttpProxyStream<S> {
+    /// Drive the `CONNECT` negotiation, returns `true` once the tunnel has been established.
+    #[inline]
+    fn negotiate(&mut self) -> io::Result<bool> {
+        if self.is_tunnel_established() {
+            return Ok(true);
+        }
+        self.negotiate_slow()
+    }
+
+    #[cold]
+    fn negotiate_slow(&mut self) -> io::Result<bool> {
+        loop {
+            match &mut self.state {
+                State::SendingRequest { request, written } => {
+                    match self.inner.write(&request[*written..]) {
+                        Ok(0) => return Err(io::Error::other("http proxy closed the connection")),
+                        Ok(n) => *written += n,
+                        Err(err) if err.kind() == WouldBlock || err.kind() == NotConnected => return Ok(false),
+                        Err(err) => return Err(err),
+                    }
+                    if *written == request.len() {
+                        self.state = State::ReadingResponse(Vec::with_capacity(256));
+                    }
+                }
+                State::ReadingResponse(response) => {
+                    let mut chunk = [0u8; 512];
+                    let read = match self.inner.read(&mut chunk) {
+                        Ok(0) => return Err(io::Error::other("http proxy closed the connection")),
+                        Ok(read) => read,
+                        Err(err) if err.kind() == WouldBlock || err.kind() == NotConnected => return Ok(false),
+                        Err(err) => return Err(err),
+                    };
+                    response.extend_from_slice(&chunk[..read]);
+                    let Some(end) = response.windows(4).position(|w| w == b"\r\n\r\n") else {
+                        if response.len() > MAX_RESPONSE_LEN {
+                            return Err(io::Error::other("http proxy response too large"));
+                        }
+                        continue;
+                    };
+                    check_status(&response[..end])?;
+                    // anything past the headers already belongs to the tunnelled stream
+                    self.pending_read = response.split_off(end + 4);
+                    self.state = State::FlushingWrites { written: 0 };
+                }
+                State::FlushingWrites { written } => {
+                    // writes issued while flushing are appended, so the order is preserved
+                    if *written < self.pending_write.len() {
+                        match self.inner.write(&self.pending_write[*written..]) {
+                            Ok(0) => return Err(io::Error::other("http proxy closed the connection")),
+                            Ok(n) => *written += n,
+                            Err(err) if err.kind() == WouldBlock => return Ok(false),
+                            Err(err) => return Err(err),
+                        }
+                        continue;
+                    }
+                    self.pending_write = Vec::new();
+                    self.state = State::Connected;
+                    return Ok(true);
+                }
+                State::Connected => return Ok(true),
+            }
+        }
+    }
+}
+
+fn connect_request(connection_info: &ConnectionInfo) -> Vec<u8> {
+    let host = connection_info.host();
+    let authority = if host.contains(':') {
+        format!("[{}]:{}", host, connection_info.port())
+    } else {
+        format!("{}:{}", host, connection_info.port())
+    };
+    format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n\r\n").into_bytes()
+}
+
+fn check_status(headers: &[u8]) -> io::Result<()> {
+    let status_line = headers.split(|b| *b == b'\n').next().unwrap_or_default();
+    let status_line = String::from_utf8_lossy(status_line);
+    let status_line = status_line.trim_end();
+    let mut parts = status_line.split(' ');
+    let version = parts.next().unwrap_or_default();
+    let status = parts.next().and_then(|status| status.parse::<u16>().ok());
+    match status {
+        Some(200..=299) if version.starts_with("HTTP/1.") => Ok(()),
+        _ => Err(io::Error::other(format!("http proxy rejected CONNECT request: {status_line}"))),
+    }
+}
+
+impl<S: Read + Write> Read for HttpProxyStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.negotiate()? {
+            return Err(io::Error::from(WouldBlock));
+        }
+        if !self.pending_read.is_empty() {
+            return self.read_pending(buf);
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl<S> HttpProxyStream<S> {
+    #[cold]
+    fn read_pending(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.pending_read.len());
+        buf[..len].copy_from_slice(&self.pending_read[..len]);
+        self.pending_read.drain(..len);
+        Ok(len)
+    }
+}
+
+impl<S: Read + Write> Write for HttpProxyStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.negotiate()? {
+            self.pending_write.extend_from_slice(buf);
+            return Ok(buf.len());
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.negotiate()? {
+            return Ok(());
+        }
+        self.inner.flush()
+    }
+}
+
+impl<S: Selectable> Selectable for HttpProxyStream<S> {
+    fn connected(&mut self) -> io::Result<bool> {
+        self.inner.connected()
+    }
+
+    fn make_writable(&mut self) -> io::Result<()> {
+        self.inner.make_writable()
+    }
+
+    fn make_readable(&mut self) -> io::Result<()> {
+        self.inner.make_readable()
+    }
+
+    fn has_pending_io(&self) -> bool {
+        !self.pending_read.is_empty()
+            || matches!(self.state, State::FlushingWrites { .. })
+            || self.inner.has_pending_io()
+    }
+}
+
+impl<S: AsRawFd> AsRawFd for HttpProxyStream<S> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+impl<S: IoUringSource> IoUringSource for HttpProxyStream<S> {
+    fn on_recv(&mut self, result: io::Result<Option<RecvBuffer>>) {
+        self.inner.on_recv(result)
+    }
+}
+
+#[cfg(feature = "mio")]
+impl<S: Source> Source for HttpProxyStream<S> {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        registry.register(&mut self.inner, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        registry.reregister(&mut self.inner, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        registry.deregister(&mut self.inner)
+    }
+}
+
+impl<S: ConnectionInfoProvider> ConnectionInfoProvider for HttpProxyStream<S> {
+    fn connection_info(&self) -> &ConnectionInfo {
+        self.inner.connection_info()
+    }
+}
+
+/// Trait to convert any stream into [`HttpProxyStream`] that will tunnel the connection through
+/// the proxy configured with [`ConnectionInfo::with_http_proxy`].
+pub trait IntoHttpProxyStream {
+    fn into_http_proxy_stream(self) -> HttpProxyStream<Self>
+    where
+        Self: Sized;
+}
+
+impl<T> IntoHttpProxyStream for T
+where
+    T: Read + Write + ConnectionInfoProvider,
+{
+    fn into_http_proxy_stream(self) -> HttpProxyStream<Self>
+    where
+        Self: Sized,
+    {
+        HttpProxyStream::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ChokedStream;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn start_proxy(response: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = io::BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            assert!(request.starts_with("CONNECT example.com:443 HTTP/1.1\r\n"));
+            let mut stream = stream;
+            stream.write_all(response.as_bytes()).unwrap();
+            // echo whatever is sent through the tunnel
+            let mut buf = [0u8; 64];
+            while let Ok(read) = reader.read(&mut buf) {
+                if read == 0 || stream.write_all(&buf[..read]).is_err() {
+                    break;
+                }
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn should_tunnel_through_http_proxy() {
+        let port = start_proxy("HTTP/1.1 200 Connection established\r\n\r\nhi");
+        let mut stream = ConnectionInfo::new("example.com", 443)
+            .with_http_proxy("127.0.0.1", port)
+            .into_tcp_stream()
+            .unwrap()
+            .into_http_proxy_stream();
+
+        // buffered until the tunnel is established
+        stream.write_all(b"hello").unwrap();
+
+        let mut received = Vec::new();
+        let mut buf = [0u8; 64];
+        while received.len() < 7 {
+            match stream.read(&mut buf) {
+                Ok(read) => received.extend_from_slice(&buf[..read]),
+                Err(err) if err.kind() == WouldBlock => continue,
+                Err(err) => panic!("{err}"),
+            }
+        }
+        assert!(stream.is_tunnel_established());
+        assert_eq!(b"hihello", received.as_slice());
+    }
+
+    #[test]
+    fn should_fail_when_proxy_rejects_request() {
+        let port = start_proxy("HTTP/1.1 407 Proxy Authentication Required\r\n\r\n");
+        let mut stream = ConnectionInfo::new("example.com", 443)
+            .with_http_proxy("127.0.0.1", port)
+            .into_tcp_stream()
+            .unwrap()
+            .into_http_proxy_stream();
+
+        let mut buf = [0u8; 64];
+        let err = loop {
+            match stream.read(&mut buf) {
+                Err(err) if err.kind() == WouldBlock => continue,
+                Err(err) => break err,
+                Ok(_) => panic!("expected error"),
+            }
+        };
+        assert!(err.to_string().contains("407"));
+    }
+
+    #[test]
+    fn should_pass_through_without_proxy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let stream = ConnectionInfo::new("127.0.0.1", port)
+            .into_tcp_stream()
+            .unwrap()
+            .into_http_proxy_stream();
+        assert!(stream.is_tunnel_established());
+    }
+
+    #[test]
+    fn should_flush_buffered_writes_once_socket_is_writable() {
+        let connection_info = ConnectionInfo::new("example.com", 443).with_http_proxy("proxy", 3128);
+        let request = connect_request(&connection_info);
+        let mut stream = ChokedStream {
+            connection_info,
+            input: io::Cursor::default(),
+            output: Vec::new(),
+            budget: request.len(),
+        }
+        .into_http_proxy_stream();
+
+        stream.write_all(b"hello").unwrap();
+        stream.inner.input = io::Cursor::new(b"HTTP/1.1 200 OK\r\n\r\n".to_vec());
+        stream.inner.budget = 3;
+        assert_eq!(WouldBlock, stream.read(&mut [0u8; 16]).unwrap_err().kind());
+        assert!(!stream.is_tunnel_established());
+        assert!(stream.has_pending_io());
+
+        // written after the buffered data that has not been flushed yet
+        stream.write_all(b" world").unwrap();
+        stream.inner.budget = usize::MAX;
+        stream.flush().unwrap();
+        assert!(stream.is_tunnel_established());
+        assert!(!stream.has_pending_io());
+        assert_eq!(b"hello world", &stream.inner.output[request.len()..]);
+    }
+
+    #[test]
+    fn should_bracket_ipv6_target() {
+        let request = connect_request(&ConnectionInfo::new("::1", 443).with_http_proxy("proxy", 3128));
+        assert_eq!(b"CONNECT [::1]:443 HTTP/1.1\r\nHost: [::1]:443\r\n\r\n", request.as_slice());
+    }
+}
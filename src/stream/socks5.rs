@@ -0,0 +1,636 @@
+//! Stream that tunnels the connection through SOCKS5 proxy (RFC 1928) with optional
+//! username/password authentication (RFC 1929).
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! use boomnet::stream::ConnectionInfo;
+//! use boomnet::stream::socks5::IntoSocks5Stream;
+//! use boomnet::stream::tls::IntoTlsStream;
+//! use boomnet::ws::IntoWebsocket;
+//!
+//! let mut ws = ConnectionInfo::new("stream.binance.com", 9443)
+//!     .with_socks5_proxy_auth("proxy.internal", 1080, "user", "secret")
+//!     .into_tcp_stream().unwrap()
+//!     .into_socks5_stream()
+//!     .into_tls_stream().unwrap()
+//!     .into_websocket("/ws");
+//! ```
+
+use crate::service::select::Selectable;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use crate::stream::io_uring::{IoUringSource, RecvBuffer};
+use crate::stream::{ConnectionInfo, ConnectionInfoProvider};
+#[cfg(feature = "mio")]
+use mio::{Interest, Registry, Token, event::Source};
+use std::fmt::{Debug, Formatter};
+use std::io;
+use std::io::ErrorKind::{NotConnected, WouldBlock};
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::os::fd::{AsRawFd, RawFd};
+
+const VERSION: u8 = 0x05;
+const AUTH_VERSION: u8 = 0x01;
+const METHOD_NO_AUTH: u8 = 0x00;
+const METHOD_USERNAME_PASSWORD: u8 = 0x02;
+const METHOD_NOT_ACCEPTABLE: u8 = 0xFF;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// SOCKS5 proxy address and optional credentials used to tunnel the connection.
+#[derive(Clone, Eq, PartialEq)]
+pub struct Socks5Proxy {
+    host: String,
+    port: u16,
+    credentials: Option<(String, String)>,
+}
+
+impl Debug for Socks5Proxy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Socks5Proxy")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("username", &self.username())
+            .finish()
+    }
+}
+
+impl Socks5Proxy {
+    pub fn new(host: impl AsRef<str>, port: u16) -> Self {
+        Self {
+            host: host.as_ref().to_string(),
+            port,
+            credentials: None,
+        }
+    }
+
+    /// Authenticate with the proxy using username and password.
+    pub fn with_credentials(self, username: impl AsRef<str>, password: impl AsRef<str>) -> Self {
+        Self {
+            credentials: Some((username.as_ref().to_string(), password.as_ref().to_string())),
+            ..self
+        }
+    }
+
+    /// Get proxy host.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Get proxy port.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Get username used to authenticate with the proxy.
+    pub fn username(&self) -> Option<&str> {
+        self.credentials.as_ref().map(|(username, _)| username.as_str())
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum State {
+    Greeting,
+    Auth,
+    Connect,
+    /// Tunnel has been established, data written so far is being flushed.
+    FlushingWrites,
+    Connected,
+}
+
+/// Performs non-blocking SOCKS5 negotiation (greeting, optional authentication and connect) before passing the data
+/// through. The target host is sent to the proxy as is, so domain names are resolved by the proxy. Any data written
+/// before the tunnel has been established is buffered and sent once the proxy has connected to the target, ahead of any
+/// data written later on. If the [`ConnectionInfo`] has no SOCKS5 proxy configured the stream acts as a pass-through.
+pub struct Socks5Stream<S> {
+    inner: S,
+    state: State,
+    outbound: Vec<u8>,
+    written: usize,
+    inbound: Vec<u8>,
+    pending_write: Vec<u8>,
+    bound_addr: Option<SocketAddr>,
+}
+
+impl<S> Debug for Socks5Stream<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Socks5Stream")
+            .field("state", &self.state)
+            .field("bound_addr", &self.bound_addr)
+            .finish()
+    }
+}
+
+impl<S: ConnectionInfoProvider> Socks5Stream<S> {
+    pub fn new(stream: S) -> Self {
+        let (state, outbound) = match stream.connection_info().socks5_proxy() {
+            Some(proxy) => (State::Greeting, greeting_request(proxy)),
+            None => (State::Connected, Vec::new()),
+        };
+        Self {
+            inner: stream,
+            state,
+            outbound,
+            written: 0,
+            inbound: Vec::with_capacity(32),
+            pending_write: Vec::new(),
+            bound_addr: None,
+        }
+    }
+}
+
+impl<S> Socks5Stream<S> {
+    /// Returns `true` once the proxy has established the tunnel.
+    #[inline]
+    pub fn is_tunnel_established(&self) -> bool {
+        self.state == State::Connected
+    }
+
+    /// Address the proxy has bound to connect to the target host, available once the tunnel has
+    /// been established.
+    pub const fn bound_addr(&self) -> Option<SocketAddr> {
+        self.bound_addr
+    }
+}
+
+impl<S: Read + Write + ConnectionInfoProvider> Socks5Stream<S> {
+    /// Drive the SOCKS5 negotiation, returns `true` once the tunnel has been established.
+    #[inline]
+    fn negotiate(&mut self) -> io::Result<bool> {
+        if self.is_tunnel_established() {
+            return Ok(true);
+        }
+        self.negotiate_slow()
+    }
+
+    #[cold]
+    fn negotiate_slow(&mut self) -> io::Result<bool> {
+        loop {
+            if self.state == State::Connected {
+                return Ok(true);
+            }
+
+            while self.written < self.outbound.len() {
+                match self.inner.write(&self.outbound[self.written..]) {
+                    Ok(0) => return Err(io::Error::other("socks5 proxy closed the connection")),
+                    Ok(n) => self.written += n,
+                    Err(err) if err.kind() == WouldBlock || err.kind() == NotConnected => return Ok(false),
+                    Err(err) => return Err(err),
+                }
+            }
+
+            if self.state == State::FlushingWrites {
+                // writes issued while flushing are queued up behind the data being flushed
+                self.outbound = std::mem::take(&mut self.pending_write);
+                self.written = 0;
+                if self.outbound.is_empty() {
+                    self.state = State::Connected;
+                }
+                continue;
+            }
+
+            // read exactly the expected reply so that no tunnelled data is consumed
+            let expected = expected_reply_len(self.state, &self.inbound);
+            if self.inbound.len() < expected {
+                let mut chunk = [0u8; 64];
+                let chunk = &mut chunk[..(expected - self.inbound.len()).min(64)];
+                match self.inner.read(chunk) {
+                    Ok(0) => return Err(io::Error::other("socks5 proxy closed the connection")),
+                    Ok(read) => self.inbound.extend_from_slice(&chunk[..read]),
+                    Err(err) if err.kind() == WouldBlock || err.kind() == NotConnected => return Ok(false),
+                    Err(err) => return Err(err),
+                }
+                continue;
+            }
+
+            self.on_reply()?;
+        }
+    }
+
+    fn on_reply(&mut self) -> io::Result<()> {
+        let reply = std::mem::take(&mut self.inbound);
+        self.outbound.clear();
+        self.written = 0;
+        let connection_info = self.inner.connection_info();
+        let proxy = connection_info
+            .socks5_proxy()
+            .ok_or_else(|| io::Error::other("socks5 proxy not configured"))?;
+        match self.state {
+            State::Greeting => {
+                if reply[0] != VERSION {
+                    return Err(io::Error::other(format!("unsupported socks version: {}", reply[0])));
+                }
+                match (reply[1], &proxy.credentials) {
+                    (METHOD_NO_AUTH, _) => {
+                        self.outbound = connect_request(connection_info)?;
+                        self.state = State::Connect;
+                    }
+                    (METHOD_USERNAME_PASSWORD, Some((username, password))) => {
+                        self.outbound = auth_request(username, password)?;
+                        self.state = State::Auth;
+                    }
+                    (METHOD_NOT_ACCEPTABLE, _) => {
+                        return Err(io::Error::other("socks5 proxy has no acceptable authentication method"));
+                    }
+                    (method, _) => {
+                        return Err(io::Error::other(format!("socks5 proxy selected unexpected method: {method}")));
+                    }
+                }
+            }
+            State::Auth => {
+                if reply[1] != 0x00 {
+                    return Err(io::Error::other("socks5 proxy authentication failed"));
+                }
+                self.outbound = connect_request(connection_info)?;
+                self.state = State::Connect;
+            }
+            State::Connect => {
+                if reply[1] != 0x00 {
+                    return Err(io::Error::other(format!(
+                        "socks5 proxy failed to connect: {}",
+                        reply_message(reply[1])
+                    )));
+                }
+                if !matches!(reply[3], ATYP_IPV4 | ATYP_IPV6 | ATYP_DOMAIN) {
+                    return Err(io::Error::other(format!(
+                        "socks5 proxy replied with unknown address type: {}",
+                        reply[3]
+                    )));
+                }
+                self.bound_addr = parse_bound_addr(&reply);
+                self.state = State::FlushingWrites;
+            }
+            State::FlushingWrites | State::Connected => {}
+        }
+        Ok(())
+    }
+}
+
+fn greeting_request(proxy: &Socks5Proxy) -> Vec<u8> {
+    match proxy.credentials {
+        Some(_) => vec![VERSION, 2, METHOD_NO_AUTH, METHOD_USERNAME_PASSWORD],
+        None => vec![VERSION, 1, METHOD_NO_AUTH],
+    }
+}
+
+fn auth_request(username: &str, password: &str) -> io::Result<Vec<u8>> {
+    let username_len = u8::try_from(username.len()).map_err(|_| io::Error::other("socks5 username too long"))?;
+    let password_len = u8::try_from(password.len()).map_err(|_| io::Error::other("socks5 password too long"))?;
+    let mut request = Vec::with_capacity(3 + username.len() + password.len());
+    request.extend_from_slice(&[AUTH_VERSION, username_len]);
+    request.extend_from_slice(username.as_bytes());
+    request.push(password_len);
+    request.extend_from_slice(password.as_bytes());
+    Ok(request)
+}
+
+fn connect_request(connection_info: &ConnectionInfo) -> io::Result<Vec<u8>> {
+    let host = connection_info.host();
+    // IPv6 address can also be given in its bracketed form
+    let addr = match host.strip_prefix('[').and_then(|host| host.strip_suffix(']')) {
+        Some(addr) => addr.parse::<Ipv6Addr>().map(IpAddr::V6).ok(),
+        None => host.parse::<IpAddr>().ok(),
+    };
+    let mut request = vec![VERSION, CMD_CONNECT, 0x00];
+    match addr {
+        Some(IpAddr::V4(addr)) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&addr.octets());
+        }
+        Some(IpAddr::V6(addr)) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&addr.octets());
+        }
+        None => {
+            let len = u8::try_from(host.len()).map_err(|_| io::Error::other("socks5 target host too long"))?;
+            request.extend_from_slice(&[ATYP_DOMAIN, len]);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&connection_info.port().to_be_bytes());
+    Ok(request)
+}
+
+/// Number of bytes expected in the reply for the current state, the connect reply length
+/// depends on the address type.
+fn expected_reply_len(state: State, inbound: &[u8]) -> usize {
+    match state {
+        State::Greeting | State::Auth => 2,
+        State::Connect => match inbound.get(3) {
+            Some(&ATYP_IPV4) => 4 + 4 + 2,
+            Some(&ATYP_IPV6) => 4 + 16 + 2,
+            Some(&ATYP_DOMAIN) => match inbound.get(4) {
+                Some(len) => 5 + *len as usize + 2,
+                None => 5,
+            },
+            // unknown address type, stop at the header and fail on the reply
+            Some(_) | None => 4,
+        },
+        State::FlushingWrites | State::Connected => 0,
+    }
+}
+
+fn parse_bound_addr(reply: &[u8]) -> Option<SocketAddr> {
+    let port = |bytes: &[u8]| u16::from_be_bytes([bytes[0], bytes[1]]);
+    match reply[3] {
+        ATYP_IPV4 if reply.len() == 10 => {
+            let addr = Ipv4Addr::new(reply[4], reply[5], reply[6], reply[7]);
+            Some(SocketAddr::new(addr.into(), port(&reply[8..])))
+        }
+        ATYP_IPV6 if reply.len() == 22 => {
+            let octets: [u8; 16] = reply[4..20].try_into().ok()?;
+            Some(SocketAddr::new(Ipv6Addr::from(octets).into(), port(&reply[20..])))
+        }
+        _ => None,
+    }
+}
+
+fn reply_message(code: u8) -> &'static str {
+    match code {
+        0x01 => "general SOCKS server failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown error",
+    }
+}
+
+impl<S: Read + Write + ConnectionInfoProvider> Read for Socks5Stream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.negotiate()? {
+            return Err(io::Error::from(WouldBlock));
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl<S: Read + Write + ConnectionInfoProvider> Write for Socks5Stream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.negotiate()? {
+            self.pending_write.extend_from_slice(buf);
+            return Ok(buf.len());
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.negotiate()? {
+            return Ok(());
+        }
+        self.inner.flush()
+    }
+}
+
+impl<S: Selectable> Selectable for Socks5Stream<S> {
+    fn connected(&mut self) -> io::Result<bool> {
+        self.inner.connected()
+    }
+
+    fn make_writable(&mut self) -> io::Result<()> {
+        self.inner.make_writable()
+    }
+
+    fn make_readable(&mut self) -> io::Result<()> {
+        self.inner.make_readable()
+    }
+
+    fn has_pending_io(&self) -> bool {
+        self.state == State::FlushingWrites || self.written < self.outbound.len() || self.inner.has_pending_io()
+    }
+}
+
+impl<S: AsRawFd> AsRawFd for Socks5Stream<S> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+impl<S: IoUringSource> IoUringSource for Socks5Stream<S> {
+    fn on_recv(&mut self, result: io::Result<Option<RecvBuffer>>) {
+        self.inner.on_recv(result)
+    }
+}
+
+#[cfg(feature = "mio")]
+impl<S: Source> Source for Socks5Stream<S> {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        registry.register(&mut self.inner, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
+        registry.reregister(&mut self.inner, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        registry.deregister(&mut self.inner)
+    }
+}
+
+impl<S: ConnectionInfoProvider> ConnectionInfoProvider for Socks5Stream<S> {
+    fn connection_info(&self) -> &ConnectionInfo {
+        self.inner.connection_info()
+    }
+}
+
+/// Trait to convert any stream into [`Socks5Stream`] that will tunnel the connection through
+/// the proxy configured with [`ConnectionInfo::with_socks5_proxy`].
+pub trait IntoSocks5Stream {
+    fn into_socks5_stream(self) -> Socks5Stream<Self>
+    where
+        Self: Sized;
+}
+
+impl<T> IntoSocks5Stream for T
+where
+    T: Read + Write + ConnectionInfoProvider,
+{
+    fn into_socks5_stream(self) -> Socks5Stream<Self>
+    where
+        Self: Sized,
+    {
+        Socks5Stream::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ChokedStream;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn read_exact<const N: usize>(stream: &mut std::net::TcpStream) -> [u8; N] {
+        let mut buf = [0u8; N];
+        stream.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    fn start_proxy(credentials: Option<(&'static str, &'static str)>, reply_code: u8) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let [version, methods] = read_exact::<2>(&mut stream);
+            assert_eq!(VERSION, version);
+            let mut methods = vec![0u8; methods as usize];
+            stream.read_exact(&mut methods).unwrap();
+
+            match credentials {
+                Some((username, password)) => {
+                    assert!(methods.contains(&METHOD_USERNAME_PASSWORD));
+                    stream.write_all(&[VERSION, METHOD_USERNAME_PASSWORD]).unwrap();
+                    let [_, len] = read_exact::<2>(&mut stream);
+                    let mut received_username = vec![0u8; len as usize];
+                    stream.read_exact(&mut received_username).unwrap();
+                    let [len] = read_exact::<1>(&mut stream);
+                    let mut received_password = vec![0u8; len as usize];
+                    stream.read_exact(&mut received_password).unwrap();
+                    let status =
+                        u8::from(username.as_bytes() != received_username || password.as_bytes() != received_password);
+                    stream.write_all(&[AUTH_VERSION, status]).unwrap();
+                }
+                None => stream.write_all(&[VERSION, METHOD_NO_AUTH]).unwrap(),
+            }
+
+            let [_, cmd, _, atyp, len] = read_exact::<5>(&mut stream);
+            assert_eq!(CMD_CONNECT, cmd);
+            assert_eq!(ATYP_DOMAIN, atyp);
+            let mut host = vec![0u8; len as usize + 2];
+            stream.read_exact(&mut host).unwrap();
+            assert_eq!(b"example.com\x01\xbb", host.as_slice());
+            stream
+                .write_all(&[VERSION, reply_code, 0x00, ATYP_IPV4, 10, 0, 0, 1, 0x1f, 0x90])
+                .unwrap();
+
+            // echo whatever is sent through the tunnel
+            let mut buf = [0u8; 64];
+            while let Ok(read) = stream.read(&mut buf) {
+                if read == 0 || stream.write_all(&buf[..read]).is_err() {
+                    break;
+                }
+            }
+        });
+        port
+    }
+
+    fn read_until_error<S: Read>(stream: &mut S, len: usize) -> io::Result<Vec<u8>> {
+        let mut received = Vec::new();
+        let mut buf = [0u8; 64];
+        while received.len() < len {
+            match stream.read(&mut buf) {
+                Ok(read) => received.extend_from_slice(&buf[..read]),
+                Err(err) if err.kind() == WouldBlock => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(received)
+    }
+
+    #[test]
+    fn should_tunnel_through_socks5_proxy() {
+        let port = start_proxy(None, 0x00);
+        let mut stream = ConnectionInfo::new("example.com", 443)
+            .with_socks5_proxy("127.0.0.1", port)
+            .into_tcp_stream()
+            .unwrap()
+            .into_socks5_stream();
+
+        // buffered until the tunnel is established
+        stream.write_all(b"hello").unwrap();
+
+        assert_eq!(b"hello", read_until_error(&mut stream, 5).unwrap().as_slice());
+        assert!(stream.is_tunnel_established());
+        assert_eq!(Some("10.0.0.1:8080".parse().unwrap()), stream.bound_addr());
+        assert_eq!("example.com", stream.connection_info().host());
+    }
+
+    #[test]
+    fn should_authenticate_with_socks5_proxy() {
+        let port = start_proxy(Some(("user", "secret")), 0x00);
+        let mut stream = ConnectionInfo::new("example.com", 443)
+            .with_socks5_proxy_auth("127.0.0.1", port, "user", "secret")
+            .into_tcp_stream()
+            .unwrap()
+            .into_socks5_stream();
+
+        stream.write_all(b"hello").unwrap();
+        assert_eq!(b"hello", read_until_error(&mut stream, 5).unwrap().as_slice());
+    }
+
+    #[test]
+    fn should_fail_with_invalid_credentials() {
+        let port = start_proxy(Some(("user", "secret")), 0x00);
+        let mut stream = ConnectionInfo::new("example.com", 443)
+            .with_socks5_proxy_auth("127.0.0.1", port, "user", "wrong")
+            .into_tcp_stream()
+            .unwrap()
+            .into_socks5_stream();
+
+        let err = read_until_error(&mut stream, 1).unwrap_err();
+        assert!(err.to_string().contains("authentication failed"));
+    }
+
+    #[test]
+    fn should_fail_when_proxy_cannot_connect() {
+        let port = start_proxy(None, 0x05);
+        let mut stream = ConnectionInfo::new("example.com", 443)
+            .with_socks5_proxy("127.0.0.1", port)
+            .into_tcp_stream()
+            .unwrap()
+            .into_socks5_stream();
+
+        let err = read_until_error(&mut stream, 1).unwrap_err();
+        assert!(err.to_string().contains("connection refused"));
+    }
+
+    #[test]
+    fn should_flush_buffered_writes_once_socket_is_writable() {
+        let connection_info = ConnectionInfo::new("example.com", 443).with_socks5_proxy("proxy", 1080);
+        let negotiation_len = greeting_request(connection_info.socks5_proxy().unwrap()).len()
+            + connect_request(&connection_info).unwrap().len();
+        let mut stream = ChokedStream {
+            connection_info,
+            input: io::Cursor::new(vec![VERSION, METHOD_NO_AUTH]),
+            output: Vec::new(),
+            budget: negotiation_len,
+        }
+        .into_socks5_stream();
+
+        stream.write_all(b"hello").unwrap();
+        stream.inner.input = io::Cursor::new(vec![VERSION, 0x00, 0x00, ATYP_IPV4, 10, 0, 0, 1, 0x1f, 0x90]);
+        stream.inner.budget = 3;
+        assert_eq!(WouldBlock, stream.read(&mut [0u8; 16]).unwrap_err().kind());
+        assert!(!stream.is_tunnel_established());
+        assert!(stream.has_pending_io());
+
+        // written after the buffered data that has not been flushed yet
+        stream.write_all(b" world").unwrap();
+        stream.inner.budget = usize::MAX;
+        stream.flush().unwrap();
+        assert!(stream.is_tunnel_established());
+        assert!(!stream.has_pending_io());
+        assert_eq!(b"hello world", &stream.inner.output[negotiation_len..]);
+    }
+
+    #[test]
+    fn should_encode_connect_request() {
+        let request = connect_request(&ConnectionInfo::new("127.0.0.1", 80)).unwrap();
+        assert_eq!(&[VERSION, CMD_CONNECT, 0, ATYP_IPV4, 127, 0, 0, 1, 0, 80], request.as_slice());
+
+        let request = connect_request(&ConnectionInfo::new("::1", 80)).unwrap();
+        assert_eq!(ATYP_IPV6, request[3]);
+        assert_eq!(4 + 16 + 2, request.len());
+        assert_eq!(request, connect_request(&ConnectionInfo::new("[::1]", 80)).unwrap());
+
+        let request = connect_request(&ConnectionInfo::new("a.io", 443)).unwrap();
+        assert_eq!(b"\x05\x01\x00\x03\x04a.io\x01\xbb", request.as_slice());
+    }
+}
@@ -12,6 +12,14 @@ use std::os::fd::{AsRawFd, RawFd};
 pub struct TcpStream {
     inner: std::net::TcpStream,
     connection_info: ConnectionInfo,
+    /// Error the non-blocking connect has failed with. The socket error is cleared once taken, so
+    /// it is kept here to be reported by every subsequent [`Selectable::connected`] call.
+    connect_error: Option<i32>,
+    /// Set once the connection has been established, so that it is not queried again.
+    connected: bool,
+    /// Set when the last read has filled the buffer, so more data may be waiting in the socket
+    /// without the selector reporting it again.
+    can_read: bool,
 }
 
 impl AsRawFd for TcpStream {
@@ -73,18 +81,27 @@ impl TcpStream {
         Self {
             inner: stream,
             connection_info,
+            connect_error: None,
+            connected: false,
+            can_read: false,
         }
     }
 
     #[inline]
     pub fn connected(&mut self) -> bool {
-        self.inner.peer_addr().is_ok()
+        if !self.connected {
+            self.connected = self.inner.peer_addr().is_ok();
+        }
+        self.connected
     }
 }
 
 impl Read for TcpStream {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        self.inner.read(buf)
+        self.can_read = false;
+        let read = self.inner.read(buf)?;
+        self.can_read = read > 0 && read == buf.len();
+        Ok(read)
     }
 }
 
@@ -100,7 +117,24 @@ impl Write for TcpStream {
 
 impl Selectable for TcpStream {
     fn connected(&mut self) -> io::Result<bool> {
-        Ok(true)
+        if self.connected {
+            return Ok(true);
+        }
+        if let Some(code) = self.connect_error {
+            return Err(io::Error::from_raw_os_error(code));
+        }
+        if let Some(err) = self.inner.take_error()? {
+            self.connect_error = err.raw_os_error();
+            return Err(err);
+        }
+        match self.inner.peer_addr() {
+            Ok(_) => {
+                self.connected = true;
+                Ok(true)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotConnected => Ok(false),
+            Err(err) => Err(err),
+        }
     }
 
     fn make_writable(&mut self) -> io::Result<()> {
@@ -110,6 +144,11 @@ impl Selectable for TcpStream {
     fn make_readable(&mut self) -> io::Result<()> {
         Ok(())
     }
+
+    fn has_pending_io(&self) -> bool {
+        // more data may be available if the last read has filled the buffer
+        self.can_read
+    }
 }
 
 impl ConnectionInfoProvider for TcpStream {
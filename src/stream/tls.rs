@@ -1,6 +1,8 @@
 //! Provides TLS stream implementation for different backends.
 
 use crate::service::select::Selectable;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use crate::stream::io_uring::{IoUringSource, RecvBuffer};
 use crate::stream::{ConnectionInfo, ConnectionInfoProvider};
 #[cfg(feature = "openssl")]
 pub use __openssl::TlsStream;
@@ -15,6 +17,7 @@ use rustls::ClientConfig;
 use std::fmt::Debug;
 use std::io;
 use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
 
 /// Used to configure TLS backend.
 pub struct TlsConfig {
@@ -135,6 +138,8 @@ impl TlsConfigExt for TlsConfig {
 #[cfg(all(feature = "rustls", not(feature = "openssl")))]
 mod __rustls {
     use crate::service::select::Selectable;
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    use crate::stream::io_uring::{IoUringSource, RecvBuffer};
     use crate::stream::tls::TlsConfig;
     use crate::stream::{ConnectionInfo, ConnectionInfoProvider};
     use crate::util::NoBlock;
@@ -151,10 +156,26 @@ mod __rustls {
     use std::fmt::Debug;
     use std::io;
     use std::io::{Read, Write};
+    use std::os::fd::{AsRawFd, RawFd};
 
     pub struct TlsStream<S> {
         inner: S,
         tls: ClientConnection,
+        /// Number of bytes that have been decrypted but not read yet.
+        plaintext: usize,
+    }
+
+    impl<S: AsRawFd> AsRawFd for TlsStream<S> {
+        fn as_raw_fd(&self) -> RawFd {
+            self.inner.as_raw_fd()
+        }
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    impl<S: IoUringSource> IoUringSource for TlsStream<S> {
+        fn on_recv(&mut self, result: io::Result<Option<RecvBuffer>>) {
+            self.inner.on_recv(result)
+        }
     }
 
     #[cfg(feature = "mio")]
@@ -184,12 +205,20 @@ mod __rustls {
         fn make_readable(&mut self) -> io::Result<()> {
             self.inner.make_readable()
         }
+
+        fn has_pending_io(&self) -> bool {
+            // encrypted records are only sent when the stream is read, while decrypted records may
+            // not have been read in full
+            self.plaintext > 0 || self.tls.wants_write() || self.inner.has_pending_io()
+        }
     }
 
     impl<S: Read + Write> Read for TlsStream<S> {
         fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
             let (_, _) = self.complete_io()?;
-            self.tls.reader().read(buf)
+            let read = self.tls.reader().read(buf)?;
+            self.plaintext = self.plaintext.saturating_sub(read);
+            Ok(read)
         }
     }
 
@@ -235,7 +264,11 @@ mod __rustls {
             let server_name = server_name.to_owned().try_into().map_err(io::Error::other)?;
             let tls = ClientConnection::new(config, server_name).map_err(io::Error::other)?;
 
-            Ok(Self { inner: stream, tls })
+            Ok(Self {
+                inner: stream,
+                tls,
+                plaintext: 0,
+            })
         }
 
         pub fn new(stream: S, server_name: &str) -> io::Result<TlsStream<S>> {
@@ -256,7 +289,8 @@ mod __rustls {
             let read = if self.tls.wants_read() {
                 let read = self.tls.read_tls(&mut self.inner).no_block()?;
                 if read > 0 {
-                    self.tls.process_new_packets().map_err(io::Error::other)?;
+                    let state = self.tls.process_new_packets().map_err(io::Error::other)?;
+                    self.plaintext = state.plaintext_bytes_to_read();
                 }
                 read
             } else {
@@ -329,6 +363,8 @@ mod __rustls {
 #[cfg(feature = "openssl")]
 mod __openssl {
     use crate::service::select::Selectable;
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    use crate::stream::io_uring::{IoUringSource, RecvBuffer};
     use crate::stream::tls::TlsConfig;
     use crate::stream::{ConnectionInfo, ConnectionInfoProvider};
     #[cfg(feature = "mio")]
@@ -342,6 +378,7 @@ mod __openssl {
     use std::io;
     use std::io::ErrorKind::WouldBlock;
     use std::io::{Read, Write};
+    use std::os::fd::{AsRawFd, RawFd};
 
     trait SslConnectionBuilderExt {
         fn setup_default_keylog_policy(&mut self);
@@ -404,6 +441,26 @@ mod __openssl {
         }
     }
 
+    impl<S: AsRawFd> AsRawFd for TlsStream<S> {
+        fn as_raw_fd(&self) -> RawFd {
+            match &self.state {
+                State::Handshake(stream_and_buf) => stream_and_buf.as_ref().unwrap().0.get_ref().as_raw_fd(),
+                State::Drain(stream_and_buf) => stream_and_buf.as_ref().unwrap().0.get_ref().as_raw_fd(),
+                State::Stream(stream) => stream.get_ref().as_raw_fd(),
+            }
+        }
+    }
+
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    impl<S: IoUringSource> IoUringSource for TlsStream<S> {
+        fn on_recv(&mut self, result: io::Result<Option<RecvBuffer>>) {
+            // the handshake has failed if the stream is no longer available
+            if let Ok(stream) = self.state.get_mut() {
+                stream.on_recv(result)
+            }
+        }
+    }
+
     #[cfg(feature = "mio")]
     impl<S: Source> Source for TlsStream<S> {
         fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
@@ -431,6 +488,17 @@ mod __openssl {
         fn make_readable(&mut self) -> io::Result<()> {
             self.state.get_mut()?.make_readable()
         }
+
+        fn has_pending_io(&self) -> bool {
+            match &self.state {
+                State::Handshake(stream_and_buf) => stream_and_buf
+                    .as_ref()
+                    .is_some_and(|(stream, _)| stream.get_ref().has_pending_io()),
+                // messages buffered during the handshake are sent when the stream is read
+                State::Drain(_) => true,
+                State::Stream(stream) => stream.ssl().pending() > 0 || stream.get_ref().has_pending_io(),
+            }
+        }
     }
 
     impl<S: Read + Write> Read for TlsStream<S> {
@@ -644,6 +712,25 @@ impl<S: ConnectionInfoProvider> ConnectionInfoProvider for TlsReadyStream<S> {
     }
 }
 
+impl<S: AsRawFd> AsRawFd for TlsReadyStream<S> {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            TlsReadyStream::Plain(stream) => stream.as_raw_fd(),
+            TlsReadyStream::Tls(stream) => stream.as_raw_fd(),
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+impl<S: IoUringSource> IoUringSource for TlsReadyStream<S> {
+    fn on_recv(&mut self, result: io::Result<Option<RecvBuffer>>) {
+        match self {
+            TlsReadyStream::Plain(stream) => stream.on_recv(result),
+            TlsReadyStream::Tls(stream) => stream.on_recv(result),
+        }
+    }
+}
+
 #[cfg(feature = "mio")]
 impl<S: Source> Source for TlsReadyStream<S> {
     fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
@@ -689,4 +776,11 @@ impl<S: Selectable> Selectable for TlsReadyStream<S> {
             TlsReadyStream::Tls(stream) => stream.make_readable(),
         }
     }
+
+    fn has_pending_io(&self) -> bool {
+        match self {
+            TlsReadyStream::Plain(stream) => stream.has_pending_io(),
+            TlsReadyStream::Tls(stream) => stream.has_pending_io(),
+        }
+    }
 }
@@ -0,0 +1,314 @@
+//! Test fixtures shared by the unit tests of the different modules.
+
+use crate::service::dns::{DnsQuery, DnsResolver};
+use crate::service::select::Selectable;
+use crate::service::time::TimeSource;
+use crate::stream::{ConnectionInfo, ConnectionInfoProvider};
+use std::cell::Cell;
+use std::io;
+use std::io::ErrorKind::WouldBlock;
+use std::io::{Cursor, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Time source that only moves when advanced by the test, clones share the same time.
+#[derive(Clone, Default)]
+pub(crate) struct ManualTimeSource(Rc<Cell<u64>>);
+
+impl ManualTimeSource {
+    pub(crate) fn advance(&self, duration: Duration) {
+        self.0.set(self.0.get() + duration.as_nanos() as u64);
+    }
+}
+
+impl TimeSource for ManualTimeSource {
+    fn current_time_nanos(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+/// Stream that replays the `inbound` bytes, reporting `WouldBlock` once they run out, and collects
+/// everything written to it in `outbound`.
+pub(crate) struct MockStream {
+    pub(crate) inbound: Cursor<Vec<u8>>,
+    pub(crate) outbound: Vec<u8>,
+    /// Address the stream has been created for.
+    pub(crate) addr: SocketAddr,
+    pub(crate) connected: bool,
+    pub(crate) pending_io: bool,
+}
+
+impl Default for MockStream {
+    fn default() -> Self {
+        Self::new([])
+    }
+}
+
+impl MockStream {
+    pub(crate) fn new(inbound: impl AsRef<[u8]>) -> Self {
+        Self {
+            inbound: Cursor::new(inbound.as_ref().to_vec()),
+            outbound: Vec::new(),
+            addr: SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            connected: true,
+            pending_io: false,
+        }
+    }
+
+    /// Stream created for the `addr` that only ever connects if it is the `reachable` address.
+    pub(crate) fn connecting(addr: SocketAddr, reachable: SocketAddr) -> Self {
+        Self {
+            addr,
+            connected: addr == reachable,
+            ..Self::default()
+        }
+    }
+}
+
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inbound.read(buf)? {
+            0 => Err(io::Error::from(WouldBlock)),
+            n => Ok(n),
+        }
+    }
+}
+
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.outbound.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Selectable for MockStream {
+    fn connected(&mut self) -> io::Result<bool> {
+        Ok(self.connected)
+    }
+
+    fn make_writable(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn make_readable(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn has_pending_io(&self) -> bool {
+        self.pending_io
+    }
+}
+
+/// Stream that replays the `input` and accepts up to `budget` bytes before it would block.
+pub(crate) struct ChokedStream {
+    pub(crate) connection_info: ConnectionInfo,
+    pub(crate) input: Cursor<Vec<u8>>,
+    pub(crate) output: Vec<u8>,
+    pub(crate) budget: usize,
+}
+
+impl Read for ChokedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.input.read(buf)? {
+            0 => Err(io::Error::from(WouldBlock)),
+            read => Ok(read),
+        }
+    }
+}
+
+impl Write for ChokedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.budget);
+        if len == 0 {
+            return Err(io::Error::from(WouldBlock));
+        }
+        self.budget -= len;
+        self.output.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Selectable for ChokedStream {
+    fn connected(&mut self) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    fn make_writable(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn make_readable(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ConnectionInfoProvider for ChokedStream {
+    fn connection_info(&self) -> &ConnectionInfo {
+        &self.connection_info
+    }
+}
+
+/// Resolver that resolves every host to the same addresses.
+pub(crate) struct StaticDnsResolver(pub(crate) Vec<SocketAddr>);
+
+pub(crate) struct StaticDnsQuery(pub(crate) Vec<SocketAddr>);
+
+impl DnsResolver for StaticDnsResolver {
+    type Query = StaticDnsQuery;
+
+    fn new_query(&self, _host: impl AsRef<str>, _port: u16) -> io::Result<Self::Query> {
+        Ok(StaticDnsQuery(self.0.clone()))
+    }
+}
+
+impl DnsQuery for StaticDnsQuery {
+    fn poll(&mut self) -> io::Result<impl IntoIterator<Item = SocketAddr>> {
+        Ok(self.0.clone())
+    }
+}
+
+#[cfg(all(target_os = "linux", any(feature = "epoll", feature = "io-uring")))]
+pub(crate) use echo::*;
+
+/// Endpoint connecting to a local server, used to run the selectors against real sockets.
+#[cfg(all(target_os = "linux", any(feature = "epoll", feature = "io-uring")))]
+mod echo {
+    use crate::service::dns::DnsResolver;
+    use crate::service::endpoint::{DisconnectReason, Endpoint};
+    use crate::service::select::Selector;
+    use crate::service::time::SystemTimeClockSource;
+    use crate::service::{IOService, IntoIOService};
+    use crate::stream::tcp::TcpStream;
+    use crate::stream::{ConnectionInfo, ConnectionInfoProvider};
+    use std::io;
+    use std::io::{Read, Write};
+    use std::marker::PhantomData;
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+
+    /// Stream the [`EchoEndpoint`] can create from the connected `TcpStream`.
+    pub(crate) trait FromTcpStream {
+        fn from_tcp_stream(stream: TcpStream) -> Self;
+    }
+
+    impl FromTcpStream for TcpStream {
+        fn from_tcp_stream(stream: TcpStream) -> Self {
+            stream
+        }
+    }
+
+    #[cfg(feature = "io-uring")]
+    impl FromTcpStream for crate::stream::io_uring::IoUringStream<TcpStream> {
+        fn from_tcp_stream(stream: TcpStream) -> Self {
+            use crate::stream::io_uring::IntoIoUringStream;
+            stream.into_io_uring_stream()
+        }
+    }
+
+    #[cfg(feature = "ws")]
+    impl FromTcpStream for crate::ws::Websocket<TcpStream> {
+        fn from_tcp_stream(stream: TcpStream) -> Self {
+            use crate::ws::IntoWebsocket;
+            stream.into_websocket("/")
+        }
+    }
+
+    pub(crate) struct EchoEndpoint<T> {
+        connection_info: ConnectionInfo,
+        pub(crate) recreate: bool,
+        target: PhantomData<fn() -> T>,
+    }
+
+    impl<T> EchoEndpoint<T> {
+        pub(crate) fn new(port: u16) -> Self {
+            Self {
+                connection_info: ConnectionInfo::new("127.0.0.1", port),
+                recreate: true,
+                target: PhantomData,
+            }
+        }
+    }
+
+    impl<T> ConnectionInfoProvider for EchoEndpoint<T> {
+        fn connection_info(&self) -> &ConnectionInfo {
+            &self.connection_info
+        }
+    }
+
+    impl<T: FromTcpStream> Endpoint for EchoEndpoint<T> {
+        type Target = T;
+
+        fn create_target(&mut self, addr: SocketAddr) -> io::Result<Option<Self::Target>> {
+            let stream = self.connection_info.clone().into_tcp_stream_with_addr(addr)?;
+            Ok(Some(T::from_tcp_stream(stream)))
+        }
+
+        fn can_recreate(&mut self, _reason: &DisconnectReason) -> bool {
+            self.recreate
+        }
+    }
+
+    /// Accept single connection, send the `payload` to it and close it.
+    pub(crate) fn serve(payload: &'static [u8]) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(payload).unwrap();
+        });
+        addr
+    }
+
+    /// Poll the service until the peer closes the connection, returning everything received.
+    pub(crate) fn receive_all<S, D>(
+        io_service: &mut IOService<S, EchoEndpoint<S::Target>, (), SystemTimeClockSource, D>,
+    ) -> Vec<u8>
+    where
+        S: Selector,
+        S::Target: Read + FromTcpStream,
+        D: DnsResolver,
+    {
+        let mut received = Vec::new();
+        let mut eof = false;
+        while !eof {
+            io_service
+                .poll(|stream, _| {
+                    let mut buf = [0u8; 16];
+                    loop {
+                        match stream.read(&mut buf) {
+                            Ok(0) => {
+                                eof = true;
+                                return Ok(());
+                            }
+                            Ok(read) => received.extend_from_slice(&buf[..read]),
+                            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                            Err(err) if err.kind() == io::ErrorKind::NotConnected => return Ok(()),
+                            Err(err) => return Err(err),
+                        }
+                    }
+                })
+                .unwrap();
+        }
+        received
+    }
+
+    /// Connect to the local server sending the `payload` and return everything received.
+    pub(crate) fn roundtrip<S>(selector: S, payload: &'static [u8]) -> Vec<u8>
+    where
+        S: Selector + IntoIOService<EchoEndpoint<S::Target>>,
+        S::Target: Read + FromTcpStream,
+    {
+        let port = serve(payload).port();
+        let mut io_service = selector.into_io_service();
+        io_service.register(EchoEndpoint::new(port)).unwrap();
+        receive_all(&mut io_service)
+    }
+}
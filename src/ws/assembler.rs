@@ -0,0 +1,182 @@
+use crate::buffer::{BufferPoolRef, OwnedReadBuffer};
+use crate::ws::{Error, Message, protocol};
+
+/// Reassembles fragmented data frames into complete messages. Messages that arrive in a single
+/// frame are passed through without copying, while fragments are accumulated in a buffer that is
+/// acquired from the pool on the first fragmented message. Once the message is complete, the buffer
+/// is retained until [`MessageAssembler::reset`] is called at the start of the next batch, so that
+/// all messages from the same batch remain valid.
+#[derive(Debug)]
+pub struct MessageAssembler {
+    pool: BufferPoolRef,
+    buffer: Option<OwnedReadBuffer<4096>>,
+    completed: Vec<OwnedReadBuffer<4096>>,
+    op_code: Option<u8>,
+}
+
+impl MessageAssembler {
+    pub const fn new(pool: BufferPoolRef) -> Self {
+        Self {
+            pool,
+            buffer: None,
+            completed: Vec::new(),
+            op_code: None,
+        }
+    }
+
+    /// Process the first frame of the message (`Text` or `Binary`). Returns the message if this
+    /// is also the last frame.
+    #[inline]
+    pub fn on_data_frame(
+        &mut self,
+        op_code: u8,
+        fin: bool,
+        payload: &'static [u8],
+    ) -> Result<Option<Message<'static>>, Error> {
+        if self.op_code.is_some() {
+            return Err(Error::Protocol("data frame received while the previous message is incomplete".to_string()));
+        }
+        if fin {
+            return Ok(Some(Message::new(op_code, payload)));
+        }
+        self.start(op_code, payload);
+        Ok(None)
+    }
+
+    /// Process the `Continuation` frame. Returns the reassembled message once the last frame
+    /// has been received.
+    #[inline]
+    pub fn on_continuation_frame(
+        &mut self,
+        fin: bool,
+        payload: &'static [u8],
+    ) -> Result<Option<Message<'static>>, Error> {
+        let (Some(op_code), Some(buffer)) = (self.op_code, self.buffer.as_mut()) else {
+            return Err(Error::Protocol("continuation frame received without the initial data frame".to_string()));
+        };
+        buffer.append(payload);
+        if !fin {
+            return Ok(None);
+        }
+        self.op_code = None;
+        let available = buffer.available();
+        // SAFETY: we consume exactly the available bytes
+        let payload = unsafe { buffer.consume_next_unchecked(available) };
+        // SAFETY: checked above
+        self.completed.push(unsafe { self.buffer.take().unwrap_unchecked() });
+        Ok(Some(Message::new(op_code, payload)))
+    }
+
+    /// Return buffers of the completed messages to the pool, must only be called once these
+    /// messages are no longer referenced.
+    #[inline]
+    pub fn reset(&mut self) {
+        if !self.completed.is_empty() {
+            self.completed.clear();
+        }
+    }
+
+    #[cfg(test)]
+    pub fn completed(&self) -> usize {
+        self.completed.len()
+    }
+
+    #[cold]
+    fn start(&mut self, op_code: u8, payload: &[u8]) {
+        let buffer = self.buffer.get_or_insert_with(|| self.pool.acquire());
+        buffer.clear();
+        buffer.append(payload);
+        self.op_code = Some(op_code);
+    }
+}
+
+impl<'a> Message<'a> {
+    #[inline]
+    const fn new(op_code: u8, payload: &'a [u8]) -> Self {
+        match op_code {
+            protocol::op::TEXT_FRAME => Message::Text(payload),
+            _ => Message::Binary(payload),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_pass_through_single_frame_message() {
+        let mut assembler = MessageAssembler::new(Default::default());
+        let payload: &'static [u8] = b"hello";
+
+        match assembler
+            .on_data_frame(protocol::op::TEXT_FRAME, true, payload)
+            .unwrap()
+        {
+            Some(Message::Text(message)) => assert_eq!(payload.as_ptr(), message.as_ptr()),
+            _ => panic!("expected text message"),
+        }
+        assert!(assembler.buffer.is_none());
+    }
+
+    #[test]
+    fn should_reassemble_fragmented_message() {
+        let mut assembler = MessageAssembler::new(Default::default());
+
+        assert!(
+            assembler
+                .on_data_frame(protocol::op::BINARY_FRAME, false, b"hello")
+                .unwrap()
+                .is_none()
+        );
+        assert!(assembler.on_continuation_frame(false, b" ").unwrap().is_none());
+        match assembler.on_continuation_frame(true, b"world").unwrap() {
+            Some(Message::Binary(message)) => assert_eq!(b"hello world", message),
+            _ => panic!("expected binary message"),
+        }
+
+        assert!(
+            assembler
+                .on_data_frame(protocol::op::TEXT_FRAME, false, b"foo")
+                .unwrap()
+                .is_none()
+        );
+        match assembler.on_continuation_frame(true, b"bar").unwrap() {
+            Some(Message::Text(message)) => assert_eq!(b"foobar", message),
+            _ => panic!("expected text message"),
+        }
+    }
+
+    #[test]
+    fn should_reject_unexpected_frames() {
+        let mut assembler = MessageAssembler::new(Default::default());
+        assert!(assembler.on_continuation_frame(true, b"foo").is_err());
+
+        assembler
+            .on_data_frame(protocol::op::TEXT_FRAME, false, b"foo")
+            .unwrap();
+        assert!(assembler.on_data_frame(protocol::op::TEXT_FRAME, true, b"bar").is_err());
+    }
+
+    #[test]
+    fn should_keep_completed_messages_until_reset() {
+        let pool = BufferPoolRef::default();
+        let mut assembler = MessageAssembler::new(pool);
+
+        assembler
+            .on_data_frame(protocol::op::TEXT_FRAME, false, b"foo")
+            .unwrap();
+        let first = assembler.on_continuation_frame(true, b"bar").unwrap();
+        assembler
+            .on_data_frame(protocol::op::TEXT_FRAME, false, b"hello")
+            .unwrap();
+        let second = assembler.on_continuation_frame(true, b"world").unwrap();
+
+        assert!(matches!(first, Some(Message::Text(b"foobar"))));
+        assert!(matches!(second, Some(Message::Text(b"helloworld"))));
+        assert_eq!(2, assembler.completed.len());
+
+        assembler.reset();
+        assert!(assembler.completed.is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+//! Close frame status codes and payload handling.
+
+use crate::ws::Error;
+use std::fmt::{Display, Formatter};
+
+/// Maximum length of the close reason as control frame payload is limited to 125 bytes.
+pub const MAX_CLOSE_REASON_LEN: usize = 123;
+
+/// Status code sent as part of the close frame (RFC 6455 section 7.4).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum CloseCode {
+    /// Normal closure (1000).
+    Normal,
+    /// Endpoint is going away, such as server shutdown or browser navigating away (1001).
+    Away,
+    /// Endpoint is terminating the connection due to protocol error (1002).
+    Protocol,
+    /// Endpoint has received data type it cannot accept (1003).
+    Unsupported,
+    /// No status code was present in the close frame (1005). Must not be sent.
+    NoStatus,
+    /// Connection was closed abnormally without close frame (1006). Must not be sent.
+    Abnormal,
+    /// Endpoint has received data inconsistent with the message type (1007).
+    InvalidPayload,
+    /// Endpoint has received message that violates its policy (1008).
+    Policy,
+    /// Endpoint has received message that is too big to process (1009).
+    MessageTooBig,
+    /// Client expected the server to negotiate one or more extensions (1010).
+    MandatoryExtension,
+    /// Server has encountered unexpected condition (1011).
+    InternalError,
+    /// Server is restarting (1012).
+    Restart,
+    /// Server is overloaded, client should try again later (1013).
+    TryAgainLater,
+    /// Gateway or proxy has received invalid response from the upstream server (1014).
+    BadGateway,
+    /// TLS handshake failure (1015). Must not be sent.
+    Tls,
+    /// Any other status code, including application and library specific ones (3000-4999).
+    Other(u16),
+}
+
+impl CloseCode {
+    /// Checks if the status code is allowed to be sent in the close frame.
+    pub const fn is_allowed(&self) -> bool {
+        match self {
+            CloseCode::NoStatus | CloseCode::Abnormal | CloseCode::Tls => false,
+            CloseCode::Other(code) => matches!(*code, 3000..=4999),
+            _ => true,
+        }
+    }
+}
+
+impl From<u16> for CloseCode {
+    fn from(code: u16) -> Self {
+        match code {
+            1000 => CloseCode::Normal,
+            1001 => CloseCode::Away,
+            1002 => CloseCode::Protocol,
+            1003 => CloseCode::Unsupported,
+            1005 => CloseCode::NoStatus,
+            1006 => CloseCode::Abnormal,
+            1007 => CloseCode::InvalidPayload,
+            1008 => CloseCode::Policy,
+            1009 => CloseCode::MessageTooBig,
+            1010 => CloseCode::MandatoryExtension,
+            1011 => CloseCode::InternalError,
+            1012 => CloseCode::Restart,
+            1013 => CloseCode::TryAgainLater,
+            1014 => CloseCode::BadGateway,
+            1015 => CloseCode::Tls,
+            code => CloseCode::Other(code),
+        }
+    }
+}
+
+impl From<CloseCode> for u16 {
+    fn from(code: CloseCode) -> Self {
+        match code {
+            CloseCode::Normal => 1000,
+            CloseCode::Away => 1001,
+            CloseCode::Protocol => 1002,
+            CloseCode::Unsupported => 1003,
+            CloseCode::NoStatus => 1005,
+            CloseCode::Abnormal => 1006,
+            CloseCode::InvalidPayload => 1007,
+            CloseCode::Policy => 1008,
+            CloseCode::MessageTooBig => 1009,
+            CloseCode::MandatoryExtension => 1010,
+            CloseCode::InternalError => 1011,
+            CloseCode::Restart => 1012,
+            CloseCode::TryAgainLater => 1013,
+            CloseCode::BadGateway => 1014,
+            CloseCode::Tls => 1015,
+            CloseCode::Other(code) => code,
+        }
+    }
+}
+
+impl Display for CloseCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({:?})", u16::from(*self), self)
+    }
+}
+
+/// Decodes close frame payload into status code and reason. Empty payload is valid and
+/// results in [`CloseCode::NoStatus`].
+pub(crate) fn decode_close_payload(payload: &[u8]) -> Result<(CloseCode, String), Error> {
+    match payload {
+        [] => Ok((CloseCode::NoStatus, String::new())),
+        [_] => Err(Error::Protocol("close frame payload with a single byte".to_string())),
+        [b0, b1, reason @ ..] => {
+            let code = CloseCode::from(u16::from_be_bytes([*b0, *b1]));
+            Ok((code, String::from_utf8_lossy(reason).into_owned()))
+        }
+    }
+}
+
+/// Encodes status code and reason into the close frame payload.
+pub(crate) fn encode_close_payload(code: CloseCode, reason: &str) -> Result<Vec<u8>, Error> {
+    if !code.is_allowed() {
+        return Err(Error::Protocol(format!("status code {code} must not be sent in the close frame")));
+    }
+    if reason.len() > MAX_CLOSE_REASON_LEN {
+        return Err(Error::Protocol(format!(
+            "close reason length {} exceeds {MAX_CLOSE_REASON_LEN} bytes",
+            reason.len()
+        )));
+    }
+    let mut payload = Vec::with_capacity(2 + reason.len());
+    payload.extend_from_slice(&u16::from(code).to_be_bytes());
+    payload.extend_from_slice(reason.as_bytes());
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_convert_close_code() {
+        for code in 1000..=5000u16 {
+            assert_eq!(code, u16::from(CloseCode::from(code)));
+        }
+        assert_eq!(CloseCode::MessageTooBig, CloseCode::from(1009));
+        assert_eq!("1000 (Normal)", CloseCode::Normal.to_string());
+    }
+
+    #[test]
+    fn should_decode_close_payload() {
+        assert_eq!((CloseCode::NoStatus, String::new()), decode_close_payload(b"").unwrap());
+        assert_eq!((CloseCode::Away, String::new()), decode_close_payload(&[0x03, 0xe9]).unwrap());
+        assert_eq!((CloseCode::Normal, "bye".to_string()), decode_close_payload(b"\x03\xe8bye").unwrap());
+        assert!(decode_close_payload(&[0x03]).is_err());
+    }
+
+    #[test]
+    fn should_encode_close_payload() {
+        assert_eq!(b"\x03\xe8bye", encode_close_payload(CloseCode::Normal, "bye").unwrap().as_slice());
+        assert_eq!(&[0x0f, 0xa0], encode_close_payload(CloseCode::Other(4000), "").unwrap().as_slice());
+        assert!(encode_close_payload(CloseCode::NoStatus, "").is_err());
+        assert!(encode_close_payload(CloseCode::Other(2000), "").is_err());
+        assert!(encode_close_payload(CloseCode::Normal, &"a".repeat(124)).is_err());
+    }
+}
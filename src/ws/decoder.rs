@@ -1,17 +1,46 @@
 use crate::buffer::{BufferPoolRef, OwnedReadBuffer};
 use crate::util::into_array;
-use crate::ws::{Error, WebsocketFrame, protocol};
+#[cfg(feature = "ws-deflate")]
+use crate::ws::deflate::{DeflateParams, Inflate};
+use crate::ws::encoder::apply_mask;
+use crate::ws::{Error, Role, WebsocketFrame, protocol};
 use std::io;
 use std::io::Read;
 
+/// Upper bounds on the size of inbound frames and reassembled messages. By default, no limits
+/// are enforced.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DecoderLimits {
+    pub max_frame_size: usize,
+    pub max_message_size: usize,
+}
+
+impl Default for DecoderLimits {
+    fn default() -> Self {
+        Self {
+            max_frame_size: usize::MAX,
+            max_message_size: usize::MAX,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Decoder {
     buffer: OwnedReadBuffer<4096>,
     decode_state: DecodeState,
+    role: Role,
+    masking_key: [u8; 4],
     fin: bool,
     payload_length: usize,
+    message_length: usize,
+    limits: DecoderLimits,
     op_code: u8,
     needs_more_data: bool,
+    /// Set until the first read, frames that arrived together with the handshake response may
+    /// already be waiting in the stream without the selector reporting them again.
+    unread: bool,
+    #[cfg(feature = "ws-deflate")]
+    inflate: Option<Inflate>,
 }
 
 #[derive(Debug)]
@@ -20,32 +49,74 @@ enum DecodeState {
     ReadingPayloadLength,
     ReadingExtendedPayloadLength2,
     ReadingExtendedPayloadLength8,
+    ReadingMaskingKey,
     ReadingPayload,
 }
 
 impl Decoder {
     pub fn new(pool: &mut BufferPoolRef) -> Self {
+        Self::new_with_role(Role::Client, pool)
+    }
+
+    /// Frames received by the server must be masked and are unmasked in place, while frames
+    /// received by the client must not be masked.
+    pub fn new_with_role(role: Role, pool: &mut BufferPoolRef) -> Self {
         Self {
             buffer: pool.acquire(),
             decode_state: DecodeState::ReadingHeader,
+            role,
+            masking_key: [0; 4],
             fin: false,
             op_code: 0,
             payload_length: 0,
+            message_length: 0,
+            limits: DecoderLimits::default(),
             needs_more_data: true,
+            unread: true,
+            #[cfg(feature = "ws-deflate")]
+            inflate: None,
         }
     }
 
     #[inline]
-    pub fn read<S: Read>(&mut self, stream: &mut S) -> io::Result<()> {
+    pub const fn limits_mut(&mut self) -> &mut DecoderLimits {
+        &mut self.limits
+    }
+
+    /// Inflate compressed inbound messages using negotiated `permessage-deflate` parameters.
+    #[cfg(feature = "ws-deflate")]
+    pub fn set_inflate(&mut self, params: &DeflateParams) {
+        self.inflate = Some(Inflate::new(params));
+    }
+
+    /// Returns `true` if the buffer might contain more frames that can be decoded without reading
+    /// from the stream or the stream has not been read yet.
+    #[inline]
+    pub fn has_pending(&self) -> bool {
+        self.unread || (!self.needs_more_data && self.buffer.available() > 0)
+    }
+
+    /// Reads from the `stream` if more data is needed and returns the number of bytes read.
+    #[inline]
+    pub fn read<S: Read>(&mut self, stream: &mut S) -> io::Result<usize> {
+        self.unread = false;
         if self.needs_more_data {
+            #[cfg(feature = "ws-deflate")]
+            if let Some(inflate) = self.inflate.as_mut() {
+                inflate.reset();
+            }
+            let available = self.buffer.available();
             self.buffer.read_all_from(stream)?;
             self.needs_more_data = false;
+            return Ok(self.buffer.available() - available);
         }
-        Ok(())
+        Ok(0)
     }
 
     #[inline]
-    pub fn decode_next(&mut self) -> Result<Option<WebsocketFrame>, Error> {
+    /// Decodes the next frame from the buffer. The payload remains valid until the next call
+    /// to [`Decoder::read`].
+    pub fn decode_next(&mut self) -> Result<Option<WebsocketFrame<'static>>, Error> {
         loop {
             let available = self.buffer.available();
             match self.decode_state {
@@ -57,11 +128,18 @@ impl Decoder {
                         let rsv1 = (b & protocol::RSV1_MASK) >> 6;
                         let rsv2 = (b & protocol::RSV2_MASK) >> 5;
                         let rsv3 = (b & protocol::RSV3_MASK) >> 4;
+                        let op_code = b & protocol::OP_CODE_MASK;
+                        #[cfg(feature = "ws-deflate")]
+                        if let Some(inflate) = self.inflate.as_mut() {
+                            inflate.on_frame_header(rsv1, rsv2, rsv3, op_code)?;
+                        } else if rsv1 + rsv2 + rsv3 != 0 {
+                            return Err(Error::Protocol("non zero RSV value received".to_string()));
+                        }
+                        #[cfg(not(feature = "ws-deflate"))]
                         if rsv1 + rsv2 + rsv3 != 0 {
                             return Err(Error::Protocol("non zero RSV value received".to_string()));
                         }
                         self.fin = fin;
-                        let op_code = b & protocol::OP_CODE_MASK;
                         self.op_code = op_code;
                         self.decode_state = DecodeState::ReadingPayloadLength
                     } else {
@@ -73,13 +151,21 @@ impl Decoder {
                         // SAFETY: available > 0
                         let b = unsafe { self.buffer.consume_next_byte_unchecked() };
                         let mask = (b & protocol::MASK_MASK) >> 7;
-                        if mask == 1 {
-                            return Err(Error::Protocol("masking bit set on the server frame".to_string()));
+                        match self.role {
+                            Role::Client if mask == 1 => {
+                                return Err(Error::Protocol("masking bit set on the server frame".to_string()));
+                            }
+                            Role::Server if mask == 0 => {
+                                return Err(Error::Protocol("masking bit not set on the client frame".to_string()));
+                            }
+                            _ => {}
                         }
                         let payload_length = b & protocol::PAYLOAD_LENGTH_MASK;
-                        self.payload_length = payload_length as usize;
                         match payload_length {
-                            0..=125 => self.decode_state = DecodeState::ReadingPayload,
+                            0..=125 => {
+                                self.set_payload_length(payload_length as usize)?;
+                                self.decode_state = self.payload_state()
+                            }
                             126 => self.decode_state = DecodeState::ReadingExtendedPayloadLength2,
                             127 => self.decode_state = DecodeState::ReadingExtendedPayloadLength8,
                             // we only use 7 bits
@@ -95,8 +181,8 @@ impl Decoder {
                         let bytes = unsafe { self.buffer.consume_next_unchecked(2) };
                         // SAFETY: we know bytes length is 2
                         let payload_length = u16::from_be_bytes(unsafe { into_array(bytes) });
-                        self.payload_length = payload_length as usize;
-                        self.decode_state = DecodeState::ReadingPayload;
+                        self.set_payload_length(payload_length as usize)?;
+                        self.decode_state = self.payload_state();
                     } else {
                         break;
                     }
@@ -107,7 +193,18 @@ impl Decoder {
                         let bytes = unsafe { self.buffer.consume_next_unchecked(8) };
                         // SAFETY: we know bytes length is 8
                         let payload_length = u64::from_be_bytes(unsafe { into_array(bytes) });
-                        self.payload_length = payload_length as usize;
+                        self.set_payload_length(usize::try_from(payload_length).unwrap_or(usize::MAX))?;
+                        self.decode_state = self.payload_state();
+                    } else {
+                        break;
+                    }
+                }
+                DecodeState::ReadingMaskingKey => {
+                    if available >= 4 {
+                        // SAFETY: available >= 4
+                        let bytes = unsafe { self.buffer.consume_next_unchecked(4) };
+                        // SAFETY: we know bytes length is 4
+                        self.masking_key = unsafe { into_array(bytes) };
                         self.decode_state = DecodeState::ReadingPayload;
                     } else {
                         break;
@@ -116,8 +213,25 @@ impl Decoder {
                 DecodeState::ReadingPayload => {
                     let payload_length = self.payload_length;
                     if available >= payload_length {
-                        // SAFETY: available >= payload_length
-                        let payload = unsafe { self.buffer.consume_next_unchecked(payload_length) };
+                        let payload = match self.role {
+                            // SAFETY: available >= payload_length
+                            Role::Client => unsafe { self.buffer.consume_next_unchecked(payload_length) },
+                            Role::Server => {
+                                // SAFETY: available >= payload_length
+                                let payload = unsafe { self.buffer.consume_next_mut_unchecked(payload_length) };
+                                apply_mask(payload, self.masking_key);
+                                payload
+                            }
+                        };
+                        #[cfg(feature = "ws-deflate")]
+                        let payload = match self.inflate.as_mut() {
+                            // compressed length has already been checked, make sure the inflated
+                            // message does not exceed the limit either
+                            Some(inflate) if inflate.is_compressed(self.op_code) => {
+                                inflate.inflate(payload, self.fin, self.limits.max_message_size)?
+                            }
+                            _ => payload,
+                        };
                         let frame = match self.op_code {
                             protocol::op::TEXT_FRAME => WebsocketFrame::Text(self.fin, payload),
                             protocol::op::BINARY_FRAME => WebsocketFrame::Binary(self.fin, payload),
@@ -140,4 +254,146 @@ impl Decoder {
         self.needs_more_data = true;
         Ok(None)
     }
+
+    #[inline]
+    const fn payload_state(&self) -> DecodeState {
+        match self.role {
+            Role::Client => DecodeState::ReadingPayload,
+            Role::Server => DecodeState::ReadingMaskingKey,
+        }
+    }
+
+    /// Validates the frame payload length against the configured limits before the payload is
+    /// buffered. The length of data frames is also accumulated until the final fragment is
+    /// received in order to bound the size of the reassembled message.
+    #[inline]
+    fn set_payload_length(&mut self, payload_length: usize) -> Result<(), Error> {
+        if payload_length > self.limits.max_frame_size {
+            return Err(Error::MessageTooBig(payload_length, self.limits.max_frame_size));
+        }
+        match self.op_code {
+            protocol::op::TEXT_FRAME | protocol::op::BINARY_FRAME => self.message_length = payload_length,
+            protocol::op::CONTINUATION_FRAME => {
+                self.message_length = self.message_length.saturating_add(payload_length)
+            }
+            _ => {}
+        }
+        if self.message_length > self.limits.max_message_size {
+            return Err(Error::MessageTooBig(self.message_length, self.limits.max_message_size));
+        }
+        self.payload_length = payload_length;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "ws-deflate")]
+    use crate::ws::deflate::DeflateConfig;
+    use std::io::Cursor;
+
+    #[test]
+    fn should_reject_frame_exceeding_max_frame_size() {
+        let mut decoder = Decoder::new(&mut Default::default());
+        decoder.limits_mut().max_frame_size = 1024;
+
+        // binary frame header declaring 2^32 bytes payload
+        let mut stream = Cursor::new(vec![0x82, 0x7f, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]);
+        decoder.read(&mut stream).unwrap();
+
+        assert!(matches!(decoder.decode_next(), Err(Error::MessageTooBig(0x1_0000_0000, 1024))));
+    }
+
+    #[test]
+    fn should_reject_message_exceeding_max_message_size() {
+        let mut decoder = Decoder::new(&mut Default::default());
+        decoder.limits_mut().max_message_size = 8;
+
+        let mut stream = Cursor::new(vec![
+            // text frame 'Hello' (fin not set)
+            0x01, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f, // ping frame in between fragments
+            0x89, 0x00, // continuation frame 'Hello' (fin set)
+            0x80, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f,
+        ]);
+        decoder.read(&mut stream).unwrap();
+
+        assert!(matches!(decoder.decode_next(), Ok(Some(WebsocketFrame::Text(false, b"Hello")))));
+        assert!(matches!(decoder.decode_next(), Ok(Some(WebsocketFrame::Ping(b"")))));
+        assert!(matches!(decoder.decode_next(), Err(Error::MessageTooBig(10, 8))));
+    }
+
+    #[test]
+    fn should_decode_frames_within_limits() {
+        let mut decoder = Decoder::new(&mut Default::default());
+        *decoder.limits_mut() = DecoderLimits {
+            max_frame_size: 5,
+            max_message_size: 5,
+        };
+
+        let mut stream = Cursor::new(vec![
+            0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x82, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f,
+        ]);
+        decoder.read(&mut stream).unwrap();
+
+        assert!(matches!(decoder.decode_next(), Ok(Some(WebsocketFrame::Text(true, b"Hello")))));
+        assert!(matches!(decoder.decode_next(), Ok(Some(WebsocketFrame::Binary(true, b"Hello")))));
+    }
+
+    #[test]
+    fn should_unmask_client_frames_as_server() {
+        let mut decoder = Decoder::new_with_role(Role::Server, &mut Default::default());
+
+        // example taken from RFC 6455 section 5.7
+        let mut stream = Cursor::new(vec![0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58]);
+        decoder.read(&mut stream).unwrap();
+
+        assert!(matches!(decoder.decode_next(), Ok(Some(WebsocketFrame::Text(true, b"Hello")))));
+    }
+
+    #[test]
+    fn should_reject_unmasked_client_frame_as_server() {
+        let mut decoder = Decoder::new_with_role(Role::Server, &mut Default::default());
+
+        let mut stream = Cursor::new(vec![0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f]);
+        decoder.read(&mut stream).unwrap();
+
+        assert!(matches!(decoder.decode_next(), Err(Error::Protocol(_))));
+    }
+
+    #[cfg(feature = "ws-deflate")]
+    #[test]
+    fn should_decode_compressed_and_uncompressed_frames() {
+        let mut decoder = Decoder::new(&mut Default::default());
+        decoder.set_inflate(&DeflateConfig::new().into_params());
+
+        let mut stream = Cursor::new(vec![
+            // compressed 'Hello' (RFC 7692 section 7.2.3.1)
+            0xc1, 0x07, 0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00, // uncompressed 'Hello'
+            0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f,
+        ]);
+        decoder.read(&mut stream).unwrap();
+
+        match decoder.decode_next().unwrap() {
+            Some(WebsocketFrame::Text(true, payload)) => assert_eq!(b"Hello", payload),
+            _ => panic!("expected compressed text frame"),
+        }
+        match decoder.decode_next().unwrap() {
+            Some(WebsocketFrame::Text(true, payload)) => assert_eq!(b"Hello", payload),
+            _ => panic!("expected uncompressed text frame"),
+        }
+        assert!(decoder.decode_next().unwrap().is_none());
+    }
+
+    #[cfg(feature = "ws-deflate")]
+    #[test]
+    fn should_reject_rsv1_on_control_frame() {
+        let mut decoder = Decoder::new(&mut Default::default());
+        decoder.set_inflate(&DeflateConfig::new().into_params());
+
+        let mut stream = Cursor::new(vec![0xc9, 0x00]);
+        decoder.read(&mut stream).unwrap();
+
+        assert!(decoder.decode_next().is_err());
+    }
 }
@@ -0,0 +1,559 @@
+//! Per-message compression extension as defined by [RFC 7692](https://datatracker.ietf.org/doc/html/rfc7692).
+//!
+//! ## Examples
+//!
+//! Offer `permessage-deflate` during the handshake.
+//!```no_run
+//! use boomnet::stream::ConnectionInfo;
+//! use boomnet::stream::tls::IntoTlsStream;
+//! use boomnet::ws::IntoWebsocket;
+//! use boomnet::ws::deflate::DeflateConfig;
+//!
+//! let mut ws = ConnectionInfo::new("stream.binance.com", 9443)
+//!     .into_tcp_stream().unwrap()
+//!     .into_tls_stream().unwrap()
+//!     .into_websocket("/ws")
+//!     .with_permessage_deflate(DeflateConfig::new().with_client_no_context_takeover());
+//! ```
+
+use crate::ws::{Error, protocol};
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use std::io;
+
+/// Extension token used during negotiation.
+const EXTENSION_NAME: &str = "permessage-deflate";
+
+/// Bytes removed from the tail of each compressed message (and appended back before inflating).
+const TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Output buffer growth step used when inflating or deflating.
+const CHUNK_SIZE: usize = 4096;
+
+const MIN_WINDOW_BITS: u8 = 8;
+/// zlib does not support raw deflate streams with 8 bit window, so the client can not compress with it.
+const MIN_CLIENT_WINDOW_BITS: u8 = 9;
+const MAX_WINDOW_BITS: u8 = 15;
+
+/// Client side `permessage-deflate` offer.
+#[derive(Debug, Copy, Clone)]
+pub struct DeflateConfig {
+    client_max_window_bits: Option<u8>,
+    server_max_window_bits: Option<u8>,
+    client_no_context_takeover: bool,
+    server_no_context_takeover: bool,
+    compression_level: u32,
+}
+
+impl Default for DeflateConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeflateConfig {
+    /// Create default offer that lets the server pick the window sizes and keeps the compression
+    /// context between messages.
+    pub const fn new() -> DeflateConfig {
+        Self {
+            client_max_window_bits: None,
+            server_max_window_bits: None,
+            client_no_context_takeover: false,
+            server_no_context_takeover: false,
+            compression_level: 6,
+        }
+    }
+
+    /// Limit the LZ77 sliding window used by the client to compress messages. Will panic if
+    /// `bits` is not within `9..=15` range, as 8 bit window is not supported by zlib.
+    pub const fn with_client_max_window_bits(self, bits: u8) -> DeflateConfig {
+        assert!(
+            bits >= MIN_CLIENT_WINDOW_BITS && bits <= MAX_WINDOW_BITS,
+            "client window bits must be within 9..=15 range"
+        );
+        Self {
+            client_max_window_bits: Some(bits),
+            ..self
+        }
+    }
+
+    /// Ask the server to limit the LZ77 sliding window used to compress messages. Will panic if
+    /// `bits` is not within `8..=15` range.
+    pub const fn with_server_max_window_bits(self, bits: u8) -> DeflateConfig {
+        assert!(bits >= MIN_WINDOW_BITS && bits <= MAX_WINDOW_BITS, "window bits must be within 8..=15 range");
+        Self {
+            server_max_window_bits: Some(bits),
+            ..self
+        }
+    }
+
+    /// Reset the client compression context after each message.
+    pub const fn with_client_no_context_takeover(self) -> DeflateConfig {
+        Self {
+            client_no_context_takeover: true,
+            ..self
+        }
+    }
+
+    /// Ask the server to reset its compression context after each message.
+    pub const fn with_server_no_context_takeover(self) -> DeflateConfig {
+        Self {
+            server_no_context_takeover: true,
+            ..self
+        }
+    }
+
+    /// Compression level (`0..=9`) used for outbound messages.
+    pub const fn with_compression_level(self, compression_level: u32) -> DeflateConfig {
+        Self {
+            compression_level,
+            ..self
+        }
+    }
+
+    /// Value of the `Sec-WebSocket-Extensions` request header.
+    pub(crate) fn offer(&self) -> String {
+        let mut offer = String::from(EXTENSION_NAME);
+        match self.client_max_window_bits {
+            Some(bits) => offer.push_str(&format!("; client_max_window_bits={bits}")),
+            None => offer.push_str("; client_max_window_bits"),
+        }
+        if let Some(bits) = self.server_max_window_bits {
+            offer.push_str(&format!("; server_max_window_bits={bits}"));
+        }
+        if self.client_no_context_takeover {
+            offer.push_str("; client_no_context_takeover");
+        }
+        if self.server_no_context_takeover {
+            offer.push_str("; server_no_context_takeover");
+        }
+        offer
+    }
+
+    /// Treat this offer as accepted by the server without any modifications.
+    pub(crate) const fn into_params(self) -> DeflateParams {
+        DeflateParams {
+            client_max_window_bits: match self.client_max_window_bits {
+                Some(bits) => bits,
+                None => MAX_WINDOW_BITS,
+            },
+            server_max_window_bits: match self.server_max_window_bits {
+                Some(bits) => bits,
+                None => MAX_WINDOW_BITS,
+            },
+            client_no_context_takeover: self.client_no_context_takeover,
+            server_no_context_takeover: self.server_no_context_takeover,
+            compression_level: self.compression_level,
+        }
+    }
+
+    /// Validate the server response to our offer. Returns `Ok(None)` if the server has not
+    /// accepted the extension.
+    pub(crate) fn negotiate<'a>(
+        &self,
+        extensions: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Option<DeflateParams>, Error> {
+        let mut negotiated = None;
+        for extension in extensions.into_iter().flat_map(|value| value.split(',')) {
+            let mut params = extension.split(';').map(str::trim);
+            match params.next() {
+                Some(EXTENSION_NAME) => {}
+                Some("") | None => continue,
+                Some(name) => return Err(Error::Protocol(format!("unsupported extension negotiated: {name}"))),
+            }
+            if negotiated.is_some() {
+                return Err(Error::Protocol(format!("{EXTENSION_NAME} negotiated more than once")));
+            }
+            let mut accepted = DeflateParams {
+                client_max_window_bits: self.client_max_window_bits.unwrap_or(MAX_WINDOW_BITS),
+                server_max_window_bits: MAX_WINDOW_BITS,
+                client_no_context_takeover: self.client_no_context_takeover,
+                server_no_context_takeover: false,
+                compression_level: self.compression_level,
+            };
+            for param in params {
+                let (key, value) = match param.split_once('=') {
+                    Some((key, value)) => (key.trim(), Some(value.trim().trim_matches('"'))),
+                    None => (param, None),
+                };
+                match (key, value) {
+                    ("client_no_context_takeover", None) => accepted.client_no_context_takeover = true,
+                    ("server_no_context_takeover", None) => accepted.server_no_context_takeover = true,
+                    ("client_max_window_bits", Some(bits)) => {
+                        let bits = parse_window_bits(bits, MIN_CLIENT_WINDOW_BITS)?;
+                        accepted.client_max_window_bits = bits.min(accepted.client_max_window_bits);
+                    }
+                    ("server_max_window_bits", Some(bits)) => {
+                        let bits = parse_window_bits(bits, MIN_WINDOW_BITS)?;
+                        if self.server_max_window_bits.is_some_and(|max| bits > max) {
+                            return Err(Error::Protocol(format!("server_max_window_bits={bits} exceeds the offer")));
+                        }
+                        accepted.server_max_window_bits = bits;
+                    }
+                    _ => return Err(Error::Protocol(format!("invalid {EXTENSION_NAME} parameter: {param}"))),
+                }
+            }
+            negotiated = Some(accepted);
+        }
+        Ok(negotiated)
+    }
+}
+
+fn parse_window_bits(bits: &str, min_window_bits: u8) -> Result<u8, Error> {
+    match bits.parse::<u8>() {
+        Ok(bits) if (min_window_bits..=MAX_WINDOW_BITS).contains(&bits) => Ok(bits),
+        _ => Err(Error::Protocol(format!("invalid window bits value: {bits}"))),
+    }
+}
+
+/// Negotiated `permessage-deflate` parameters.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DeflateParams {
+    client_max_window_bits: u8,
+    server_max_window_bits: u8,
+    client_no_context_takeover: bool,
+    server_no_context_takeover: bool,
+    compression_level: u32,
+}
+
+impl DeflateParams {
+    /// LZ77 sliding window size used by the client to compress messages.
+    pub const fn client_max_window_bits(&self) -> u8 {
+        self.client_max_window_bits
+    }
+
+    /// LZ77 sliding window size used by the server to compress messages.
+    pub const fn server_max_window_bits(&self) -> u8 {
+        self.server_max_window_bits
+    }
+
+    /// Returns `true` if the client resets compression context after each message.
+    pub const fn client_no_context_takeover(&self) -> bool {
+        self.client_no_context_takeover
+    }
+
+    /// Returns `true` if the server resets compression context after each message.
+    pub const fn server_no_context_takeover(&self) -> bool {
+        self.server_no_context_takeover
+    }
+}
+
+/// Decompresses inbound messages.
+#[derive(Debug)]
+pub(crate) struct Inflate {
+    decompress: Decompress,
+    // each inflated frame gets its own buffer so that frames decoded from the same batch
+    // remain valid until the next network read
+    buffers: Vec<Vec<u8>>,
+    used: usize,
+    no_context_takeover: bool,
+    compressed: bool,
+    /// Number of bytes inflated so far for the current message.
+    message_length: usize,
+}
+
+impl Inflate {
+    pub fn new(params: &DeflateParams) -> Self {
+        Self {
+            // a decoder using the largest window can decode any of the smaller windows
+            decompress: Decompress::new(false),
+            buffers: Vec::new(),
+            used: 0,
+            no_context_takeover: params.server_no_context_takeover,
+            compressed: false,
+            message_length: 0,
+        }
+    }
+
+    /// Validate the frame header RSV bits and track whether the current message is compressed.
+    #[inline]
+    pub fn on_frame_header(&mut self, rsv1: u8, rsv2: u8, rsv3: u8, op_code: u8) -> Result<(), Error> {
+        if rsv2 + rsv3 != 0 {
+            return Err(Error::Protocol("non zero RSV value received".to_string()));
+        }
+        match op_code {
+            protocol::op::TEXT_FRAME | protocol::op::BINARY_FRAME => {
+                self.compressed = rsv1 == 1;
+                self.message_length = 0;
+            }
+            _ if rsv1 == 1 => return Err(Error::Protocol("RSV1 set on non starting frame".to_string())),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the payload of the frame with `op_code` needs to be inflated.
+    #[inline]
+    pub const fn is_compressed(&self, op_code: u8) -> bool {
+        self.compressed
+            && matches!(
+                op_code,
+                protocol::op::TEXT_FRAME | protocol::op::BINARY_FRAME | protocol::op::CONTINUATION_FRAME
+            )
+    }
+
+    /// Inflate the frame `payload`, failing as soon as the inflated message (across all of its
+    /// fragments) exceeds `max_message_size`. The returned slice is only valid until
+    /// [`Inflate::reset`] is called.
+    #[inline]
+    pub fn inflate(&mut self, payload: &[u8], fin: bool, max_message_size: usize) -> Result<&'static [u8], Error> {
+        if self.used == self.buffers.len() {
+            self.buffers.push(Vec::with_capacity(CHUNK_SIZE));
+        }
+        let buffer = &mut self.buffers[self.used];
+        buffer.clear();
+        let inflated = self.message_length;
+        decompress(&mut self.decompress, buffer, payload, inflated, max_message_size)?;
+        if fin {
+            decompress(&mut self.decompress, buffer, &TRAILER, inflated, max_message_size)?;
+            if self.no_context_takeover {
+                self.decompress.reset(false);
+            }
+        }
+        self.message_length += buffer.len();
+        self.used += 1;
+        // SAFETY: the buffer is not modified (or moved) until reset
+        Ok(unsafe { &*std::ptr::slice_from_raw_parts(buffer.as_ptr(), buffer.len()) })
+    }
+
+    /// Make all buffers available for reuse, must only be called once the previously inflated
+    /// payloads are no longer referenced.
+    #[inline]
+    pub const fn reset(&mut self) {
+        self.used = 0;
+    }
+}
+
+/// Inflate the `input` into the `buffer`. The buffer never grows past one byte over what is left of
+/// the `max_message_size` once the `inflated` bytes of the previous fragments are accounted for,
+/// so that compressed payload cannot make us allocate without bound.
+fn decompress(
+    decompress: &mut Decompress,
+    buffer: &mut Vec<u8>,
+    mut input: &[u8],
+    inflated: usize,
+    max_message_size: usize,
+) -> Result<(), Error> {
+    let limit = max_message_size.saturating_sub(inflated).saturating_add(1);
+    loop {
+        if buffer.len() == buffer.capacity() {
+            let additional = buffer.capacity().max(CHUNK_SIZE).min(limit - buffer.len());
+            buffer.reserve_exact(additional);
+        }
+        let total_in = decompress.total_in();
+        let status = decompress
+            .decompress_vec(input, buffer, FlushDecompress::Sync)
+            .map_err(io::Error::other)?;
+        let message_length = inflated.saturating_add(buffer.len());
+        if message_length > max_message_size {
+            return Err(Error::MessageTooBig(message_length, max_message_size));
+        }
+        input = &input[(decompress.total_in() - total_in) as usize..];
+        match status {
+            Status::StreamEnd => return Ok(()),
+            _ if input.is_empty() && buffer.len() < buffer.capacity() => return Ok(()),
+            Status::BufError if buffer.len() < buffer.capacity() => {
+                return Err(Error::Protocol("unable to inflate the message".to_string()));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Compresses outbound messages.
+#[derive(Debug)]
+pub(crate) struct Deflate {
+    params: DeflateParams,
+    compress: Compress,
+    buffer: Vec<u8>,
+}
+
+impl Deflate {
+    pub fn new(params: DeflateParams) -> Self {
+        let window_bits = params.client_max_window_bits;
+        Self {
+            params,
+            compress: Compress::new_with_window_bits(Compression::new(params.compression_level), false, window_bits),
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        }
+    }
+
+    #[inline]
+    pub const fn params(&self) -> &DeflateParams {
+        &self.params
+    }
+
+    /// Returns `true` if the frame with `op_code` should be compressed. Only complete data
+    /// messages are compressed.
+    #[inline]
+    pub const fn should_compress(&self, fin: bool, op_code: u8) -> bool {
+        fin && matches!(op_code, protocol::op::TEXT_FRAME | protocol::op::BINARY_FRAME)
+    }
+
+    /// Compress the whole message `payload` and strip the trailing empty block.
+    pub fn deflate(&mut self, mut input: &[u8]) -> io::Result<&[u8]> {
+        self.buffer.clear();
+        loop {
+            if self.buffer.len() == self.buffer.capacity() {
+                self.buffer.reserve(CHUNK_SIZE);
+            }
+            let total_in = self.compress.total_in();
+            self.compress
+                .compress_vec(input, &mut self.buffer, FlushCompress::Sync)
+                .map_err(io::Error::other)?;
+            input = &input[(self.compress.total_in() - total_in) as usize..];
+            if input.is_empty() && self.buffer.len() < self.buffer.capacity() {
+                break;
+            }
+        }
+        if self.buffer.ends_with(&TRAILER) {
+            self.buffer.truncate(self.buffer.len() - TRAILER.len());
+        }
+        if self.params.client_no_context_takeover {
+            self.compress.reset();
+        }
+        Ok(&self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_create_offer() {
+        assert_eq!("permessage-deflate; client_max_window_bits", DeflateConfig::new().offer());
+        assert_eq!(
+            "permessage-deflate; client_max_window_bits=10; server_max_window_bits=12; client_no_context_takeover",
+            DeflateConfig::new()
+                .with_client_max_window_bits(10)
+                .with_server_max_window_bits(12)
+                .with_client_no_context_takeover()
+                .offer()
+        );
+    }
+
+    #[test]
+    fn should_negotiate_params() {
+        let config = DeflateConfig::new();
+        let params = config
+            .negotiate(["permessage-deflate; server_no_context_takeover; client_max_window_bits=\"11\""])
+            .unwrap()
+            .unwrap();
+        assert_eq!(11, params.client_max_window_bits());
+        assert_eq!(15, params.server_max_window_bits());
+        assert!(params.server_no_context_takeover());
+        assert!(!params.client_no_context_takeover());
+
+        assert_eq!(None, config.negotiate([]).unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "client window bits must be within 9..=15 range")]
+    fn should_not_offer_client_window_bits_not_supported_by_zlib() {
+        let _ = DeflateConfig::new().with_client_max_window_bits(8);
+    }
+
+    #[test]
+    fn should_reject_invalid_negotiation() {
+        let config = DeflateConfig::new().with_server_max_window_bits(10);
+        assert!(
+            config
+                .negotiate(["permessage-deflate; server_max_window_bits=12"])
+                .is_err()
+        );
+        assert!(
+            config
+                .negotiate(["permessage-deflate; client_max_window_bits=7"])
+                .is_err()
+        );
+        assert!(
+            config
+                .negotiate(["permessage-deflate; client_max_window_bits=8"])
+                .is_err()
+        );
+        assert!(
+            config
+                .negotiate(["permessage-deflate; server_max_window_bits=8"])
+                .is_ok()
+        );
+        assert!(config.negotiate(["permessage-deflate; unknown_param"]).is_err());
+        assert!(config.negotiate(["x-webkit-deflate-frame"]).is_err());
+        assert!(config.negotiate(["permessage-deflate, permessage-deflate"]).is_err());
+    }
+
+    #[test]
+    fn should_inflate_message() {
+        // example taken from RFC 7692 section 7.2.3.1
+        let params = DeflateConfig::new().into_params();
+        let mut inflate = Inflate::new(&params);
+        inflate.on_frame_header(1, 0, 0, protocol::op::TEXT_FRAME).unwrap();
+        assert!(inflate.is_compressed(protocol::op::TEXT_FRAME));
+        let payload = inflate
+            .inflate(&[0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00], true, usize::MAX)
+            .unwrap();
+        assert_eq!(b"Hello", payload);
+
+        // same message again using the shared sliding window
+        let second = inflate
+            .inflate(&[0xf2, 0x00, 0x11, 0x00, 0x00], true, usize::MAX)
+            .unwrap();
+        assert_eq!(b"Hello", second);
+
+        // payloads inflated before reset remain valid
+        assert_eq!(b"Hello", payload);
+        assert_ne!(payload.as_ptr(), second.as_ptr());
+        inflate.reset();
+        assert_eq!(0, inflate.used);
+    }
+
+    #[test]
+    fn should_deflate_and_inflate_message() {
+        let params = DeflateConfig::new().with_client_no_context_takeover().into_params();
+        let mut deflate = Deflate::new(params);
+        let mut inflate = Inflate::new(&params);
+        let message = "hello world ".repeat(1000);
+        for _ in 0..3 {
+            let compressed = deflate.deflate(message.as_bytes()).unwrap().to_vec();
+            assert!(compressed.len() < message.len());
+            inflate.on_frame_header(1, 0, 0, protocol::op::TEXT_FRAME).unwrap();
+            assert_eq!(message.as_bytes(), inflate.inflate(&compressed, true, usize::MAX).unwrap());
+        }
+    }
+
+    #[test]
+    fn should_stop_inflating_once_max_message_size_exceeded() {
+        let params = DeflateConfig::new().into_params();
+        let compressed = Deflate::new(params).deflate(&[0u8; 1 << 20]).unwrap().to_vec();
+        assert!(compressed.len() < 2048);
+
+        let mut inflate = Inflate::new(&params);
+        inflate.on_frame_header(1, 0, 0, protocol::op::BINARY_FRAME).unwrap();
+        assert!(matches!(inflate.inflate(&compressed, true, 10_000), Err(Error::MessageTooBig(10_001, 10_000))));
+        assert!(inflate.buffers[0].capacity() <= 10_001);
+    }
+
+    #[test]
+    fn should_limit_inflated_size_across_fragments() {
+        // poorly compressible payload so that each half of the compressed message inflates to
+        // roughly half of it
+        let mut seed = 1u32;
+        let message: Vec<u8> = (0..1500)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        let params = DeflateConfig::new().into_params();
+        let compressed = Deflate::new(params).deflate(&message).unwrap().to_vec();
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+
+        let mut inflate = Inflate::new(&params);
+        inflate.on_frame_header(1, 0, 0, protocol::op::BINARY_FRAME).unwrap();
+        let inflated = inflate.inflate(first, false, 1000).unwrap().len();
+        assert!(inflated > 0 && inflated < 1000);
+        inflate
+            .on_frame_header(0, 0, 0, protocol::op::CONTINUATION_FRAME)
+            .unwrap();
+        assert!(matches!(inflate.inflate(second, true, 1000), Err(Error::MessageTooBig(_, 1000))));
+    }
+}
@@ -1,8 +1,11 @@
-use crate::ws::{Error, State, Websocket, WebsocketFrame};
+use crate::ws::assembler::MessageAssembler;
+use crate::ws::encoder::Encoder;
+use crate::ws::{DEFAULT_CLOSE_TIMEOUT, Error, Masking, State, Websocket, WebsocketFrame};
 use std::io;
 
 pub trait DataSource {
-    fn next(&self) -> Result<Option<WebsocketFrame>, Error>;
+    /// Returns the next frame, the payload of which can borrow from the data source.
+    fn next(&self) -> Result<Option<WebsocketFrame<'_>>, Error>;
 
     fn into_stream(self) -> DataSourceStream<Self>
     where
@@ -17,7 +20,7 @@ pub struct DataSourceStream<D> {
 }
 
 impl<D: DataSource> Websocket<DataSourceStream<D>> {
-    pub fn receive_next(&mut self) -> Result<Option<WebsocketFrame>, Error> {
+    pub fn receive_next(&mut self) -> Result<Option<WebsocketFrame<'_>>, Error> {
         self.stream.data_source.next()
     }
 }
@@ -27,7 +30,13 @@ impl<D: DataSource> Websocket<D> {
         Ok(Websocket {
             stream: data_source.into_stream(),
             closed: false,
+            close_deadline: None,
+            close_timeout: DEFAULT_CLOSE_TIMEOUT,
+            clock: Default::default(),
             state: State::connection(Default::default()),
+            encoder: Encoder::new(Masking::default()),
+            assembler: MessageAssembler::new(Default::default()),
+            keepalive: None,
         })
     }
 }
@@ -41,7 +50,7 @@ mod tests {
         struct CustomDataSource;
 
         impl DataSource for CustomDataSource {
-            fn next(&self) -> Result<Option<WebsocketFrame>, Error> {
+            fn next(&self) -> Result<Option<WebsocketFrame<'_>>, Error> {
                 Ok(Some(WebsocketFrame::Text(true, b"foo")))
             }
         }
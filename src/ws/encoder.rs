@@ -1,37 +1,129 @@
 use std::io;
 use std::io::Write;
 
-use crate::ws::protocol;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
 
-#[inline]
-pub fn send<S: Write>(stream: &mut S, fin: bool, op_code: u8, body: Option<&[u8]>) -> io::Result<()> {
-    let mut header = 0u8;
-    if fin {
-        header |= protocol::FIN_MASK;
+#[cfg(feature = "ws-deflate")]
+use crate::ws::deflate::{Deflate, DeflateParams};
+use crate::ws::{Role, protocol};
+
+/// Maximum size of the frame header (excluding masking key).
+const MAX_HEADER_LEN: usize = 10;
+
+/// Initial capacity of the buffer the payload is masked in.
+const MASKING_BUFFER_CAPACITY: usize = 4096;
+
+/// Controls how outbound frames are masked.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum Masking {
+    /// Use zero masking key. This turns XOR into no-op so the payload can be written as is,
+    /// but some strict servers and proxies will reject it as protocol violation.
+    #[default]
+    ZeroKey,
+    /// Generate random 32-bit masking key for each frame as required by RFC 6455. The payload
+    /// is masked in-place in the outbound buffer before it is written to the stream.
+    RandomKey,
+}
+
+#[derive(Debug)]
+pub struct Encoder {
+    role: Role,
+    masking: Masking,
+    /// Created once the first frame is masked with random key.
+    rng: Option<SmallRng>,
+    buffer: Vec<u8>,
+    #[cfg(feature = "ws-deflate")]
+    deflate: Option<Deflate>,
+}
+
+impl Encoder {
+    pub fn new(masking: Masking) -> Self {
+        Self::new_with_role(Role::Client, masking)
     }
-    header |= op_code;
-    stream.write_all(&header.to_be_bytes())?;
-    let mut payload_length = 0u8;
-    payload_length |= protocol::MASK_MASK;
-    if let Some(body) = body {
-        let len = body.len();
-        if len <= 125 {
-            payload_length |= len as u8;
-            stream.write_all(&payload_length.to_be_bytes())?;
-        } else if len <= u16::MAX as usize {
-            payload_length |= 126;
-            let extended_payload_length = len as u16;
-            stream.write_all(&payload_length.to_be_bytes())?;
-            stream.write_all(&extended_payload_length.to_be_bytes())?;
-        } else if len <= u64::MAX as usize {
-            payload_length |= 127;
-            let extended_payload_length = len as u64;
-            stream.write_all(&payload_length.to_be_bytes())?;
-            stream.write_all(&extended_payload_length.to_be_bytes())?;
+
+    /// Frames sent by the server are never masked, in which case `masking` is ignored.
+    pub fn new_with_role(role: Role, masking: Masking) -> Self {
+        Self {
+            role,
+            masking,
+            rng: None,
+            buffer: Vec::new(),
+            #[cfg(feature = "ws-deflate")]
+            deflate: None,
         }
-    } else {
-        stream.write_all(&payload_length.to_be_bytes())?;
     }
+
+    #[inline]
+    pub const fn masking(&self) -> Masking {
+        self.masking
+    }
+
+    #[inline]
+    pub const fn set_masking(&mut self, masking: Masking) {
+        self.masking = masking;
+    }
+
+    /// Compress outbound data messages using negotiated `permessage-deflate` parameters.
+    #[cfg(feature = "ws-deflate")]
+    pub fn set_deflate(&mut self, params: DeflateParams) {
+        self.deflate = Some(Deflate::new(params));
+    }
+
+    #[cfg(feature = "ws-deflate")]
+    #[inline]
+    pub fn deflate_params(&self) -> Option<&DeflateParams> {
+        self.deflate.as_ref().map(Deflate::params)
+    }
+
+    #[inline]
+    pub fn send<S: Write>(&mut self, stream: &mut S, fin: bool, op_code: u8, body: Option<&[u8]>) -> io::Result<()> {
+        #[cfg(feature = "ws-deflate")]
+        if let Some(deflate) = self.deflate.as_mut() {
+            if deflate.should_compress(fin, op_code) {
+                let body = deflate.deflate(body.unwrap_or_default())?;
+                let op_code = op_code | protocol::RSV1_MASK;
+                return match self.masking {
+                    _ if self.role == Role::Server => send_unmasked(stream, fin, op_code, Some(body)),
+                    Masking::ZeroKey => send(stream, fin, op_code, Some(body)),
+                    Masking::RandomKey => {
+                        let masking_key = random_masking_key(&mut self.rng, &mut self.buffer);
+                        send_masked(stream, &mut self.buffer, fin, op_code, Some(body), masking_key)
+                    }
+                };
+            }
+        }
+        match self.masking {
+            _ if self.role == Role::Server => send_unmasked(stream, fin, op_code, body),
+            Masking::ZeroKey => send(stream, fin, op_code, body),
+            Masking::RandomKey => {
+                let masking_key = random_masking_key(&mut self.rng, &mut self.buffer);
+                send_masked(stream, &mut self.buffer, fin, op_code, body, masking_key)
+            }
+        }
+    }
+}
+
+/// Generates random masking key, seeding the `rng` and allocating the masking `buffer` on first use
+/// so that encoders that never mask with random key do not pay for them.
+#[inline]
+fn random_masking_key(rng: &mut Option<SmallRng>, buffer: &mut Vec<u8>) -> [u8; 4] {
+    match rng {
+        Some(rng) => rng.random::<u32>().to_be_bytes(),
+        None => init_random_masking(rng, buffer),
+    }
+}
+
+#[cold]
+fn init_random_masking(rng: &mut Option<SmallRng>, buffer: &mut Vec<u8>) -> [u8; 4] {
+    buffer.reserve(MASKING_BUFFER_CAPACITY);
+    rng.insert(SmallRng::from_os_rng()).random::<u32>().to_be_bytes()
+}
+
+#[inline]
+pub fn send<S: Write>(stream: &mut S, fin: bool, op_code: u8, body: Option<&[u8]>) -> io::Result<()> {
+    let (header, header_len) = encode_header(fin, op_code, body.map_or(0, |body| body.len()));
+    stream.write_all(&header[..header_len])?;
     let masking_key = 0u32;
     stream.write_all(&masking_key.to_be_bytes())?;
     if let Some(body) = body {
@@ -42,3 +134,191 @@ pub fn send<S: Write>(stream: &mut S, fin: bool, op_code: u8, body: Option<&[u8]
     stream.flush()?;
     Ok(())
 }
+
+/// Writes the frame to the stream without masking key as required for frames sent by the server.
+#[inline]
+fn send_unmasked<S: Write>(stream: &mut S, fin: bool, op_code: u8, body: Option<&[u8]>) -> io::Result<()> {
+    let (mut header, header_len) = encode_header(fin, op_code, body.map_or(0, |body| body.len()));
+    header[1] &= !protocol::MASK_MASK;
+    stream.write_all(&header[..header_len])?;
+    if let Some(body) = body {
+        stream.write_all(body)?;
+    }
+    stream.flush()?;
+    Ok(())
+}
+
+/// Writes the frame to the stream after masking the payload in-place in the outbound `buffer`.
+#[inline]
+fn send_masked<S: Write>(
+    stream: &mut S,
+    buffer: &mut Vec<u8>,
+    fin: bool,
+    op_code: u8,
+    body: Option<&[u8]>,
+    masking_key: [u8; 4],
+) -> io::Result<()> {
+    let body = body.unwrap_or_default();
+    let (header, header_len) = encode_header(fin, op_code, body.len());
+    buffer.clear();
+    buffer.extend_from_slice(&header[..header_len]);
+    buffer.extend_from_slice(&masking_key);
+    let offset = buffer.len();
+    buffer.extend_from_slice(body);
+    apply_mask(&mut buffer[offset..], masking_key);
+    stream.write_all(buffer)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Encodes frame header (with the mask bit set) and returns it together with its length.
+#[inline]
+const fn encode_header(fin: bool, op_code: u8, len: usize) -> ([u8; MAX_HEADER_LEN], usize) {
+    let mut header = [0u8; MAX_HEADER_LEN];
+    if fin {
+        header[0] |= protocol::FIN_MASK;
+    }
+    header[0] |= op_code;
+    header[1] |= protocol::MASK_MASK;
+    if len <= 125 {
+        header[1] |= len as u8;
+        (header, 2)
+    } else if len <= u16::MAX as usize {
+        header[1] |= 126;
+        let extended_payload_length = (len as u16).to_be_bytes();
+        header[2] = extended_payload_length[0];
+        header[3] = extended_payload_length[1];
+        (header, 4)
+    } else {
+        header[1] |= 127;
+        let extended_payload_length = (len as u64).to_be_bytes();
+        let mut i = 0;
+        while i < 8 {
+            header[2 + i] = extended_payload_length[i];
+            i += 1;
+        }
+        (header, MAX_HEADER_LEN)
+    }
+}
+
+/// XOR the `payload` in-place with the `masking_key`. Processes eight bytes at a time and falls
+/// back to byte-by-byte masking for the remainder.
+#[inline]
+pub fn apply_mask(payload: &mut [u8], masking_key: [u8; 4]) {
+    let [k0, k1, k2, k3] = masking_key;
+    let wide_key = u64::from_ne_bytes([k0, k1, k2, k3, k0, k1, k2, k3]);
+    let mut chunks = payload.chunks_exact_mut(8);
+    for chunk in &mut chunks {
+        // SAFETY: chunk is exactly 8 bytes long
+        let word = u64::from_ne_bytes(unsafe { chunk.try_into().unwrap_unchecked() }) ^ wide_key;
+        chunk.copy_from_slice(&word.to_ne_bytes());
+    }
+    // chunks are multiple of 4 so the remainder starts at masking key offset zero
+    for (i, b) in chunks.into_remainder().iter_mut().enumerate() {
+        *b ^= masking_key[i & 3];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_mask_and_unmask_payload() {
+        let masking_key = [0x37, 0xfa, 0x21, 0x3d];
+        let original = b"hello world, this payload is longer than eight bytes!".to_vec();
+        let mut payload = original.clone();
+
+        apply_mask(&mut payload, masking_key);
+        for (i, b) in payload.iter().enumerate() {
+            assert_eq!(original[i] ^ masking_key[i % 4], *b);
+        }
+
+        apply_mask(&mut payload, masking_key);
+        assert_eq!(original, payload);
+    }
+
+    #[test]
+    fn should_send_frame_with_zero_masking_key() {
+        let mut stream = Vec::new();
+        let mut encoder = Encoder::new(Masking::ZeroKey);
+        encoder
+            .send(&mut stream, true, protocol::op::TEXT_FRAME, Some(b"Hello"))
+            .unwrap();
+
+        assert_eq!(&[0x81, 0x85, 0, 0, 0, 0], &stream[..6]);
+        assert_eq!(b"Hello", &stream[6..]);
+    }
+
+    #[test]
+    fn should_send_frame_with_random_masking_key() {
+        let mut stream = Vec::new();
+        let mut buffer = Vec::new();
+        send_masked(&mut stream, &mut buffer, true, protocol::op::TEXT_FRAME, Some(b"Hello"), [0x37, 0xfa, 0x21, 0x3d])
+            .unwrap();
+
+        // example taken from RFC 6455 section 5.7
+        assert_eq!(&[0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58], stream.as_slice());
+    }
+
+    #[test]
+    fn should_create_rng_and_buffer_only_when_masking_with_random_key() {
+        let mut stream = Vec::new();
+        let mut encoder = Encoder::new(Masking::ZeroKey);
+        encoder
+            .send(&mut stream, true, protocol::op::TEXT_FRAME, Some(b"Hello"))
+            .unwrap();
+        assert!(encoder.rng.is_none());
+        assert_eq!(0, encoder.buffer.capacity());
+
+        encoder.set_masking(Masking::RandomKey);
+        encoder
+            .send(&mut stream, true, protocol::op::TEXT_FRAME, Some(b"Hello"))
+            .unwrap();
+        assert!(encoder.rng.is_some());
+        assert!(encoder.buffer.capacity() >= MASKING_BUFFER_CAPACITY);
+    }
+
+    #[test]
+    fn should_encode_extended_payload_length() {
+        let mut stream = Vec::new();
+        let mut encoder = Encoder::new(Masking::RandomKey);
+        let body = vec![0u8; 256];
+        encoder
+            .send(&mut stream, true, protocol::op::BINARY_FRAME, Some(&body))
+            .unwrap();
+
+        assert_eq!(&[0x82, 0xfe, 0x01, 0x00], &stream[..4]);
+        let masking_key: [u8; 4] = stream[4..8].try_into().unwrap();
+        let mut payload = stream[8..].to_vec();
+        apply_mask(&mut payload, masking_key);
+        assert_eq!(body, payload);
+    }
+
+    #[test]
+    fn should_send_unmasked_frame_as_server() {
+        let mut stream = Vec::new();
+        let mut encoder = Encoder::new_with_role(Role::Server, Masking::RandomKey);
+        encoder
+            .send(&mut stream, true, protocol::op::TEXT_FRAME, Some(b"Hello"))
+            .unwrap();
+
+        // example taken from RFC 6455 section 5.7
+        assert_eq!(&[0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f], stream.as_slice());
+    }
+
+    #[cfg(feature = "ws-deflate")]
+    #[test]
+    fn should_send_compressed_frame() {
+        let mut stream = Vec::new();
+        let mut encoder = Encoder::new(Masking::ZeroKey);
+        encoder.set_deflate(crate::ws::deflate::DeflateConfig::new().into_params());
+        encoder
+            .send(&mut stream, true, protocol::op::TEXT_FRAME, Some(b"Hello"))
+            .unwrap();
+
+        // example taken from RFC 7692 section 7.2.3.1
+        assert_eq!(&[0xc1, 0x87, 0, 0, 0, 0], &stream[..6]);
+        assert_eq!(&[0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00], &stream[6..]);
+    }
+}
@@ -1,14 +1,26 @@
+use crate::ws::CloseCode;
 use std::array::TryFromSliceError;
 use std::io;
+use std::time::Duration;
 use thiserror::Error;
 use url::ParseError;
 
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("the peer has sent the close frame: status code {0}, body: {1}")]
-    ReceivedCloseFrame(u16, String),
+    ReceivedCloseFrame(CloseCode, String),
     #[error("websocket protocol error: {0}")]
     Protocol(String),
+    #[error("invalid handshake header: {0}")]
+    InvalidHeader(String),
+    #[error("invalid Sec-WebSocket-Accept header: expected {0}, received {1}")]
+    InvalidAcceptKey(String, String),
+    #[error("frame or message of {0} bytes exceeds the limit of {1} bytes")]
+    MessageTooBig(usize, usize),
+    #[error("no pong received within {0:?} of sending the ping")]
+    PongTimeout(Duration),
+    #[error("no data received within {0:?}")]
+    IdleTimeout(Duration),
     #[error("the websocket is closed and can be dropped")]
     Closed,
     #[error("IO error: {0}")]
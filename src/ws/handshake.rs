@@ -1,26 +1,152 @@
 use crate::buffer::{BufferPoolRef, OwnedReadBuffer};
-use crate::ws::Error;
-use crate::ws::handshake::HandshakeState::{Completed, NotStarted, PendingResponse};
+#[cfg(feature = "ws-deflate")]
+use crate::ws::deflate::{DeflateConfig, DeflateParams};
+use crate::ws::handshake::HandshakeState::{AwaitingRequest, Completed, NotStarted, PendingResponse, SendingResponse};
+use crate::ws::{Error, Role};
 use HandshakeState::PendingRequest;
 use base64::Engine;
 use base64::engine::general_purpose;
-use http::StatusCode;
-use httparse::Response;
+use http::header::{HeaderName, HeaderValue, SEC_WEBSOCKET_PROTOCOL};
+use http::{HeaderMap, StatusCode};
+use httparse::{Request, Response};
 use rand::{Rng, rng};
+use sha1::{Digest, Sha1};
 use std::collections::VecDeque;
 use std::io;
 use std::io::ErrorKind::WouldBlock;
-use std::io::{Cursor, Read, Write};
+use std::io::{Read, Write};
+
+/// Used to customise the handshake request sent to the server.
+///
+/// ## Examples
+///
+/// ```no_run
+/// use boomnet::stream::ConnectionInfo;
+/// use boomnet::stream::tls::IntoTlsStream;
+/// use boomnet::ws::IntoWebsocket;
+///
+/// let mut ws = ConnectionInfo::new("stream.binance.com", 9443)
+///     .into_tcp_stream().unwrap()
+///     .into_tls_stream().unwrap()
+///     .into_websocket_with_config("/ws", |config| {
+///         config
+///             .with_origin("https://www.binance.com").unwrap()
+///             .with_header("Authorization", "Bearer token").unwrap()
+///             .with_subprotocol("v1.json").unwrap();
+///     });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HandshakeConfig {
+    headers: Vec<(String, String)>,
+    subprotocols: Vec<String>,
+}
+
+impl HandshakeConfig {
+    /// Append custom header to the handshake request. The headers required by the websocket
+    /// protocol (such as `Upgrade` or `Sec-WebSocket-Key`) are always sent and should not be added.
+    /// Will return [`Error::InvalidHeader`] if `name` is not a valid token or `value` contains
+    /// control characters such as `\r` or `\n`.
+    pub fn with_header(&mut self, name: impl AsRef<str>, value: impl AsRef<str>) -> Result<&mut Self, Error> {
+        let (name, value) = (name.as_ref(), value.as_ref());
+        if HeaderName::from_bytes(name.as_bytes()).is_err() {
+            return Err(Error::InvalidHeader(format!("invalid header name: {name:?}")));
+        }
+        if HeaderValue::from_str(value).is_err() {
+            return Err(Error::InvalidHeader(format!("invalid value of header {name}: {value:?}")));
+        }
+        self.headers.push((name.to_owned(), value.to_owned()));
+        Ok(self)
+    }
+
+    /// Set `Origin` header of the handshake request.
+    pub fn with_origin(&mut self, origin: impl AsRef<str>) -> Result<&mut Self, Error> {
+        self.with_header("Origin", origin)
+    }
+
+    /// Request application level subprotocol. Can be called multiple times, in which case the
+    /// subprotocols are sent in order of preference. Will return [`Error::InvalidHeader`] if the
+    /// `subprotocol` is not a valid token.
+    pub fn with_subprotocol(&mut self, subprotocol: impl AsRef<str>) -> Result<&mut Self, Error> {
+        let subprotocol = subprotocol.as_ref();
+        // subprotocol names follow the same token rules as the header names
+        if HeaderName::from_bytes(subprotocol.as_bytes()).is_err() {
+            return Err(Error::InvalidHeader(format!("invalid subprotocol: {subprotocol:?}")));
+        }
+        self.subprotocols.push(subprotocol.to_owned());
+        Ok(self)
+    }
+}
+
+/// GUID appended to the nonce when computing `Sec-WebSocket-Accept` (RFC 6455 section 1.3).
+const WEBSOCKET_GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Upgrade response received from the server once the handshake has completed.
+#[derive(Debug, Clone)]
+pub struct HandshakeResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+}
+
+impl HandshakeResponse {
+    /// Returns the response status code, always `101 Switching Protocols`.
+    pub const fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns all headers sent by the server as part of the upgrade response.
+    pub const fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Returns value of the header with the given `name` (case-insensitive). Will return `None`
+    /// if the header is not present or its value is not valid UTF-8.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// Returns the subprotocol selected by the server (if any).
+    pub fn subprotocol(&self) -> Option<&str> {
+        self.headers
+            .get(SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|value| value.to_str().ok())
+    }
+
+    fn parse(response: &Response) -> Result<Self, Error> {
+        let mut headers = HeaderMap::with_capacity(response.headers.len());
+        for header in response.headers.iter() {
+            let name = HeaderName::from_bytes(header.name.as_bytes())
+                .map_err(|err| Error::Protocol(format!("invalid response header name: {err}")))?;
+            let value = HeaderValue::from_bytes(header.value)
+                .map_err(|err| Error::Protocol(format!("invalid response header value: {err}")))?;
+            headers.append(name, value);
+        }
+        Ok(Self {
+            status: StatusCode::SWITCHING_PROTOCOLS,
+            headers,
+        })
+    }
+}
 
 #[derive(Debug)]
 pub struct Handshaker {
+    role: Role,
     inbound_buffer: OwnedReadBuffer<1>,
-    outbound_buffer: Cursor<[u8; 256]>,
+    outbound_buffer: Vec<u8>,
     bytes_sent: usize,
     state: HandshakeState,
+    /// Set once the request has been sent until the next read, the response may have arrived while
+    /// the request was being flushed without the selector reporting it again.
+    unread: bool,
     server_name: String,
     endpoint: String,
+    config: HandshakeConfig,
+    nonce: String,
+    response: Option<HandshakeResponse>,
     pending_msg_buffer: VecDeque<(u8, bool, Option<Vec<u8>>)>,
+    #[cfg(feature = "ws-deflate")]
+    deflate_offer: Option<DeflateConfig>,
+    #[cfg(feature = "ws-deflate")]
+    deflate_params: Option<DeflateParams>,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -28,69 +154,171 @@ pub enum HandshakeState {
     NotStarted,
     PendingRequest,
     PendingResponse,
+    /// Server is waiting for the upgrade request from the client.
+    AwaitingRequest,
+    /// Server is sending the upgrade response to the client.
+    SendingResponse,
     Completed,
 }
 
 impl Handshaker {
-    pub fn new(server_name: &str, endpoint: &str, pool: &mut BufferPoolRef) -> Self {
+    pub fn new(server_name: &str, endpoint: &str, config: HandshakeConfig, pool: &mut BufferPoolRef) -> Self {
         Self {
+            role: Role::Client,
             inbound_buffer: pool.acquire(),
-            outbound_buffer: Cursor::new([0; 256]),
+            outbound_buffer: Vec::new(),
             bytes_sent: 0,
             state: NotStarted,
+            unread: false,
             server_name: server_name.to_string(),
             endpoint: endpoint.to_string(),
+            config,
+            nonce: generate_nonce(),
+            response: None,
             pending_msg_buffer: VecDeque::with_capacity(256),
+            #[cfg(feature = "ws-deflate")]
+            deflate_offer: None,
+            #[cfg(feature = "ws-deflate")]
+            deflate_params: None,
+        }
+    }
+
+    /// Create handshaker that will accept the upgrade request from the client. Custom headers from
+    /// the `config` are sent as part of the upgrade response.
+    pub fn new_server(config: HandshakeConfig, pool: &mut BufferPoolRef) -> Self {
+        Self {
+            role: Role::Server,
+            state: AwaitingRequest,
+            ..Self::new("", "", config, pool)
         }
     }
 
+    #[inline]
+    pub const fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Offer `permessage-deflate` extension as part of the handshake request.
+    #[cfg(feature = "ws-deflate")]
+    pub fn set_deflate_offer(&mut self, config: DeflateConfig) {
+        self.deflate_offer = Some(config);
+    }
+
+    /// Returns `permessage-deflate` parameters accepted by the server.
+    #[cfg(feature = "ws-deflate")]
+    pub const fn deflate_params(&self) -> Option<DeflateParams> {
+        self.deflate_params
+    }
+
+    /// Returns `true` if the handshake can progress without waiting for the peer, that is when
+    /// there is a request or response to send, the response has not been read yet or the
+    /// completed handshake has not been taken over by the connection. Only one step is performed
+    /// per [`Handshaker::perform_handshake`].
+    #[inline]
+    pub const fn has_pending(&self) -> bool {
+        self.unread || matches!(self.state, NotStarted | PendingRequest | SendingResponse | Completed)
+    }
+
+    /// Returns the upgrade response once the handshake has completed.
+    pub fn take_response(&mut self) -> Option<HandshakeResponse> {
+        self.response.take()
+    }
+
     #[cold]
     pub fn read<S: Read>(&mut self, stream: &mut S) -> io::Result<()> {
-        if self.state == PendingResponse {
-            self.inbound_buffer.read_from(stream)?;
+        if matches!(self.state, PendingResponse | AwaitingRequest) {
+            self.unread = false;
+            // the header is read one byte at a time so that none of the frames that follow is
+            // consumed with it, keep reading until it is complete or the stream would block as
+            // the selector may not report the remaining bytes again
+            loop {
+                let available = self.inbound_buffer.available();
+                self.inbound_buffer.read_from(stream)?;
+                if self.inbound_buffer.available() == available || self.header_received() {
+                    break;
+                }
+            }
         }
         Ok(())
     }
 
     #[cold]
-    pub fn perform_handshake<S: Read + Write>(&mut self, stream: &mut S) -> io::Result<()> {
+    pub fn perform_handshake<S: Read + Write>(&mut self, stream: &mut S) -> Result<(), Error> {
         match self.state {
             NotStarted => {
                 self.prepare_handshake_request()?;
-                Err(io::Error::from(WouldBlock))
+                Err(io::Error::from(WouldBlock).into())
             }
             PendingRequest => {
-                let from = self.bytes_sent;
-                let position = self.outbound_buffer.position();
-                let remaining = &self.outbound_buffer.get_ref()[from..position as usize];
+                let remaining = &self.outbound_buffer[self.bytes_sent..];
                 // transmit the remaining handshake bytes
                 if !remaining.is_empty() {
                     self.bytes_sent += stream.write(remaining)?;
                 } else {
                     stream.flush()?;
                     self.state = PendingResponse;
+                    self.unread = true;
                 }
-                Err(io::Error::from(WouldBlock))
+                Err(io::Error::from(WouldBlock).into())
             }
             PendingResponse => {
-                let available = self.inbound_buffer.available();
-                if available >= 4 && self.inbound_buffer.view_last(4) == b"\r\n\r\n" {
+                if self.header_received() {
                     // decode http response
                     let mut headers = [httparse::EMPTY_HEADER; 64];
                     let mut response = Response::new(&mut headers);
                     response.parse(self.inbound_buffer.view()).map_err(io::Error::other)?;
                     if response.code.unwrap() != StatusCode::SWITCHING_PROTOCOLS.as_u16() {
                         let reason = response.reason.unwrap_or_default();
-                        return Err(io::Error::other(format!("unable to switch protocols, reason: {}", reason)));
+                        return Err(io::Error::other(format!("unable to switch protocols, reason: {}", reason)).into());
+                    }
+                    self.validate_accept_key(&response)?;
+                    let handshake_response = HandshakeResponse::parse(&response)?;
+                    self.validate_subprotocol(&handshake_response)?;
+                    #[cfg(feature = "ws-deflate")]
+                    if let Some(offer) = self.deflate_offer.as_ref() {
+                        let extensions = response
+                            .headers
+                            .iter()
+                            .filter(|header| header.name.eq_ignore_ascii_case("Sec-WebSocket-Extensions"))
+                            .filter_map(|header| std::str::from_utf8(header.value).ok());
+                        self.deflate_params = offer.negotiate(extensions)?;
+                    }
+                    self.response = Some(handshake_response);
+                    self.state = Completed;
+                }
+                Err(io::Error::from(WouldBlock).into())
+            }
+            AwaitingRequest => {
+                if self.header_received() {
+                    if let Err(err) = self.prepare_handshake_response() {
+                        let _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n");
+                        let _ = stream.flush();
+                        return Err(err);
                     }
+                }
+                Err(io::Error::from(WouldBlock).into())
+            }
+            SendingResponse => {
+                let remaining = &self.outbound_buffer[self.bytes_sent..];
+                // transmit the remaining handshake bytes
+                if !remaining.is_empty() {
+                    self.bytes_sent += stream.write(remaining)?;
+                } else {
+                    stream.flush()?;
                     self.state = Completed;
                 }
-                Err(io::Error::from(WouldBlock))
+                Err(io::Error::from(WouldBlock).into())
             }
             Completed => Ok(()),
         }
     }
 
+    /// Returns `true` once the whole HTTP header has been read.
+    #[inline]
+    fn header_received(&self) -> bool {
+        self.inbound_buffer.available() >= 4 && self.inbound_buffer.view_last(4) == b"\r\n\r\n"
+    }
+
     #[cold]
     pub fn buffer_message(&mut self, fin: bool, op: u8, body: Option<&[u8]>) {
         let body = body.map(|body| body.to_vec());
@@ -109,18 +337,108 @@ impl Handshaker {
         Ok(())
     }
 
+    fn validate_accept_key(&self, response: &Response) -> Result<(), Error> {
+        let expected = accept_key(&self.nonce);
+        let received = response
+            .headers
+            .iter()
+            .find(|header| header.name.eq_ignore_ascii_case("Sec-WebSocket-Accept"))
+            .map(|header| String::from_utf8_lossy(header.value).trim().to_owned())
+            .unwrap_or_default();
+        if received != expected {
+            return Err(Error::InvalidAcceptKey(expected, received));
+        }
+        Ok(())
+    }
+
+    fn validate_subprotocol(&self, response: &HandshakeResponse) -> Result<(), Error> {
+        match response.subprotocol() {
+            Some(subprotocol)
+                if !self
+                    .config
+                    .subprotocols
+                    .iter()
+                    .any(|requested| requested == subprotocol) =>
+            {
+                Err(Error::Protocol(format!("server selected subprotocol that was not requested: {subprotocol}")))
+            }
+            _ => Ok(()),
+        }
+    }
+
     fn prepare_handshake_request(&mut self) -> io::Result<()> {
         let outbound = &mut self.outbound_buffer;
+        outbound.clear();
         outbound.write_all(format!("GET {} HTTP/1.1\r\n", self.endpoint).as_bytes())?;
         outbound.write_all(format!("Host: {}\r\n", self.server_name).as_bytes())?;
         outbound.write_all(b"Upgrade: websocket\r\n")?;
         outbound.write_all(b"Connection: upgrade\r\n")?;
-        outbound.write_all(format!("Sec-WebSocket-Key: {}\r\n", generate_nonce()).as_bytes())?;
+        outbound.write_all(format!("Sec-WebSocket-Key: {}\r\n", self.nonce).as_bytes())?;
         outbound.write_all(b"Sec-WebSocket-Version: 13\r\n")?;
+        if !self.config.subprotocols.is_empty() {
+            outbound
+                .write_all(format!("Sec-WebSocket-Protocol: {}\r\n", self.config.subprotocols.join(", ")).as_bytes())?;
+        }
+        for (name, value) in &self.config.headers {
+            outbound.write_all(format!("{name}: {value}\r\n").as_bytes())?;
+        }
+        #[cfg(feature = "ws-deflate")]
+        if let Some(offer) = self.deflate_offer.as_ref() {
+            outbound.write_all(format!("Sec-WebSocket-Extensions: {}\r\n", offer.offer()).as_bytes())?;
+        }
         outbound.write_all(b"\r\n")?;
         self.state = PendingRequest;
         Ok(())
     }
+
+    fn prepare_handshake_response(&mut self) -> Result<(), Error> {
+        let mut headers = [httparse::EMPTY_HEADER; 64];
+        let mut request = Request::new(&mut headers);
+        request
+            .parse(self.inbound_buffer.view())
+            .map_err(|err| Error::Protocol(format!("invalid upgrade request: {err}")))?;
+        if request.method != Some("GET") {
+            return Err(Error::Protocol(format!("invalid upgrade request method: {:?}", request.method)));
+        }
+        let header = |name: &str| {
+            request
+                .headers
+                .iter()
+                .find(|header| header.name.eq_ignore_ascii_case(name))
+                .map(|header| String::from_utf8_lossy(header.value).trim().to_owned())
+                .unwrap_or_default()
+        };
+        if !header("Upgrade").eq_ignore_ascii_case("websocket") {
+            return Err(Error::Protocol("missing 'Upgrade: websocket' header".to_string()));
+        }
+        if !header("Connection")
+            .split(',')
+            .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+        {
+            return Err(Error::Protocol("missing 'Connection: upgrade' header".to_string()));
+        }
+        if header("Sec-WebSocket-Version") != "13" {
+            return Err(Error::Protocol("unsupported websocket version".to_string()));
+        }
+        let key = header("Sec-WebSocket-Key");
+        match general_purpose::STANDARD.decode(&key) {
+            Ok(nonce) if nonce.len() == 16 => {}
+            _ => return Err(Error::Protocol(format!("invalid Sec-WebSocket-Key: {key}"))),
+        }
+
+        let outbound = &mut self.outbound_buffer;
+        outbound.clear();
+        outbound.write_all(b"HTTP/1.1 101 Switching Protocols\r\n")?;
+        outbound.write_all(b"Upgrade: websocket\r\n")?;
+        outbound.write_all(b"Connection: Upgrade\r\n")?;
+        outbound.write_all(format!("Sec-WebSocket-Accept: {}\r\n", accept_key(&key)).as_bytes())?;
+        for (name, value) in &self.config.headers {
+            outbound.write_all(format!("{name}: {value}\r\n").as_bytes())?;
+        }
+        outbound.write_all(b"\r\n")?;
+        self.state = SendingResponse;
+        Ok(())
+    }
 }
 
 fn generate_nonce() -> String {
@@ -128,3 +446,183 @@ fn generate_nonce() -> String {
     let nonce_bytes: [u8; 16] = rng.random();
     general_purpose::STANDARD.encode(nonce_bytes)
 }
+
+fn accept_key(nonce: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(nonce.as_bytes());
+    sha1.update(WEBSOCKET_GUID);
+    general_purpose::STANDARD.encode(sha1.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockStream;
+
+    // nonce and accept key taken from RFC 6455 section 1.3
+    const NONCE: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+    const ACCEPT: &str = "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
+
+    fn handshaker(endpoint: &str, config: HandshakeConfig) -> Handshaker {
+        let mut handshaker = Handshaker::new("example.com", endpoint, config, &mut Default::default());
+        handshaker.nonce = NONCE.to_owned();
+        handshaker
+    }
+
+    fn handshake(handshaker: &mut Handshaker, stream: &mut MockStream) -> Result<(), Error> {
+        loop {
+            handshaker.read(stream)?;
+            match handshaker.perform_handshake(stream) {
+                Ok(()) => return Ok(()),
+                Err(Error::IO(err)) if err.kind() == WouldBlock => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn server_handshake(handshaker: &mut Handshaker, stream: &mut MockStream) -> Result<(), Error> {
+        for _ in 0..1024 {
+            handshaker.read(stream)?;
+            match handshaker.perform_handshake(stream) {
+                Ok(()) => return Ok(()),
+                Err(Error::IO(err)) if err.kind() == WouldBlock => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Err(io::Error::from(WouldBlock).into())
+    }
+
+    #[test]
+    fn should_accept_upgrade_request() {
+        let mut config = HandshakeConfig::default();
+        config.with_header("Server", "boomnet").unwrap();
+        let mut handshaker = Handshaker::new_server(config, &mut Default::default());
+        let mut stream = MockStream::new(format!(
+            "GET /chat HTTP/1.1\r\nHost: server.example.com\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\nSec-WebSocket-Key: {NONCE}\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        ));
+
+        server_handshake(&mut handshaker, &mut stream).unwrap();
+
+        let response = String::from_utf8(stream.outbound).unwrap();
+        assert_eq!(
+            format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {ACCEPT}\r\nServer: boomnet\r\n\r\n"
+            ),
+            response
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_upgrade_request() {
+        let mut handshaker = Handshaker::new_server(Default::default(), &mut Default::default());
+        let mut stream = MockStream::new(format!(
+            "GET /chat HTTP/1.1\r\nHost: server.example.com\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {NONCE}\r\nSec-WebSocket-Version: 8\r\n\r\n"
+        ));
+
+        assert!(matches!(server_handshake(&mut handshaker, &mut stream), Err(Error::Protocol(_))));
+        assert!(
+            String::from_utf8(stream.outbound)
+                .unwrap()
+                .starts_with("HTTP/1.1 400 Bad Request")
+        );
+    }
+
+    #[test]
+    fn should_compute_accept_key() {
+        assert_eq!(ACCEPT, accept_key(NONCE));
+    }
+
+    #[test]
+    fn should_send_custom_headers_and_subprotocols() {
+        let mut config = HandshakeConfig::default();
+        config
+            .with_origin("https://example.com")
+            .unwrap()
+            .with_header("Authorization", "Bearer token")
+            .unwrap()
+            .with_subprotocol("v2.json")
+            .unwrap()
+            .with_subprotocol("v1.json")
+            .unwrap();
+        let endpoint = format!("/ws?{}", "a".repeat(512));
+        let mut handshaker = handshaker(&endpoint, config);
+        let mut stream = MockStream::new(format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nSec-WebSocket-Accept: {ACCEPT}\r\nSec-WebSocket-Protocol: v1.json\r\n\r\n"
+        ));
+
+        handshake(&mut handshaker, &mut stream).unwrap();
+
+        let request = String::from_utf8(stream.outbound).unwrap();
+        assert!(request.starts_with(&format!("GET {endpoint} HTTP/1.1\r\n")));
+        assert!(request.contains(&format!("Sec-WebSocket-Key: {NONCE}\r\n")));
+        assert!(request.contains("Origin: https://example.com\r\n"));
+        assert!(request.contains("Authorization: Bearer token\r\n"));
+        assert!(request.contains("Sec-WebSocket-Protocol: v2.json, v1.json\r\n"));
+        assert!(request.ends_with("\r\n\r\n"));
+        let response = handshaker.take_response().unwrap();
+        assert_eq!(Some("v1.json"), response.subprotocol());
+    }
+
+    #[test]
+    fn should_reject_invalid_headers_and_subprotocols() {
+        let mut config = HandshakeConfig::default();
+        assert!(matches!(config.with_header("X-Injected\r\nHost", "evil"), Err(Error::InvalidHeader(_))));
+        assert!(matches!(config.with_header("Bad Name", "value"), Err(Error::InvalidHeader(_))));
+        assert!(matches!(config.with_header("", "value"), Err(Error::InvalidHeader(_))));
+        assert!(matches!(config.with_header("X-Value", "a\r\nHost: evil"), Err(Error::InvalidHeader(_))));
+        assert!(matches!(config.with_origin("https://example.com\n"), Err(Error::InvalidHeader(_))));
+        assert!(matches!(config.with_subprotocol("v1.json, v2"), Err(Error::InvalidHeader(_))));
+        assert!(config.headers.is_empty());
+        assert!(config.subprotocols.is_empty());
+    }
+
+    #[test]
+    fn should_expose_response_headers() {
+        let mut handshaker = handshaker("/ws", Default::default());
+        let mut stream = MockStream::new(format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nSec-WebSocket-Accept: {ACCEPT}\r\nX-RateLimit-Remaining: 42\r\n\r\n"
+        ));
+
+        handshake(&mut handshaker, &mut stream).unwrap();
+
+        let response = handshaker.take_response().unwrap();
+        assert_eq!(StatusCode::SWITCHING_PROTOCOLS, response.status());
+        assert_eq!(Some("42"), response.header("x-ratelimit-remaining"));
+        assert_eq!(Some("websocket"), response.header("Upgrade"));
+        assert_eq!(None, response.subprotocol());
+    }
+
+    #[test]
+    fn should_reject_invalid_accept_key() {
+        let mut handshaker = handshaker("/ws", Default::default());
+        let mut stream = MockStream::new(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nSec-WebSocket-Accept: invalid\r\n\r\n",
+        );
+
+        match handshake(&mut handshaker, &mut stream) {
+            Err(Error::InvalidAcceptKey(expected, received)) => {
+                assert_eq!(ACCEPT, expected);
+                assert_eq!("invalid", received);
+            }
+            other => panic!("expected invalid accept key error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn should_reject_missing_accept_key() {
+        let mut handshaker = handshaker("/ws", Default::default());
+        let mut stream = MockStream::new("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n");
+
+        assert!(matches!(handshake(&mut handshaker, &mut stream), Err(Error::InvalidAcceptKey(_, _))));
+    }
+
+    #[test]
+    fn should_reject_subprotocol_that_was_not_requested() {
+        let mut handshaker = handshaker("/ws", Default::default());
+        let mut stream = MockStream::new(format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nSec-WebSocket-Accept: {ACCEPT}\r\nSec-WebSocket-Protocol: v1.json\r\n\r\n"
+        ));
+
+        assert!(matches!(handshake(&mut handshaker, &mut stream), Err(Error::Protocol(_))));
+    }
+}
@@ -0,0 +1,222 @@
+use crate::service::time::{SystemTimeClockSource, TimeSource};
+use crate::ws::Error;
+use std::fmt::{Debug, Formatter};
+use std::time::Duration;
+
+/// Keepalive policy that can be applied to the websocket with [`Websocket::with_keepalive`](crate::ws::Websocket::with_keepalive).
+/// All checks are disabled by default.
+///
+/// ## Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use boomnet::service::time::SystemTimeClockSource;
+/// use boomnet::stream::ConnectionInfo;
+/// use boomnet::stream::tls::IntoTlsStream;
+/// use boomnet::ws::{IntoWebsocket, KeepalivePolicy};
+///
+/// let policy = KeepalivePolicy::new()
+///     .with_ping_interval(Duration::from_secs(10))
+///     .with_pong_timeout(Duration::from_secs(5))
+///     .with_idle_timeout(Duration::from_secs(30));
+///
+/// let mut ws = ConnectionInfo::new("stream.binance.com", 9443)
+///     .into_tcp_stream().unwrap()
+///     .into_tls_stream().unwrap()
+///     .into_websocket("/ws")
+///     .with_keepalive(policy, SystemTimeClockSource);
+/// ```
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct KeepalivePolicy {
+    ping_interval: Option<Duration>,
+    pong_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+}
+
+impl KeepalivePolicy {
+    pub const fn new() -> Self {
+        Self {
+            ping_interval: None,
+            pong_timeout: None,
+            idle_timeout: None,
+        }
+    }
+
+    /// Send ping frame to the server every `ping_interval`.
+    pub const fn with_ping_interval(self, ping_interval: Duration) -> Self {
+        Self {
+            ping_interval: Some(ping_interval),
+            ..self
+        }
+    }
+
+    /// Fail with [`Error::PongTimeout`] if no pong frame is received within `pong_timeout` of
+    /// sending the ping. Only applies when the ping interval has been set.
+    pub const fn with_pong_timeout(self, pong_timeout: Duration) -> Self {
+        Self {
+            pong_timeout: Some(pong_timeout),
+            ..self
+        }
+    }
+
+    /// Fail with [`Error::IdleTimeout`] if no data is received from the server within `idle_timeout`.
+    pub const fn with_idle_timeout(self, idle_timeout: Duration) -> Self {
+        Self {
+            idle_timeout: Some(idle_timeout),
+            ..self
+        }
+    }
+}
+
+/// Provides the current time to the websocket timers, [`SystemTimeClockSource`] is used by default.
+pub struct Clock(Box<dyn TimeSource>);
+
+impl Clock {
+    pub fn new<T: TimeSource + 'static>(time_source: T) -> Self {
+        Self(Box::new(time_source))
+    }
+
+    #[inline]
+    pub fn now(&self) -> u64 {
+        self.0.current_time_nanos()
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new(SystemTimeClockSource)
+    }
+}
+
+impl Debug for Clock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Clock").finish_non_exhaustive()
+    }
+}
+
+/// Tracks keepalive timers, all timestamps are in nanos as provided by the [`Clock`].
+#[derive(Debug)]
+pub struct Keepalive {
+    policy: KeepalivePolicy,
+    started: bool,
+    last_read_ns: u64,
+    last_ping_ns: u64,
+    pending_ping_ns: Option<u64>,
+}
+
+impl Keepalive {
+    pub const fn new(policy: KeepalivePolicy) -> Self {
+        Self {
+            policy,
+            started: false,
+            last_read_ns: 0,
+            last_ping_ns: 0,
+            pending_ping_ns: None,
+        }
+    }
+
+    /// Check the timers at `now` after the network read that returned `bytes_read`. Returns `true`
+    /// if the ping frame should be sent.
+    #[inline]
+    pub fn poll(&mut self, now: u64, bytes_read: usize) -> Result<bool, Error> {
+        if !self.started {
+            // timers start once the connection has been established
+            self.started = true;
+            self.last_read_ns = now;
+            self.last_ping_ns = now;
+        }
+        if bytes_read > 0 {
+            self.last_read_ns = now;
+        }
+        if let Some(idle_timeout) = self.policy.idle_timeout {
+            if now.saturating_sub(self.last_read_ns) >= idle_timeout.as_nanos() as u64 {
+                return Err(Error::IdleTimeout(idle_timeout));
+            }
+        }
+        if let (Some(pong_timeout), Some(ping_ns)) = (self.policy.pong_timeout, self.pending_ping_ns) {
+            if now.saturating_sub(ping_ns) >= pong_timeout.as_nanos() as u64 {
+                return Err(Error::PongTimeout(pong_timeout));
+            }
+        }
+        if let Some(ping_interval) = self.policy.ping_interval {
+            if now.saturating_sub(self.last_ping_ns) >= ping_interval.as_nanos() as u64 {
+                self.last_ping_ns = now;
+                self.pending_ping_ns.get_or_insert(now);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns `true` if any of the timers has expired at `now` and [`Keepalive::poll`] should be
+    /// called even if there is no data to read. Timers that have not started yet are always due.
+    #[inline]
+    pub fn is_due(&self, now: u64) -> bool {
+        let expired = |since: u64, timeout: Duration| now.saturating_sub(since) >= timeout.as_nanos() as u64;
+        !self.started
+            || self
+                .policy
+                .idle_timeout
+                .is_some_and(|timeout| expired(self.last_read_ns, timeout))
+            || self
+                .policy
+                .pong_timeout
+                .zip(self.pending_ping_ns)
+                .is_some_and(|(timeout, ping_ns)| expired(ping_ns, timeout))
+            || self
+                .policy
+                .ping_interval
+                .is_some_and(|interval| expired(self.last_ping_ns, interval))
+    }
+
+    #[inline]
+    pub const fn on_pong(&mut self) {
+        self.pending_ping_ns = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ManualTimeSource;
+
+    #[test]
+    fn should_send_ping_and_detect_pong_timeout() {
+        let time_source = ManualTimeSource::default();
+        let policy = KeepalivePolicy::new()
+            .with_ping_interval(Duration::from_secs(10))
+            .with_pong_timeout(Duration::from_secs(5));
+        let mut keepalive = Keepalive::new(policy);
+
+        assert!(!keepalive.poll(time_source.current_time_nanos(), 0).unwrap());
+        time_source.advance(Duration::from_secs(10));
+        assert!(keepalive.poll(time_source.current_time_nanos(), 0).unwrap());
+        time_source.advance(Duration::from_secs(4));
+        assert!(!keepalive.poll(time_source.current_time_nanos(), 0).unwrap());
+        keepalive.on_pong();
+        time_source.advance(Duration::from_secs(6));
+        assert!(keepalive.poll(time_source.current_time_nanos(), 0).unwrap());
+        time_source.advance(Duration::from_secs(4));
+        assert!(!keepalive.is_due(time_source.current_time_nanos()));
+        time_source.advance(Duration::from_secs(1));
+        assert!(keepalive.is_due(time_source.current_time_nanos()));
+        assert!(matches!(keepalive.poll(time_source.current_time_nanos(), 100), Err(Error::PongTimeout(_))));
+    }
+
+    #[test]
+    fn should_detect_idle_timeout() {
+        let time_source = ManualTimeSource::default();
+        let policy = KeepalivePolicy::new().with_idle_timeout(Duration::from_secs(30));
+        let mut keepalive = Keepalive::new(policy);
+
+        assert!(keepalive.is_due(time_source.current_time_nanos()));
+        assert!(!keepalive.poll(time_source.current_time_nanos(), 0).unwrap());
+        assert!(!keepalive.is_due(time_source.current_time_nanos()));
+        time_source.advance(Duration::from_secs(20));
+        assert!(!keepalive.poll(time_source.current_time_nanos(), 10).unwrap());
+        time_source.advance(Duration::from_secs(20));
+        assert!(!keepalive.poll(time_source.current_time_nanos(), 0).unwrap());
+        time_source.advance(Duration::from_secs(10));
+        assert!(matches!(keepalive.poll(time_source.current_time_nanos(), 0), Err(Error::IdleTimeout(_))));
+    }
+}
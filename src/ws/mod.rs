@@ -39,6 +39,21 @@
 //! }
 //! ```
 //!
+//! Receive complete messages with fragmented frames reassembled automatically.
+//!```no_run
+//! use std::io::{Read, Write};
+//! use boomnet::ws::{Message, Websocket};
+//!
+//! fn consume_messages<S: Read + Write>(ws: &mut Websocket<S>) -> std::io::Result<()> {
+//!    for message in ws.read_batch_messages()? {
+//!      if let Message::Text(body) = message? {
+//!        println!("{}", String::from_utf8_lossy(body));
+//!      }
+//!    }
+//!    Ok(())
+//! }
+//! ```
+//!
 //! Receive messages at most one at a tine. If possible, use batch mode instead.
 //!```no_run
 //! use std::io::{Read, Write};
@@ -56,52 +71,108 @@
 
 use crate::buffer::{BufferPoolRef, default_buffer_pool_ref};
 use crate::service::select::Selectable;
+use crate::service::time::TimeSource;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use crate::stream::io_uring::{IoUringSource, RecvBuffer};
 use crate::stream::tcp::TcpStream;
 #[cfg(any(feature = "rustls", feature = "openssl"))]
 use crate::stream::tls::{IntoTlsStream, TlsReadyStream, TlsStream};
 use crate::stream::{BindAndConnect, ConnectionInfoProvider};
-use crate::util::NoBlock;
 use crate::ws::Error::{Closed, ReceivedCloseFrame};
-use crate::ws::decoder::Decoder;
+use crate::ws::assembler::MessageAssembler;
+pub use crate::ws::close::CloseCode;
+use crate::ws::decoder::{Decoder, DecoderLimits};
+use crate::ws::encoder::Encoder;
+pub use crate::ws::encoder::Masking;
 pub use crate::ws::error::Error;
 use crate::ws::handshake::Handshaker;
+pub use crate::ws::handshake::{HandshakeConfig, HandshakeResponse};
+pub use crate::ws::keepalive::KeepalivePolicy;
+use crate::ws::keepalive::{Clock, Keepalive};
 #[cfg(feature = "mio")]
 use mio::{Interest, Registry, Token, event::Source};
 use std::fmt::Debug;
 use std::io;
 use std::io::ErrorKind::WouldBlock;
 use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::time::Duration;
 use thiserror::Error;
 use url::Url;
 
+mod assembler;
+mod close;
 mod decoder;
+#[cfg(feature = "ws-deflate")]
+pub mod deflate;
 pub mod ds;
 mod encoder;
 mod error;
 mod handshake;
+mod keepalive;
 mod protocol;
 pub mod util;
 
-/// Supported web socket frame variants.
-pub enum WebsocketFrame {
+/// Supported web socket frame variants. The payload borrows from the websocket read buffer and
+/// is tied to the lifetime of the [`Batch`] (or the [`Websocket`] borrow) it was decoded from, so the
+/// frame cannot be held across the next network read.
+///
+/// ```compile_fail
+/// use std::io::{Read, Write};
+/// use boomnet::ws::{Websocket, WebsocketFrame};
+///
+/// fn hold<S: Read + Write>(ws: &mut Websocket<S>) -> std::io::Result<()> {
+///     let frame = ws.read_batch()?.into_iter().next();
+///     let _ = ws.read_batch()?;
+///     drop(frame);
+///     Ok(())
+/// }
+/// ```
+pub enum WebsocketFrame<'a> {
     /// Server has sent ping frame that will generate automatic pong response. This frame is not
     /// exposed to the user.
-    Ping(&'static [u8]),
-    Pong(&'static [u8]),
-    Text(bool, &'static [u8]),
-    Binary(bool, &'static [u8]),
-    Continuation(bool, &'static [u8]),
+    Ping(&'a [u8]),
+    Pong(&'a [u8]),
+    Text(bool, &'a [u8]),
+    Binary(bool, &'a [u8]),
+    Continuation(bool, &'a [u8]),
     /// Server has sent close frame. The websocket will be closed as a result. This frame is not
     /// exposed to the user.
-    Close(&'static [u8]),
+    Close(&'a [u8]),
+}
+
+/// Complete websocket message returned by [`Websocket::read_batch_messages`]. Messages received
+/// in a single frame reference the decoder buffer directly, while fragmented messages are
+/// reassembled into a pooled buffer. In both cases the payload is tied to the lifetime of the
+/// [`MessageBatch`] it was decoded from.
+#[derive(Debug)]
+pub enum Message<'a> {
+    Text(&'a [u8]),
+    Binary(&'a [u8]),
 }
 
+/// Determines how frames are masked and which side of the handshake is performed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum Role {
+    Client,
+    Server,
+}
+
+/// Default amount of time to wait for the peer to echo the close frame.
+pub const DEFAULT_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Websocket client that owns underlying stream.
 #[derive(Debug)]
 pub struct Websocket<S> {
     stream: S,
     closed: bool,
+    close_deadline: Option<u64>,
+    close_timeout: Duration,
+    clock: Clock,
     state: State,
+    encoder: Encoder,
+    assembler: MessageAssembler,
+    keepalive: Option<Keepalive>,
 }
 
 impl<S> Websocket<S> {
@@ -112,12 +183,32 @@ impl<S> Websocket<S> {
     where
         S: ConnectionInfoProvider,
     {
+        Self::new_with_config(stream, endpoint, |_| {})
+    }
+
+    /// Create a new websocket by wrapping the provided `stream` and using `endpoint`. The `configure`
+    /// closure can be used to customise the handshake request, for example to add custom headers
+    /// or request subprotocols (see [`HandshakeConfig`]).
+    pub fn new_with_config<F>(stream: S, endpoint: &str, configure: F) -> Websocket<S>
+    where
+        S: ConnectionInfoProvider,
+        F: FnOnce(&mut HandshakeConfig),
+    {
+        let mut config = HandshakeConfig::default();
+        configure(&mut config);
         let connection_info = stream.connection_info().clone();
         let server_name = connection_info.host();
+        let pool = default_buffer_pool_ref();
         Self {
             stream,
             closed: false,
-            state: State::handshake(server_name, endpoint, default_buffer_pool_ref()),
+            close_deadline: None,
+            close_timeout: DEFAULT_CLOSE_TIMEOUT,
+            clock: Clock::default(),
+            state: State::handshake(server_name, endpoint, config, pool.clone()),
+            encoder: Encoder::new(Masking::default()),
+            assembler: MessageAssembler::new(pool),
+            keepalive: None,
         }
     }
 
@@ -125,10 +216,185 @@ impl<S> Websocket<S> {
     /// user's responsibility to make sure the handshake has been completed. Otherwise, can result
     /// in undefined behaviour.
     pub fn new_with_handshake_complete(stream: S) -> Websocket<S> {
+        let pool = default_buffer_pool_ref();
+        Self {
+            stream,
+            closed: false,
+            close_deadline: None,
+            close_timeout: DEFAULT_CLOSE_TIMEOUT,
+            clock: Clock::default(),
+            state: State::connection(pool.clone()),
+            encoder: Encoder::new(Masking::default()),
+            assembler: MessageAssembler::new(pool),
+            keepalive: None,
+        }
+    }
+
+    /// Create a server side websocket by wrapping the `stream` accepted from the listener. The
+    /// upgrade request will be read from the client and the handshake response sent back before
+    /// any frames are decoded. Outbound frames are not masked, while the inbound frames are required
+    /// to be masked by the client.
+    ///
+    /// ## Examples
+    ///
+    /// ```no_run
+    /// use std::net::TcpListener;
+    /// use boomnet::ws::{Websocket, WebsocketFrame};
+    ///
+    /// let listener = TcpListener::bind("127.0.0.1:9001").unwrap();
+    /// let (stream, _) = listener.accept().unwrap();
+    /// let mut ws = Websocket::accept(stream);
+    ///
+    /// loop {
+    ///     let mut echo = Vec::new();
+    ///     for frame in ws.read_batch().unwrap() {
+    ///         if let WebsocketFrame::Text(true, data) = frame.unwrap() {
+    ///             echo.push(data.to_vec());
+    ///         }
+    ///     }
+    ///     for data in echo {
+    ///         ws.send_text(true, Some(&data)).unwrap();
+    ///     }
+    /// }
+    /// ```
+    pub fn accept(stream: S) -> Websocket<S> {
+        Self::accept_with_config(stream, |_| {})
+    }
+
+    /// Same as [`Websocket::accept`] but the `configure` closure can be used to add custom headers
+    /// to the upgrade response. Subprotocols and `permessage-deflate` are not negotiated by the
+    /// server.
+    pub fn accept_with_config<F>(stream: S, configure: F) -> Websocket<S>
+    where
+        F: FnOnce(&mut HandshakeConfig),
+    {
+        let mut config = HandshakeConfig::default();
+        configure(&mut config);
+        let pool = default_buffer_pool_ref();
         Self {
             stream,
             closed: false,
-            state: State::connection(default_buffer_pool_ref()),
+            close_deadline: None,
+            close_timeout: DEFAULT_CLOSE_TIMEOUT,
+            clock: Clock::default(),
+            state: State::accept(config, pool.clone()),
+            encoder: Encoder::new_with_role(Role::Server, Masking::default()),
+            assembler: MessageAssembler::new(pool),
+            keepalive: None,
+        }
+    }
+
+    /// Specify how the outbound frames should be masked. By default, zero masking key is used
+    /// which avoids the cost of masking the payload. Use [`Masking::RandomKey`] when talking to
+    /// servers or proxies that strictly enforce RFC 6455 masking requirements.
+    ///
+    /// ## Examples
+    ///
+    /// ```no_run
+    /// use boomnet::stream::ConnectionInfo;
+    /// use boomnet::stream::tls::IntoTlsStream;
+    /// use boomnet::ws::{IntoWebsocket, Masking};
+    ///
+    /// let mut ws = ConnectionInfo::new("stream.binance.com", 9443)
+    ///     .into_tcp_stream().unwrap()
+    ///     .into_tls_stream().unwrap()
+    ///     .into_websocket("/ws")
+    ///     .with_masking(Masking::RandomKey);
+    /// ```
+    pub fn with_masking(mut self, masking: Masking) -> Websocket<S> {
+        self.encoder.set_masking(masking);
+        self
+    }
+
+    /// Returns the masking policy used for outbound frames.
+    pub const fn masking(&self) -> Masking {
+        self.encoder.masking()
+    }
+
+    /// Offer `permessage-deflate` extension (RFC 7692) during the handshake. If the server accepts
+    /// the offer, inbound compressed messages will be transparently inflated and outbound messages
+    /// compressed. Uncompressed frames are still decoded without copying.
+    ///
+    /// If the handshake has already been completed, the `config` is assumed to have been accepted
+    /// by the server as is.
+    #[cfg(feature = "ws-deflate")]
+    pub fn with_permessage_deflate(mut self, config: deflate::DeflateConfig) -> Websocket<S> {
+        match &mut self.state {
+            State::Handshake(handshake, _, _) => handshake.set_deflate_offer(config),
+            State::Connection(decoder, _) => {
+                let params = config.into_params();
+                decoder.set_inflate(&params);
+                self.encoder.set_deflate(params);
+            }
+        }
+        self
+    }
+
+    /// Returns `permessage-deflate` parameters negotiated with the server or `None` if the
+    /// extension is not in use.
+    #[cfg(feature = "ws-deflate")]
+    pub fn permessage_deflate(&self) -> Option<&deflate::DeflateParams> {
+        self.encoder.deflate_params()
+    }
+
+    /// Limit the size of a single inbound frame. A frame that declares a larger payload length is
+    /// rejected before any of its payload is buffered, the connection is closed with status code
+    /// `1009` and [`Error::MessageTooBig`] is returned. By default, no limit is enforced.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Websocket<S> {
+        self.state.limits_mut().max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Limit the size of an inbound message, accumulated across all of its fragments (and after
+    /// inflation when `permessage-deflate` is in use). Exceeding the limit closes the connection
+    /// with status code `1009` and returns [`Error::MessageTooBig`]. By default, no limit is enforced.
+    ///
+    /// ## Examples
+    ///
+    /// ```no_run
+    /// use boomnet::stream::ConnectionInfo;
+    /// use boomnet::stream::tls::IntoTlsStream;
+    /// use boomnet::ws::IntoWebsocket;
+    ///
+    /// let mut ws = ConnectionInfo::new("stream.binance.com", 9443)
+    ///     .into_tcp_stream().unwrap()
+    ///     .into_tls_stream().unwrap()
+    ///     .into_websocket("/ws")
+    ///     .with_max_frame_size(1 << 20)
+    ///     .with_max_message_size(16 << 20);
+    /// ```
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Websocket<S> {
+        self.state.limits_mut().max_message_size = max_message_size;
+        self
+    }
+
+    /// Specify how long to wait for the peer to echo the close frame after [`send_close`](Websocket::send_close)
+    /// has been called. Once the timeout has elapsed the websocket is considered closed. Default is
+    /// [`DEFAULT_CLOSE_TIMEOUT`].
+    pub fn with_close_timeout(self, close_timeout: Duration) -> Websocket<S> {
+        Self { close_timeout, ..self }
+    }
+
+    /// Use `time_source` to drive the close timeout and keepalive timers. By default,
+    /// [`SystemTimeClockSource`](crate::service::time::SystemTimeClockSource) is used.
+    pub fn with_time_source<T: TimeSource + 'static>(self, time_source: T) -> Websocket<S> {
+        Self {
+            clock: Clock::new(time_source),
+            ..self
+        }
+    }
+
+    /// Apply keepalive `policy` driven by the `time_source`. The timers are checked on every
+    /// [`read_batch`](Websocket::read_batch) once the handshake has completed: ping frames are
+    /// sent periodically and [`Error::PongTimeout`] or [`Error::IdleTimeout`] is returned if the
+    /// server stops responding. As with any other error, the websocket is closed as a result, so
+    /// when used with `IOService` the endpoint can decide to reconnect in `Endpoint::can_recreate`.
+    /// The `time_source` is also used for the close timeout.
+    pub fn with_keepalive<T: TimeSource + 'static>(self, policy: KeepalivePolicy, time_source: T) -> Websocket<S> {
+        Self {
+            keepalive: Some(Keepalive::new(policy)),
+            clock: Clock::new(time_source),
+            ..self
         }
     }
 
@@ -138,18 +404,70 @@ impl<S> Websocket<S> {
         self.closed
     }
 
+    /// Checks if the close frame has been sent and the websocket is waiting for the peer to
+    /// echo it back.
+    pub const fn closing(&self) -> bool {
+        !self.closed && self.close_deadline.is_some()
+    }
+
     /// Checks if the handshake has completed successfully. If attempt is made to send a message
     /// while the handshake is pending the message will be buffered and dispatched once handshake
     /// has finished.
     #[inline]
     pub const fn handshake_complete(&self) -> bool {
         match self.state {
-            State::Handshake(_, _) => false,
-            State::Connection(_) => true,
+            State::Handshake(_, _, _) => false,
+            State::Connection(_, _) => true,
+        }
+    }
+
+    /// Returns the subprotocol selected by the server during the handshake. Will return `None` if
+    /// the handshake is still pending or no subprotocol has been selected.
+    pub fn subprotocol(&self) -> Option<&str> {
+        self.handshake_response().and_then(HandshakeResponse::subprotocol)
+    }
+
+    /// Returns the upgrade response (status and headers) received from the server once
+    /// [`handshake_complete`](Websocket::handshake_complete) is `true`. Will return `None` if the
+    /// handshake is still pending or the websocket was created with the handshake already complete.
+    ///
+    /// ## Examples
+    ///
+    /// ```no_run
+    /// use std::io::{Read, Write};
+    /// use boomnet::ws::Websocket;
+    ///
+    /// fn log_rate_limit<S: Read + Write>(ws: &Websocket<S>) {
+    ///     if let Some(remaining) = ws.handshake_response().and_then(|response| response.header("x-ratelimit-remaining")) {
+    ///         println!("remaining requests: {remaining}");
+    ///     }
+    /// }
+    /// ```
+    pub fn handshake_response(&self) -> Option<&HandshakeResponse> {
+        match &self.state {
+            State::Handshake(_, _, _) => None,
+            State::Connection(_, response) => response.as_ref(),
         }
     }
 }
 
+impl<S> Websocket<S> {
+    /// Returns `true` if the keepalive or close timeout has to be checked. The timers are only
+    /// checked when the websocket is read, so it must be polled even if there is no data available.
+    #[inline]
+    fn timers_due(&self) -> bool {
+        if self.keepalive.is_none() && self.close_deadline.is_none() {
+            return false;
+        }
+        let now = self.clock.now();
+        self.close_deadline.is_some_and(|deadline| now >= deadline)
+            || self
+                .keepalive
+                .as_ref()
+                .is_some_and(|keepalive| self.handshake_complete() && keepalive.is_due(now))
+    }
+}
+
 impl<S: Read + Write> Websocket<S> {
     /// Allows to decode and iterate over incoming messages in a batch efficient way. It will perform
     /// single network read operation if there is no more data available for processing. It is possible
@@ -190,8 +508,18 @@ impl<S: Read + Write> Websocket<S> {
     /// ```
     #[inline]
     pub fn read_batch(&mut self) -> Result<Batch<'_, S>, Error> {
-        match self.state.read(&mut self.stream).no_block() {
-            Ok(()) => Ok(Batch { websocket: self }),
+        // messages from the previous batch can no longer be referenced
+        self.assembler.reset();
+        if let Some(deadline) = self.close_deadline {
+            self.ensure_close_not_timed_out(deadline)?;
+        }
+        match self.state.read(&mut self.stream) {
+            Ok(bytes_read) => {
+                if self.keepalive.is_some() && self.handshake_complete() {
+                    self.poll_keepalive(bytes_read)?;
+                }
+                Ok(Batch { websocket: self })
+            }
             Err(err) => {
                 self.closed = true;
                 Err(err)?
@@ -199,8 +527,39 @@ impl<S: Read + Write> Websocket<S> {
         }
     }
 
+    /// Similar to [`read_batch`](Websocket::read_batch) but yields complete messages instead of
+    /// frames. Fragmented messages are reassembled from `Continuation` frames into a buffer
+    /// acquired from the buffer pool, while messages that arrived in a single frame are returned
+    /// without copying. Control frames are handled internally and not exposed.
+    ///
+    /// This method should not be interleaved with frame level API while a fragmented message is
+    /// being received.
+    ///
+    /// ## Examples
+    ///
+    /// ```no_run
+    /// use std::io::{Read, Write};
+    /// use boomnet::ws::{Message, Websocket};
+    ///
+    /// fn process<S: Read + Write>(ws: &mut Websocket<S>) -> std::io::Result<()> {
+    ///     for message in ws.read_batch_messages()? {
+    ///         match message? {
+    ///             Message::Text(data) => println!("{}", String::from_utf8_lossy(data)),
+    ///             Message::Binary(data) => println!("received {} bytes", data.len()),
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    #[inline]
+    pub fn read_batch_messages(&mut self) -> Result<MessageBatch<'_, S>, Error> {
+        Ok(MessageBatch {
+            batch: self.read_batch()?,
+        })
+    }
+
     #[inline]
-    pub fn receive_next(&mut self) -> Option<Result<WebsocketFrame, Error>> {
+    pub fn receive_next(&mut self) -> Option<Result<WebsocketFrame<'_>, Error>> {
         match self.read_batch() {
             Ok(mut batch) => batch.receive_next(),
             Err(err) => Some(Err(err)),
@@ -227,17 +586,60 @@ impl<S: Read + Write> Websocket<S> {
         self.send(true, protocol::op::PING, body)
     }
 
-    #[inline]
-    pub fn send_close(&mut self) -> Result<(), Error> {
-        self.send(true, protocol::op::CONNECTION_CLOSE, None)?;
-        self.closed = true;
+    /// Initiate the closing handshake by sending the close frame with status `code` and `reason`.
+    /// The websocket will not accept any more outbound messages but will keep decoding inbound
+    /// frames until the peer echoes the close frame, at which point [`Error::ReceivedCloseFrame`]
+    /// is returned, or the close timeout elapses and [`Error::Closed`] is returned instead.
+    ///
+    /// The `reason` must not exceed 123 bytes and the `code` must be allowed to be sent (see
+    /// [`CloseCode::is_allowed`]). Calling this method again while closing has no effect.
+    ///
+    /// ## Examples
+    ///
+    /// ```no_run
+    /// use std::io::{Read, Write};
+    /// use boomnet::ws::{CloseCode, Error, Websocket};
+    ///
+    /// fn close<S: Read + Write>(ws: &mut Websocket<S>) -> Result<(), Error> {
+    ///     ws.send_close(CloseCode::Normal, "bye")?;
+    ///     while !ws.closed() {
+    ///         for frame in ws.read_batch()? {
+    ///             if let Err(Error::ReceivedCloseFrame(code, reason)) = frame {
+    ///                 println!("closed by peer: {code} {reason}");
+    ///             }
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn send_close(&mut self, code: CloseCode, reason: &str) -> Result<(), Error> {
+        if self.close_deadline.is_some() {
+            self.ensure_not_closed()?;
+            return Ok(());
+        }
+        let payload = close::encode_close_payload(code, reason)?;
+        self.send(true, protocol::op::CONNECTION_CLOSE, Some(&payload))?;
+        let close_timeout = self.close_timeout.as_nanos() as u64;
+        self.close_deadline = Some(self.clock.now().saturating_add(close_timeout));
         Ok(())
     }
 
     #[inline]
-    fn next(&mut self) -> Result<Option<WebsocketFrame>, Error> {
+    fn next(&mut self) -> Result<Option<WebsocketFrame<'static>>, Error> {
         self.ensure_not_closed()?;
-        match self.state.next(&mut self.stream) {
+        if let Some(deadline) = self.close_deadline {
+            self.ensure_close_not_timed_out(deadline)?;
+        }
+        match self
+            .state
+            .next(&mut self.stream, &mut self.encoder, self.close_deadline.is_some())
+        {
+            Ok(Some(WebsocketFrame::Pong(payload))) => {
+                if let Some(keepalive) = self.keepalive.as_mut() {
+                    keepalive.on_pong();
+                }
+                Ok(Some(WebsocketFrame::Pong(payload)))
+            }
             Ok(frame) => Ok(frame),
             Err(err) => {
                 self.closed = true;
@@ -246,10 +648,62 @@ impl<S: Read + Write> Websocket<S> {
         }
     }
 
+    #[inline]
+    fn next_message(&mut self) -> Result<Option<Message<'static>>, Error> {
+        loop {
+            let message = match self.next()? {
+                Some(WebsocketFrame::Text(fin, payload)) => {
+                    self.assembler.on_data_frame(protocol::op::TEXT_FRAME, fin, payload)
+                }
+                Some(WebsocketFrame::Binary(fin, payload)) => {
+                    self.assembler.on_data_frame(protocol::op::BINARY_FRAME, fin, payload)
+                }
+                Some(WebsocketFrame::Continuation(fin, payload)) => self.assembler.on_continuation_frame(fin, payload),
+                Some(_) => continue,
+                None => return Ok(None),
+            };
+            match message {
+                Ok(Some(message)) => return Ok(Some(message)),
+                Ok(None) => continue,
+                Err(err) => {
+                    self.closed = true;
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn poll_keepalive(&mut self, bytes_read: usize) -> Result<(), Error> {
+        // SAFETY: checked by the caller
+        let keepalive = unsafe { self.keepalive.as_mut().unwrap_unchecked() };
+        match keepalive.poll(self.clock.now(), bytes_read) {
+            Ok(true) if self.close_deadline.is_none() => self.send_ping(None),
+            Ok(_) => Ok(()),
+            Err(err) => {
+                self.closed = true;
+                Err(err)
+            }
+        }
+    }
+
+    #[cold]
+    fn ensure_close_not_timed_out(&mut self, deadline: u64) -> Result<(), Error> {
+        if self.clock.now() >= deadline {
+            self.closed = true;
+            return Err(Closed);
+        }
+        Ok(())
+    }
+
     #[inline]
     fn send(&mut self, fin: bool, op_code: u8, body: Option<&[u8]>) -> Result<(), Error> {
         self.ensure_not_closed()?;
-        match self.state.send(&mut self.stream, fin, op_code, body) {
+        if self.close_deadline.is_some() {
+            // no more frames can be sent once the close frame has been sent
+            return Err(Closed);
+        }
+        match self.state.send(&mut self.stream, &mut self.encoder, fin, op_code, body) {
             Ok(()) => Ok(()),
             Err(err) => {
                 self.closed = true;
@@ -267,6 +721,19 @@ impl<S: Read + Write> Websocket<S> {
     }
 }
 
+impl<S: AsRawFd> AsRawFd for Websocket<S> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+impl<S: IoUringSource> IoUringSource for Websocket<S> {
+    fn on_recv(&mut self, result: io::Result<Option<RecvBuffer>>) {
+        self.stream.on_recv(result)
+    }
+}
+
 #[cfg(feature = "mio")]
 impl<S: Source> Source for Websocket<S> {
     fn register(&mut self, registry: &Registry, token: Token, interests: Interest) -> io::Result<()> {
@@ -294,73 +761,134 @@ impl<S: Selectable> Selectable for Websocket<S> {
     fn make_readable(&mut self) -> io::Result<()> {
         self.stream.make_readable()
     }
+
+    fn has_pending_io(&self) -> bool {
+        self.state.has_pending() || self.stream.has_pending_io() || self.timers_due()
+    }
 }
 
 #[derive(Debug)]
 #[allow(clippy::large_enum_variant)]
 enum State {
-    Handshake(Handshaker, BufferPoolRef),
-    Connection(Decoder),
+    Handshake(Handshaker, BufferPoolRef, DecoderLimits),
+    Connection(Decoder, Option<HandshakeResponse>),
 }
 
 impl State {
-    pub fn handshake(server_name: &str, endpoint: &str, mut pool: BufferPoolRef) -> Self {
-        Self::Handshake(Handshaker::new(server_name, endpoint, &mut pool), pool)
+    pub fn handshake(server_name: &str, endpoint: &str, config: HandshakeConfig, mut pool: BufferPoolRef) -> Self {
+        Self::Handshake(Handshaker::new(server_name, endpoint, config, &mut pool), pool, DecoderLimits::default())
+    }
+
+    pub fn accept(config: HandshakeConfig, mut pool: BufferPoolRef) -> Self {
+        Self::Handshake(Handshaker::new_server(config, &mut pool), pool, DecoderLimits::default())
     }
 
     pub fn connection(mut pool: BufferPoolRef) -> Self {
-        Self::Connection(Decoder::new(&mut pool))
+        Self::Connection(Decoder::new(&mut pool), None)
     }
 }
 
 impl State {
     #[inline]
-    fn read<S: Read>(&mut self, stream: &mut S) -> io::Result<()> {
+    const fn limits_mut(&mut self) -> &mut DecoderLimits {
+        match self {
+            State::Handshake(_, _, limits) => limits,
+            State::Connection(decoder, _) => decoder.limits_mut(),
+        }
+    }
+
+    /// Returns `true` if the handshake can progress or there are buffered bytes that have not been
+    /// decoded yet.
+    #[inline]
+    fn has_pending(&self) -> bool {
+        match self {
+            State::Handshake(handshake, _, _) => handshake.has_pending(),
+            State::Connection(decoder, _) => decoder.has_pending(),
+        }
+    }
+
+    #[inline]
+    fn read<S: Read>(&mut self, stream: &mut S) -> io::Result<usize> {
         match self {
-            State::Handshake(handshake, _) => handshake.read(stream),
-            State::Connection(decoder) => decoder.read(stream),
+            State::Handshake(handshake, _, _) => handshake.read(stream).map(|_| 0),
+            State::Connection(decoder, _) => decoder.read(stream),
         }
     }
 
     #[inline]
-    fn next<S: Read + Write>(&mut self, stream: &mut S) -> Result<Option<WebsocketFrame>, Error> {
+    fn next<S: Read + Write>(
+        &mut self,
+        stream: &mut S,
+        encoder: &mut Encoder,
+        closing: bool,
+    ) -> Result<Option<WebsocketFrame<'static>>, Error> {
         match self {
-            State::Handshake(handshake, pool) => match handshake.perform_handshake(stream) {
+            State::Handshake(handshake, pool, limits) => match handshake.perform_handshake(stream) {
                 Ok(()) => {
-                    handshake.drain_pending_message_buffer(stream, encoder::send)?;
-                    *self = State::connection(pool.clone());
+                    #[cfg(feature = "ws-deflate")]
+                    let deflate_params = handshake.deflate_params();
+                    #[cfg(feature = "ws-deflate")]
+                    if let Some(params) = deflate_params {
+                        encoder.set_deflate(params);
+                    }
+                    handshake.drain_pending_message_buffer(stream, |stream, fin, op_code, body| {
+                        encoder.send(stream, fin, op_code, body)
+                    })?;
+                    let mut decoder = Decoder::new_with_role(handshake.role(), pool);
+                    *decoder.limits_mut() = *limits;
+                    *self = State::Connection(decoder, handshake.take_response());
+                    #[cfg(feature = "ws-deflate")]
+                    if let (State::Connection(decoder, _), Some(params)) = (&mut *self, deflate_params) {
+                        decoder.set_inflate(&params);
+                    }
                     Ok(None)
                 }
-                Err(err) if err.kind() == WouldBlock => Ok(None),
-                Err(err) => Err(err)?,
+                Err(Error::IO(err)) if err.kind() == WouldBlock => Ok(None),
+                Err(err) => Err(err),
             },
-            State::Connection(decoder) => match decoder.decode_next() {
+            State::Connection(decoder, _) => match decoder.decode_next() {
                 Ok(Some(WebsocketFrame::Ping(payload))) => {
-                    self.send(stream, true, protocol::op::PONG, Some(payload))?;
+                    // no more frames can be sent once the close frame has been sent
+                    if !closing {
+                        self.send(stream, encoder, true, protocol::op::PONG, Some(payload))?;
+                    }
                     Ok(None)
                 }
                 Ok(Some(WebsocketFrame::Close(payload))) => {
-                    let _ = self.send(stream, true, protocol::op::CONNECTION_CLOSE, Some(payload));
-                    let (status_code, body) = payload.split_at(std::mem::size_of::<u16>());
-                    let status_code = u16::from_be_bytes(status_code.try_into()?);
-                    let body = String::from_utf8_lossy(body).to_string();
-                    Err(ReceivedCloseFrame(status_code, body))
+                    let (code, reason) = close::decode_close_payload(payload)?;
+                    // echo the close frame unless we have initiated the closing handshake
+                    if !closing {
+                        let _ = self.send(stream, encoder, true, protocol::op::CONNECTION_CLOSE, Some(payload));
+                    }
+                    Err(ReceivedCloseFrame(code, reason))
                 }
                 Ok(frame) => Ok(frame),
+                Err(err @ Error::MessageTooBig(_, _)) => {
+                    let payload = u16::from(CloseCode::MessageTooBig).to_be_bytes();
+                    let _ = self.send(stream, encoder, true, protocol::op::CONNECTION_CLOSE, Some(&payload));
+                    Err(err)
+                }
                 Err(err) => Err(err)?,
             },
         }
     }
 
     #[inline]
-    fn send<S: Write>(&mut self, stream: &mut S, fin: bool, op_code: u8, body: Option<&[u8]>) -> Result<(), Error> {
+    fn send<S: Write>(
+        &mut self,
+        stream: &mut S,
+        encoder: &mut Encoder,
+        fin: bool,
+        op_code: u8,
+        body: Option<&[u8]>,
+    ) -> Result<(), Error> {
         match self {
-            State::Handshake(handshake, _) => {
+            State::Handshake(handshake, _, _) => {
                 handshake.buffer_message(fin, op_code, body);
                 Ok(())
             }
-            State::Connection(_) => {
-                encoder::send(stream, fin, op_code, body)?;
+            State::Connection(_, _) => {
+                encoder.send(stream, fin, op_code, body)?;
                 Ok(())
             }
         }
@@ -373,7 +901,7 @@ pub struct Batch<'a, S> {
 }
 
 impl<'a, S: Read + Write> IntoIterator for Batch<'a, S> {
-    type Item = Result<WebsocketFrame, Error>;
+    type Item = Result<WebsocketFrame<'a>, Error>;
     type IntoIter = BatchIter<'a, S>;
 
     fn into_iter(self) -> Self::IntoIter {
@@ -381,10 +909,10 @@ impl<'a, S: Read + Write> IntoIterator for Batch<'a, S> {
     }
 }
 
-impl<S: Read + Write> Batch<'_, S> {
+impl<'a, S: Read + Write> Batch<'a, S> {
     /// Try to decode next frame from the underlying `Batch`. If no more frames are available it
     /// will return `None`.
-    pub fn receive_next(&mut self) -> Option<Result<WebsocketFrame, Error>> {
+    pub fn receive_next(&mut self) -> Option<Result<WebsocketFrame<'a>, Error>> {
         self.websocket.next().transpose()
     }
 }
@@ -395,8 +923,44 @@ pub struct BatchIter<'a, S> {
     batch: Batch<'a, S>,
 }
 
-impl<S: Read + Write> Iterator for BatchIter<'_, S> {
-    type Item = Result<WebsocketFrame, Error>;
+impl<'a, S: Read + Write> Iterator for BatchIter<'a, S> {
+    type Item = Result<WebsocketFrame<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.batch.receive_next()
+    }
+}
+
+/// Represents a batch of 0 to N complete websocket messages since the last network read.
+pub struct MessageBatch<'a, S> {
+    batch: Batch<'a, S>,
+}
+
+impl<'a, S: Read + Write> IntoIterator for MessageBatch<'a, S> {
+    type Item = Result<Message<'a>, Error>;
+    type IntoIter = MessageBatchIter<'a, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        MessageBatchIter { batch: self }
+    }
+}
+
+impl<'a, S: Read + Write> MessageBatch<'a, S> {
+    /// Try to decode next complete message from the underlying `MessageBatch`. If no more
+    /// messages are available it will return `None`.
+    pub fn receive_next(&mut self) -> Option<Result<Message<'a>, Error>> {
+        self.batch.websocket.next_message().transpose()
+    }
+}
+
+/// Iterator that owns the current `MessageBatch`. When no more messages are available to be decoded
+/// in the buffer it will yield `None`.
+pub struct MessageBatchIter<'a, S> {
+    batch: MessageBatch<'a, S>,
+}
+
+impl<'a, S: Read + Write> Iterator for MessageBatchIter<'a, S> {
+    type Item = Result<Message<'a>, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.batch.receive_next()
@@ -407,6 +971,12 @@ pub trait IntoWebsocket {
     fn into_websocket(self, endpoint: &str) -> Websocket<Self>
     where
         Self: Sized;
+
+    /// Convert into [`Websocket`] and customise the handshake request using [`HandshakeConfig`].
+    fn into_websocket_with_config<F>(self, endpoint: &str, configure: F) -> Websocket<Self>
+    where
+        Self: Sized,
+        F: FnOnce(&mut HandshakeConfig);
 }
 
 impl<T> IntoWebsocket for T
@@ -419,6 +989,14 @@ where
     {
         Websocket::new(self, endpoint)
     }
+
+    fn into_websocket_with_config<F>(self, endpoint: &str, configure: F) -> Websocket<Self>
+    where
+        Self: Sized,
+        F: FnOnce(&mut HandshakeConfig),
+    {
+        Websocket::new_with_config(self, endpoint, configure)
+    }
 }
 
 #[cfg(any(feature = "rustls", feature = "openssl"))]
@@ -482,3 +1060,188 @@ where
         Ok(Websocket::new(tls_ready_stream, &endpoint))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{ManualTimeSource, MockStream};
+
+    #[test]
+    fn should_complete_closing_handshake_initiated_by_client() {
+        // text frame 'Hello' followed by close frame echo with 1000 status code
+        let inbound = [0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x88, 0x02, 0x03, 0xe8];
+        let mut ws = Websocket::new_with_handshake_complete(MockStream::new(inbound));
+
+        ws.send_close(CloseCode::Normal, "bye").unwrap();
+        assert!(ws.closing());
+        assert!(matches!(ws.send_text(true, Some(b"Hello")), Err(Closed)));
+
+        assert!(matches!(ws.receive_next(), Some(Ok(WebsocketFrame::Text(true, b"Hello")))));
+        match ws.receive_next() {
+            Some(Err(ReceivedCloseFrame(CloseCode::Normal, reason))) => assert!(reason.is_empty()),
+            _ => panic!("expected close frame"),
+        }
+        assert!(ws.closed());
+        assert!(!ws.closing());
+        // only the close frame initiated by the client has been sent
+        assert_eq!(&[0x88, 0x85, 0, 0, 0, 0, 0x03, 0xe8, b'b', b'y', b'e'], ws.stream.outbound.as_slice());
+    }
+
+    #[test]
+    fn should_release_reassembled_messages_on_next_batch() {
+        // text frame 'Hel' followed by continuation frame 'lo'
+        let inbound = [0x01, 0x03, 0x48, 0x65, 0x6c, 0x80, 0x02, 0x6c, 0x6f];
+        let mut ws = Websocket::new_with_handshake_complete(MockStream::new(inbound));
+
+        let messages: Vec<_> = ws
+            .read_batch_messages()
+            .unwrap()
+            .into_iter()
+            .map(|message| match message.unwrap() {
+                Message::Text(data) => data.to_vec(),
+                Message::Binary(_) => panic!("expected text message"),
+            })
+            .collect();
+        assert_eq!(vec![b"Hello".to_vec()], messages);
+        assert_eq!(1, ws.assembler.completed());
+
+        // frame level batch releases the buffer as well
+        assert_eq!(0, ws.read_batch().unwrap().into_iter().count());
+        assert_eq!(0, ws.assembler.completed());
+    }
+
+    #[test]
+    fn should_echo_empty_close_frame() {
+        let mut ws = Websocket::new_with_handshake_complete(MockStream::new([0x88, 0x00]));
+
+        match ws.receive_next() {
+            Some(Err(ReceivedCloseFrame(CloseCode::NoStatus, reason))) => assert!(reason.is_empty()),
+            _ => panic!("expected close frame"),
+        }
+        assert!(ws.closed());
+        assert_eq!(&[0x88, 0x80, 0, 0, 0, 0], ws.stream.outbound.as_slice());
+    }
+
+    #[test]
+    fn should_close_when_close_frame_is_not_echoed_in_time() {
+        let mut ws = Websocket::new_with_handshake_complete(MockStream::default()).with_close_timeout(Duration::ZERO);
+
+        ws.send_close(CloseCode::Away, "").unwrap();
+
+        assert!(matches!(ws.receive_next(), Some(Err(Closed))));
+        assert!(ws.closed());
+    }
+
+    #[test]
+    fn should_not_respond_to_ping_once_close_frame_sent() {
+        // ping frame followed by close frame echo with 1000 status code
+        let inbound = [0x89, 0x00, 0x88, 0x02, 0x03, 0xe8];
+        let mut ws = Websocket::new_with_handshake_complete(MockStream::new(inbound));
+
+        ws.send_close(CloseCode::Normal, "").unwrap();
+        // ping frame is consumed without yielding
+        assert!(ws.receive_next().is_none());
+        assert!(matches!(ws.receive_next(), Some(Err(ReceivedCloseFrame(CloseCode::Normal, _)))));
+        // only the close frame has been sent
+        assert_eq!(&[0x88, 0x82, 0, 0, 0, 0, 0x03, 0xe8], ws.stream.outbound.as_slice());
+    }
+
+    #[test]
+    fn should_use_time_source_for_close_timeout() {
+        let time_source = ManualTimeSource::default();
+        let mut ws = Websocket::new_with_handshake_complete(MockStream::default())
+            .with_close_timeout(Duration::from_secs(5))
+            .with_time_source(time_source.clone());
+
+        ws.send_close(CloseCode::Away, "").unwrap();
+        time_source.advance(Duration::from_secs(4));
+        assert!(ws.read_batch().is_ok());
+        assert!(ws.closing());
+
+        time_source.advance(Duration::from_secs(1));
+        assert!(matches!(ws.read_batch(), Err(Closed)));
+        assert!(ws.closed());
+    }
+
+    #[test]
+    fn should_report_timers_due_only_once_expired() {
+        let time_source = ManualTimeSource::default();
+        let policy = KeepalivePolicy::new().with_ping_interval(Duration::from_secs(10));
+        let mut ws = Websocket::new_with_handshake_complete(MockStream::default())
+            .with_keepalive(policy, time_source.clone())
+            .with_close_timeout(Duration::from_secs(5));
+
+        // timers start on the first read
+        assert!(ws.timers_due());
+        assert!(ws.receive_next().is_none());
+        assert!(!ws.timers_due());
+
+        time_source.advance(Duration::from_secs(10));
+        assert!(ws.timers_due());
+        assert!(ws.receive_next().is_none());
+        assert_eq!(&[0x89, 0x80, 0, 0, 0, 0], ws.stream.outbound.as_slice());
+
+        ws.send_close(CloseCode::Normal, "").unwrap();
+        time_source.advance(Duration::from_secs(4));
+        assert!(!ws.timers_due());
+        time_source.advance(Duration::from_secs(1));
+        assert!(ws.timers_due());
+    }
+
+    #[test]
+    fn should_send_ping_when_keepalive_enabled() {
+        let policy = KeepalivePolicy::new().with_ping_interval(Duration::ZERO);
+        let mut ws = Websocket::new_with_handshake_complete(MockStream::default())
+            .with_keepalive(policy, crate::service::time::SystemTimeClockSource);
+
+        assert!(ws.receive_next().is_none());
+        assert_eq!(&[0x89, 0x80, 0, 0, 0, 0], ws.stream.outbound.as_slice());
+    }
+
+    #[test]
+    fn should_fail_when_no_data_received_within_idle_timeout() {
+        let policy = KeepalivePolicy::new().with_idle_timeout(Duration::ZERO);
+        let mut ws = Websocket::new_with_handshake_complete(MockStream::default())
+            .with_keepalive(policy, crate::service::time::SystemTimeClockSource);
+
+        assert!(matches!(ws.receive_next(), Some(Err(Error::IdleTimeout(_)))));
+        assert!(ws.closed());
+    }
+
+    #[test]
+    fn should_exchange_frames_between_client_and_server() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut ws = Websocket::accept(stream);
+            loop {
+                let mut echo = None;
+                for frame in ws.read_batch().unwrap() {
+                    if let WebsocketFrame::Text(true, data) = frame.unwrap() {
+                        echo = Some(data.to_vec());
+                    }
+                }
+                if let Some(data) = echo {
+                    ws.send_text(true, Some(&data)).unwrap();
+                    return;
+                }
+            }
+        });
+
+        let stream = crate::stream::ConnectionInfo::new("127.0.0.1", port)
+            .into_tcp_stream()
+            .unwrap();
+        let mut ws = Websocket::new(stream, "/").with_masking(Masking::RandomKey);
+        ws.send_text(true, Some(b"hello server")).unwrap();
+        loop {
+            if let Some(WebsocketFrame::Text(true, data)) = ws.receive_next().transpose().unwrap() {
+                assert_eq!(b"hello server", data);
+                break;
+            }
+        }
+        assert!(ws.handshake_complete());
+        server.join().unwrap();
+    }
+}